@@ -3,9 +3,56 @@ drop table if exists flappy_dragon_score;
 create table flappy_dragon_score (
 
     id serial primary key,
+    game_id text not null default 'default',
     player_name text not null,
     player_score INT not null,
-    posted_time TIMESTAMP default now()
+    player_id text,
+    avatar text,
+    client_version text,
+    tier text,
+    difficulty text,
+    play_count INT,
+    posted_time TIMESTAMP default (now() AT TIME ZONE 'UTC')
 
 );
 
+create index on flappy_dragon_score (game_id, player_score desc);
+
+
+drop table if exists server_notice;
+
+create table server_notice (
+
+    id INT primary key check (id = 1),
+    message text,
+    level text not null default 'info'
+
+);
+
+drop table if exists flappy_dragon_score_archive;
+
+create table flappy_dragon_score_archive (
+
+    id serial primary key,
+    season_id INT not null,
+    player_name text not null,
+    player_score INT not null,
+    player_id text,
+    avatar text,
+    posted_time TIMESTAMP
+
+);
+
+create index on flappy_dragon_score_archive (season_id, player_score desc);
+
+drop table if exists users;
+
+-- Accounts that can log in, argon2_hash holds the PHC string of the password
+create table users (
+
+    id serial primary key,
+    username text not null unique,
+    argon2_hash text not null,
+    role text not null default 'default'
+
+);
@@ -0,0 +1,214 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::timestamp::rfc3339;
+
+// Latest events kept in memory for GET /api/audit, older ones are only in the log files
+pub const AUDIT_BUFFER_SIZE: usize = 1000;
+
+// Trail of destructive and admin actions. Every event gets the next sequence number,
+// so a gap in the log shows that entries were dropped or deleted
+#[derive(Debug)]
+pub struct AuditLog {
+    // JWT secret is rotated daily, so chain has its own key to stay verifiable afterwards
+    chain_key: Option<String>,
+    trail: Mutex<AuditTrail>,
+}
+
+// Sequence, chain and buffer change under one lock, so the buffer is always in sequence order.
+// Each event is signed together with the previous signature, so an edited or removed
+// entry breaks every signature after it
+#[derive(Debug, Default)]
+struct AuditTrail {
+    last_seq: u64,
+    previous_mac: String,
+    recent: VecDeque<AuditEvent>,
+}
+
+// Events never carry tokens or secrets, so they are shown to admins as recorded
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEvent {
+    pub seq: u64,
+    #[serde(with = "rfc3339")]
+    pub at: DateTime<Utc>,
+    // Subject of the token the action was made with
+    pub actor: String,
+    pub action: String,
+    pub details: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affected: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+impl AuditLog {
+    pub fn new(chain_key: Option<String>) -> Self {
+        AuditLog {
+            chain_key,
+            trail: Mutex::new(AuditTrail::default()),
+        }
+    }
+
+    pub fn record(
+        &self,
+        actor: &str,
+        action: &str,
+        details: &str,
+        affected: Option<u64>,
+    ) -> AuditEvent {
+        let mut trail = self.trail.lock().expect("Audit trail is poisoned!");
+        trail.last_seq += 1;
+        let mut event = AuditEvent {
+            seq: trail.last_seq,
+            at: Utc::now(),
+            actor: actor.to_owned(),
+            action: action.to_owned(),
+            details: details.to_owned(),
+            affected,
+            mac: None,
+        };
+        if let Some(key) = &self.chain_key {
+            let mac = chain_mac(key, &trail.previous_mac, &event);
+            trail.previous_mac.clone_from(&mac);
+            event.mac = Some(mac);
+        }
+
+        if trail.recent.len() >= AUDIT_BUFFER_SIZE {
+            trail.recent.pop_front();
+        }
+        trail.recent.push_back(event.clone());
+        drop(trail);
+
+        tracing::info!(
+            target: "audit",
+            seq = event.seq,
+            actor = event.actor,
+            action = event.action,
+            details = event.details,
+            affected = event.affected,
+            mac = event.mac.as_deref().unwrap_or("-"),
+            "Audit event"
+        );
+        event
+    }
+
+    // Newest events first
+    pub fn recent(&self, limit: usize) -> Vec<AuditEvent> {
+        let trail = self.trail.lock().expect("Audit trail is poisoned!");
+        trail.recent.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+// Signature covers every field of the event, mac itself aside
+pub fn chain_mac(key: &str, previous: &str, event: &AuditEvent) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    let affected = event.affected.map(|n| n.to_string()).unwrap_or_default();
+    for part in [
+        previous,
+        &event.seq.to_string(),
+        &event.at.timestamp_micros().to_string(),
+        &event.actor,
+        &event.action,
+        &event.details,
+        &affected,
+    ] {
+        // Length prefix keeps ("ab", "c") and ("a", "bc") apart
+        mac.update(&(part.len() as u64).to_be_bytes());
+        mac.update(part.as_bytes());
+    }
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_audit_sequence_without_gaps() {
+        let audit = Arc::new(AuditLog::new(None));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let audit = audit.clone();
+                std::thread::spawn(move || {
+                    (0..25)
+                        .map(|_| audit.record("admin", "flush", "test", None).seq)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut seqs: Vec<u64> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Audit thread failed"))
+            .collect();
+        seqs.sort();
+
+        assert_eq!(seqs, (1..=100).collect::<Vec<_>>(), "Sequence has gaps!");
+        assert_eq!(audit.record("admin", "flush", "test", None).mac, None);
+    }
+
+    #[tokio::test]
+    async fn test_audit_chain() {
+        let audit = AuditLog::new(Some("audit_key".to_string()));
+
+        let first = audit.record("admin", "flush", "all scores", None);
+        let second = audit.record("admin", "set_notice", "Downtime", None);
+        let unsigned = |event: &AuditEvent| AuditEvent {
+            mac: None,
+            ..event.clone()
+        };
+
+        assert_eq!((first.seq, second.seq), (1, 2));
+        assert_eq!(
+            first.mac.as_deref(),
+            Some(chain_mac("audit_key", "", &unsigned(&first)).as_str())
+        );
+        assert_eq!(
+            second.mac.as_deref(),
+            Some(
+                chain_mac(
+                    "audit_key",
+                    first.mac.as_deref().unwrap(),
+                    &unsigned(&second)
+                )
+                .as_str()
+            ),
+            "Event is not chained to the previous one!"
+        );
+        assert_ne!(
+            chain_mac(
+                "audit_key",
+                "",
+                &AuditEvent {
+                    actor: "someone else".into(),
+                    ..unsigned(&first)
+                }
+            ),
+            first.mac.unwrap(),
+            "Actor is not signed!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_buffer_is_bounded() {
+        let audit = AuditLog::new(None);
+        for _ in 0..AUDIT_BUFFER_SIZE + 5 {
+            audit.record("admin", "flush", "test", None);
+        }
+
+        let recent = audit.recent(usize::MAX);
+        assert_eq!(recent.len(), AUDIT_BUFFER_SIZE);
+        assert_eq!(
+            recent[0].seq,
+            (AUDIT_BUFFER_SIZE + 5) as u64,
+            "Newest event is not first!"
+        );
+        assert_eq!(audit.recent(2).len(), 2);
+    }
+}
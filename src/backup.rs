@@ -0,0 +1,155 @@
+use std::{collections::BTreeMap, fmt};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::{
+    db_access::{SCORE_MAX, SCORE_MIN},
+    game::{DEFAULT_GAME, GameId},
+    notice::Notice,
+    player_name::PlayerName,
+    timestamp::rfc3339_option,
+};
+
+// Bumped on every change of the document shape, restore accepts only the current one
+// and the legacy one
+pub const BACKUP_VERSION: u32 = 2;
+// Posted times are Postgres text, e.g. "2026-10-15 12:30:00.123", read as UTC
+pub const LEGACY_BACKUP_VERSION: u32 = 1;
+
+// Everything needed to bring a board back - live scores, archived seasons and the notice
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub scores: Vec<BackupScore>,
+    pub archive: Vec<BackupArchivedScore>,
+    pub notice: Notice,
+}
+
+// Rows are kept as stored, with the fields never shown on the board.
+// Rules are the same as for submitted scores, so an edited backup can't bring in what
+// a client could never post. Names are plain strings until validated, rows stored before
+// the name rules are rejected one by one instead of the whole backup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+pub struct BackupScore {
+    // Missing in backups taken before boards of several games
+    #[serde(default = "default_game_id")]
+    #[validate(custom(function = "valid_game_id"))]
+    pub game_id: String,
+    #[validate(custom(function = "valid_player_name"))]
+    pub player_name: String,
+    #[validate(range(min = SCORE_MIN, max = SCORE_MAX))]
+    pub player_score: i32,
+    #[validate(length(min = 1, max = 64))]
+    pub player_id: Option<String>,
+    #[validate(length(min = 1, max = 128))]
+    pub avatar: Option<String>,
+    #[validate(length(min = 1, max = 32))]
+    pub tier: Option<String>,
+    // Missing in backups taken before difficulties were stored
+    #[serde(default)]
+    #[validate(length(min = 1, max = 32))]
+    pub difficulty: Option<String>,
+    pub client_version: Option<String>,
+    pub play_count: Option<i32>,
+    #[serde(default, with = "rfc3339_option")]
+    pub posted_time: Option<DateTime<Utc>>,
+}
+
+fn default_game_id() -> String {
+    DEFAULT_GAME.to_string()
+}
+
+fn valid_game_id(game_id: &str) -> Result<(), ValidationError> {
+    match GameId::try_new(game_id) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("game_id")),
+    }
+}
+
+// Name must be already normalized, as every name stored under the name rules is
+fn valid_player_name(name: &str) -> Result<(), ValidationError> {
+    match PlayerName::try_new(name) {
+        Ok(valid) if valid.as_str() == name => Ok(()),
+        _ => Err(ValidationError::new("player_name")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+pub struct BackupArchivedScore {
+    pub season_id: i32,
+    #[validate(custom(function = "valid_player_name"))]
+    pub player_name: String,
+    #[validate(range(min = SCORE_MIN, max = SCORE_MAX))]
+    pub player_score: i32,
+    #[validate(length(min = 1, max = 64))]
+    pub player_id: Option<String>,
+    #[validate(length(min = 1, max = 128))]
+    pub avatar: Option<String>,
+    #[serde(default, with = "rfc3339_option")]
+    pub posted_time: Option<DateTime<Utc>>,
+}
+
+// Row left out of a restore. Row is the index in its list of the uploaded backup,
+// fields map each invalid field to the codes of the rules it broke
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedRow {
+    pub table: &'static str,
+    pub row: usize,
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+impl RejectedRow {
+    fn new(table: &'static str, row: usize, errors: &ValidationErrors) -> Self {
+        let fields = errors
+            .field_errors()
+            .into_iter()
+            .map(|(field, errors)| {
+                let codes = errors.iter().map(|e| e.code.to_string()).collect();
+                (field.to_string(), codes)
+            })
+            .collect();
+        RejectedRow { table, row, fields }
+    }
+}
+
+impl fmt::Display for RejectedRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(field, codes)| format!("{} ({})", field, codes.join(", ")))
+            .collect();
+        write!(f, "{}[{}]: {}", self.table, self.row, fields.join(", "))
+    }
+}
+
+impl Backup {
+    // Drops every row breaking the score rules and returns them, valid rows keep their order
+    pub fn take_invalid_rows(&mut self) -> Vec<RejectedRow> {
+        let mut rejected = Vec::new();
+        retain_valid(&mut self.scores, "scores", &mut rejected);
+        retain_valid(&mut self.archive, "archive", &mut rejected);
+        rejected
+    }
+}
+
+fn retain_valid<T: Validate>(
+    rows: &mut Vec<T>,
+    table: &'static str,
+    rejected: &mut Vec<RejectedRow>,
+) {
+    let mut row = 0;
+    rows.retain(|score| {
+        let index = row;
+        row += 1;
+        match score.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                rejected.push(RejectedRow::new(table, index, &e));
+                false
+            }
+        }
+    });
+}
@@ -0,0 +1,439 @@
+use dotenv::dotenv;
+use std::{collections::HashMap, env, str::FromStr};
+
+use crate::{
+    game::GameId, player_name::NameStep, sanitize::ControlChars, security::REQUIRED_CLAIMS,
+};
+
+pub const DEFAULT_LOG_DIR: &str = "logs";
+pub const DEFAULT_AUTH_SCHEME: &str = "Bearer";
+pub const DEFAULT_SCORE_FLOOR: i32 = 1;
+pub const DEFAULT_LEADERBOARD_SIZE: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    // Requests slower than this are logged with WARN level
+    pub slow_request_ms: u64,
+    // Requests in flight above this limit are rejected with 503
+    pub max_concurrency: usize,
+    // Responding to score submission with the stored record
+    pub echo_stored_score: bool,
+    pub cors_allowed_origins: Vec<String>,
+    // Response headers browsers are allowed to read, none beyond the safelisted ones by default
+    pub cors_expose_headers: Vec<String>,
+    // Rejecting requests with Origin header outside of CORS allowlist with 403
+    pub enforce_origin: bool,
+    pub auth_mode: AuthMode,
+    // Scheme expected in Authorization header, for gateways that rewrite "Bearer"
+    pub auth_scheme: String,
+    // Storing player names lowercased, so "Dragon" and "dragon" are the same player
+    pub fold_name_case: bool,
+    // Keeping only the best score of each player, players are told apart by id when it is sent
+    pub dedup_players: bool,
+    // Counting every submission of a deduplicated player, lower ones included
+    pub count_plays: bool,
+    // Claims every JWT must carry, token without any of them is rejected
+    pub jwt_required_claims: Vec<String>,
+    // Clock skew allowed for nbf claim, separate from the expiry leeway
+    pub jwt_nbf_leeway_secs: u64,
+    // Disable only behind a gateway that sets its own security headers - otherwise responses
+    // go out without CSP, HSTS and frame protection at all
+    pub security_headers_enabled: bool,
+    // Keeping notice banner in the database, so it survives restarts
+    pub persist_notice: bool,
+    // Rejecting plaintext requests with 400, even if TLS is terminated somewhere else
+    pub require_https: bool,
+    // Peers allowed to report original scheme with X-Forwarded-Proto
+    pub trusted_proxies: Vec<String>,
+    // Salt for anonymized exports, without it anonymized exports are refused
+    pub export_salt: Option<String>,
+    // Directory for log files, stdout is used when it is not writable
+    pub log_dir: String,
+    // Limits of kept log files, oldest files are deleted first. 0 disables a limit
+    pub log_retention_days: u64,
+    pub log_max_total_mb: u64,
+    // Key for signing audit events into a chain, events are not signed without it
+    pub audit_chain_key: Option<String>,
+    // Distinct player names a single token may submit within the window, 0 disables the limit
+    pub max_names_per_token: usize,
+    pub name_window_secs: u64,
+    // Lowest score accepted while the board has free places, 0 for games where 0 is a valid run
+    pub score_floor: i32,
+    // Rejecting bodies by declared Content-Length before reading them
+    pub early_length_check: bool,
+    // Node reports not ready for this long after start, so it can warm up before traffic
+    pub readiness_delay_secs: u64,
+    // Rejecting writes without User-Agent header with 400
+    pub require_user_agent: bool,
+    // Highest score accepted per player tier, e.g. "bronze:100,silver:500". Empty disables the check
+    pub tier_max_scores: HashMap<String, i32>,
+    // Serving top scores as a plain HTML page on /board, the server is an API first
+    pub board_page_enabled: bool,
+    // What private routes are rate limited by, with "ip+token" both limits apply
+    pub rate_limit_key: RateLimitKey,
+    // Steps applied to submitted names in order, e.g. "trim,strip-non-ascii,case-fold"
+    pub name_normalize: Vec<NameStep>,
+    // Safety cap of stored rows, submissions are refused with 503 once it is reached.
+    // Board is trimmed to its size after every insert, so this only catches runaway growth
+    pub hard_max_rows: i64,
+    // Identical submission from the same token within this window is a no-op, 0 disables it
+    pub duplicate_window_secs: u64,
+    // Deriving JWT secret from passphrase, so all nodes share it. Without it every node
+    // generates its own random secret and rotates it daily
+    pub jwt_passphrase: Option<String>,
+    // Bumped to rotate the derived secret
+    pub jwt_key_version: u32,
+    // Letting CDN cache the board for max age and serve it stale while revalidating.
+    // Keep max age to a few seconds, writes are not seen until it passes. 0 sends no-store
+    pub scores_max_age_secs: u64,
+    pub scores_stale_secs: u64,
+    // Sending opaque row ids with links to per-score resources in board responses
+    pub expose_score_ids: bool,
+    // Logging every request rejected by a governor with its key, for alerts on abusive clients
+    pub rate_limit_events: bool,
+    // Live board asked for an archived season answers 410 with the archive route instead of
+    // the current board, which would look like the season's scores were lost
+    pub archived_season_gone: bool,
+    // Shared key of the edge gateway for batch token validation, the route is off without it
+    pub gateway_api_key: Option<String>,
+    // Control characters in text fields of login and score bodies, "reject" or "strip"
+    pub control_chars: ControlChars,
+    // Budget of the private governors sent to clients in X-RateLimit-* headers
+    pub rate_limit_headers: bool,
+    // Player's score never goes down - lower submissions are ignored and not counted as plays
+    pub monotonic_scores: bool,
+    // Current UTC time of the node in health output, for debugging token expiry across nodes
+    pub health_server_time: bool,
+    // Deepest offset a page of archived scores may start at, deeper scans are refused
+    pub max_page_offset: i64,
+    // Difficulties a score may be tagged with, scores of all of them share one board
+    pub difficulties: Vec<String>,
+    // Connection pool counts in headers of every response, for debugging contention in staging
+    pub debug_diagnostics: bool,
+    // Concurrent get-scores requests for the same board version share one DB query
+    pub coalesce_reads: bool,
+    // Places on the board, cutoff and trimming after insert both follow it
+    pub leaderboard_size: usize,
+    // Places on boards of single games, e.g. "endless:100,time_attack:10".
+    // Games without an entry have leaderboard_size places
+    pub leaderboard_sizes: HashMap<String, usize>,
+    // Scores are accepted only with a session token from /api/game/start, see game_session
+    pub game_sessions: bool,
+    pub game_session_ttl_secs: u64,
+    // Fastest plausible scoring, a score higher than this times session age is refused
+    pub max_score_per_sec: u32,
+    // Restore leaves out rows breaking the score rules and reports them,
+    // without it one such row refuses the whole backup
+    pub restore_skip_invalid: bool,
+    // JSON Schema of the set-score body on /api/schema/score, for validating on client side
+    pub score_schema_enabled: bool,
+    // Scores are accepted only with X-Score-Signature made with this secret, see
+    // verify_score_signature. Unset disables the check
+    pub score_signing_secret: Option<String>,
+    // Lifetime of access tokens, refresh tokens keep their 7 days
+    pub jwt_ttl_secs: u64,
+    // PEM files of the RS256 key pair, set both to sign with it instead of the HS256 secret
+    pub jwt_private_key_path: Option<String>,
+    pub jwt_public_key_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AuthMode {
+    // Token is sent only in Authorization header
+    #[default]
+    Header,
+    // Token is also set as HttpOnly cookie on login and accepted from it
+    Cookie,
+}
+
+impl FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "header" => Ok(AuthMode::Header),
+            "cookie" => Ok(AuthMode::Cookie),
+            other => Err(format!("Unknown auth mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RateLimitKey {
+    // Client IP, taken from X-Forwarded-For only behind a trusted proxy
+    Ip,
+    #[default]
+    Token,
+    // Separate limits by IP and by token, so varying only one of them doesn't help
+    IpAndToken,
+}
+
+impl RateLimitKey {
+    pub fn by_ip(&self) -> bool {
+        matches!(self, RateLimitKey::Ip | RateLimitKey::IpAndToken)
+    }
+
+    pub fn by_token(&self) -> bool {
+        matches!(self, RateLimitKey::Token | RateLimitKey::IpAndToken)
+    }
+}
+
+impl FromStr for RateLimitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ip" => Ok(RateLimitKey::Ip),
+            "token" => Ok(RateLimitKey::Token),
+            "ip+token" => Ok(RateLimitKey::IpAndToken),
+            other => Err(format!("Unknown rate limit key: {}", other)),
+        }
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            slow_request_ms: 1000,
+            max_concurrency: 1024,
+            echo_stored_score: false,
+            cors_allowed_origins: vec![
+                "http://0.0.0.0:3000".to_string(),
+                "http://0.0.0.0:8080".to_string(),
+            ],
+            cors_expose_headers: vec![],
+            enforce_origin: false,
+            auth_mode: AuthMode::default(),
+            auth_scheme: DEFAULT_AUTH_SCHEME.to_string(),
+            fold_name_case: false,
+            dedup_players: false,
+            count_plays: false,
+            jwt_required_claims: REQUIRED_CLAIMS.map(String::from).to_vec(),
+            jwt_nbf_leeway_secs: 60,
+            security_headers_enabled: true,
+            persist_notice: false,
+            require_https: false,
+            trusted_proxies: vec![],
+            export_salt: None,
+            log_dir: DEFAULT_LOG_DIR.to_string(),
+            log_retention_days: 0,
+            log_max_total_mb: 0,
+            audit_chain_key: None,
+            max_names_per_token: 0,
+            name_window_secs: 3600,
+            score_floor: DEFAULT_SCORE_FLOOR,
+            early_length_check: true,
+            readiness_delay_secs: 0,
+            require_user_agent: false,
+            tier_max_scores: HashMap::new(),
+            board_page_enabled: false,
+            rate_limit_key: RateLimitKey::default(),
+            name_normalize: vec![NameStep::Trim],
+            hard_max_rows: 100_000,
+            duplicate_window_secs: 0,
+            jwt_passphrase: None,
+            jwt_key_version: 1,
+            scores_max_age_secs: 0,
+            scores_stale_secs: 0,
+            expose_score_ids: false,
+            rate_limit_events: false,
+            archived_season_gone: false,
+            gateway_api_key: None,
+            control_chars: ControlChars::default(),
+            rate_limit_headers: false,
+            monotonic_scores: false,
+            health_server_time: false,
+            max_page_offset: 10_000,
+            difficulties: vec!["easy".to_string(), "normal".to_string(), "hard".to_string()],
+            debug_diagnostics: false,
+            coalesce_reads: false,
+            leaderboard_size: DEFAULT_LEADERBOARD_SIZE,
+            leaderboard_sizes: HashMap::new(),
+            game_sessions: false,
+            game_session_ttl_secs: 3600,
+            max_score_per_sec: 2,
+            restore_skip_invalid: false,
+            score_schema_enabled: false,
+            score_signing_secret: None,
+            jwt_ttl_secs: 3600,
+            jwt_private_key_path: None,
+            jwt_public_key_path: None,
+        }
+    }
+}
+
+impl AppConfig {
+    // Read alone before the rest, so tracing is up when parse warnings of other values come
+    pub fn log_dir_from_env() -> String {
+        dotenv().ok();
+        env_or("LOG_DIR", DEFAULT_LOG_DIR.to_string())
+    }
+
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let default = AppConfig::default();
+
+        AppConfig {
+            slow_request_ms: env_or("SLOW_REQUEST_MS", default.slow_request_ms),
+            max_concurrency: env_or("MAX_CONCURRENCY", default.max_concurrency),
+            echo_stored_score: env_or("ECHO_STORED_SCORE", default.echo_stored_score),
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS", default.cors_allowed_origins),
+            cors_expose_headers: env_list("CORS_EXPOSE_HEADERS", default.cors_expose_headers),
+            enforce_origin: env_or("ENFORCE_ORIGIN", default.enforce_origin),
+            auth_mode: env_or("AUTH_MODE", default.auth_mode),
+            auth_scheme: env_or("AUTH_SCHEME", default.auth_scheme),
+            fold_name_case: env_or("FOLD_NAME_CASE", default.fold_name_case),
+            dedup_players: env_or("DEDUP_PLAYERS", default.dedup_players),
+            count_plays: env_or("COUNT_PLAYS", default.count_plays),
+            jwt_required_claims: env_list("JWT_REQUIRED_CLAIMS", default.jwt_required_claims),
+            jwt_nbf_leeway_secs: env_or("JWT_NBF_LEEWAY_SECS", default.jwt_nbf_leeway_secs),
+            security_headers_enabled: env_or(
+                "SECURITY_HEADERS_ENABLED",
+                default.security_headers_enabled,
+            ),
+            persist_notice: env_or("PERSIST_NOTICE", default.persist_notice),
+            require_https: env_or("REQUIRE_HTTPS", default.require_https),
+            trusted_proxies: env_list("TRUSTED_PROXIES", default.trusted_proxies),
+            export_salt: env::var("EXPORT_SALT")
+                .ok()
+                .filter(|salt| !salt.is_empty())
+                .or(default.export_salt),
+            log_dir: AppConfig::log_dir_from_env(),
+            log_retention_days: env_or("LOG_RETENTION_DAYS", default.log_retention_days),
+            log_max_total_mb: env_or("LOG_MAX_TOTAL_MB", default.log_max_total_mb),
+            audit_chain_key: env::var("AUDIT_CHAIN_KEY")
+                .ok()
+                .filter(|key| !key.is_empty())
+                .or(default.audit_chain_key),
+            max_names_per_token: env_or("MAX_NAMES_PER_TOKEN", default.max_names_per_token),
+            name_window_secs: env_or("NAME_WINDOW_SECS", default.name_window_secs),
+            score_floor: env_or("SCORE_FLOOR", default.score_floor),
+            early_length_check: env_or("EARLY_LENGTH_CHECK", default.early_length_check),
+            readiness_delay_secs: env_or("READINESS_DELAY_SECS", default.readiness_delay_secs),
+            require_user_agent: env_or("REQUIRE_USER_AGENT", default.require_user_agent),
+            tier_max_scores: env_map("TIER_MAX_SCORES", default.tier_max_scores),
+            board_page_enabled: env_or("BOARD_PAGE_ENABLED", default.board_page_enabled),
+            rate_limit_key: env_or("RATE_LIMIT_KEY", default.rate_limit_key),
+            name_normalize: env_parsed_list("NAME_NORMALIZE", default.name_normalize),
+            hard_max_rows: env_or("HARD_MAX_ROWS", default.hard_max_rows),
+            duplicate_window_secs: env_or("DUPLICATE_WINDOW_SECS", default.duplicate_window_secs),
+            jwt_passphrase: env::var("JWT_PASSPHRASE")
+                .ok()
+                .filter(|passphrase| !passphrase.is_empty())
+                .or(default.jwt_passphrase),
+            jwt_key_version: env_or("JWT_KEY_VERSION", default.jwt_key_version),
+            scores_max_age_secs: env_or("SCORES_MAX_AGE_SECS", default.scores_max_age_secs),
+            scores_stale_secs: env_or("SCORES_STALE_SECS", default.scores_stale_secs),
+            expose_score_ids: env_or("EXPOSE_SCORE_IDS", default.expose_score_ids),
+            rate_limit_events: env_or("RATE_LIMIT_EVENTS", default.rate_limit_events),
+            archived_season_gone: env_or("ARCHIVED_SEASON_GONE", default.archived_season_gone),
+            gateway_api_key: env::var("GATEWAY_API_KEY")
+                .ok()
+                .filter(|key| !key.is_empty())
+                .or(default.gateway_api_key),
+            control_chars: env_or("CONTROL_CHARS", default.control_chars),
+            rate_limit_headers: env_or("RATE_LIMIT_HEADERS", default.rate_limit_headers),
+            monotonic_scores: env_or("MONOTONIC_SCORES", default.monotonic_scores),
+            health_server_time: env_or("HEALTH_SERVER_TIME", default.health_server_time),
+            max_page_offset: env_or("MAX_PAGE_OFFSET", default.max_page_offset),
+            difficulties: env_list("DIFFICULTIES", default.difficulties),
+            debug_diagnostics: env_or("DEBUG_DIAGNOSTICS", default.debug_diagnostics),
+            coalesce_reads: env_or("COALESCE_READS", default.coalesce_reads),
+            // Board without places would refuse every score
+            leaderboard_size: env_or("LEADERBOARD_SIZE", default.leaderboard_size).max(1),
+            leaderboard_sizes: env_map("LEADERBOARD_SIZES", default.leaderboard_sizes)
+                .into_iter()
+                .map(|(game, size)| (game, size.max(1)))
+                .collect(),
+            game_sessions: env_or("GAME_SESSIONS", default.game_sessions),
+            game_session_ttl_secs: env_or("GAME_SESSION_TTL_SECS", default.game_session_ttl_secs),
+            max_score_per_sec: env_or("MAX_SCORE_PER_SEC", default.max_score_per_sec),
+            restore_skip_invalid: env_or("RESTORE_SKIP_INVALID", default.restore_skip_invalid),
+            score_schema_enabled: env_or("SCORE_SCHEMA_ENABLED", default.score_schema_enabled),
+            score_signing_secret: env::var("SCORE_SIGNING_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty())
+                .or(default.score_signing_secret),
+            // Token expiring as it is issued would lock everyone out
+            jwt_ttl_secs: env_or("JWT_TTL_SECONDS", default.jwt_ttl_secs).max(1),
+            jwt_private_key_path: env::var("JWT_PRIVATE_KEY_PATH")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .or(default.jwt_private_key_path),
+            jwt_public_key_path: env::var("JWT_PUBLIC_KEY_PATH")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .or(default.jwt_public_key_path),
+        }
+    }
+
+    pub fn board_size(&self, game: &GameId) -> usize {
+        self.leaderboard_sizes
+            .get(game.as_str())
+            .copied()
+            .unwrap_or(self.leaderboard_size)
+    }
+}
+
+// Reading variable from env, falling back to default if it is missing or can't be parsed
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            tracing::warn!("Can't parse {} from env! Using default value", key);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+// Reading comma separated list from env, falling back to default if it is missing
+fn env_list(key: &str, default: Vec<String>) -> Vec<String> {
+    match env::var(key) {
+        Ok(value) => value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+        Err(_) => default,
+    }
+}
+
+// Reading comma separated list of parsed values, items that can't be parsed are skipped
+fn env_parsed_list<T: FromStr>(key: &str, default: Vec<T>) -> Vec<T> {
+    match env::var(key) {
+        Ok(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .filter_map(|item| {
+                item.parse()
+                    .inspect_err(|_| {
+                        tracing::warn!("Can't parse {} entry {}! Skipping it", key, item)
+                    })
+                    .ok()
+            })
+            .collect(),
+        Err(_) => default,
+    }
+}
+
+// Reading comma separated key:value pairs from env, pairs that can't be parsed are skipped
+fn env_map<T: FromStr>(key: &str, default: HashMap<String, T>) -> HashMap<String, T> {
+    match env::var(key) {
+        Ok(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .filter_map(|pair| {
+                let parsed = pair
+                    .split_once(':')
+                    .and_then(|(k, v)| Some((k.trim().to_string(), v.trim().parse().ok()?)));
+                if parsed.is_none() {
+                    tracing::warn!("Can't parse {} entry {}! Skipping it", key, pair);
+                }
+                parsed
+            })
+            .collect(),
+        Err(_) => default,
+    }
+}
@@ -1,18 +1,42 @@
+use crate::AppConfig;
 use crate::Arc;
 use crate::JwtConfig;
+use crate::error::ServerError;
 use crate::generate_secret;
-use axum::http::Method;
-use std::time::Duration;
+use crate::routes::{
+    ClientIpGovernorConfig, IpGovernorConfig, JwtGovernorConfig, PrivateGovernorConfig,
+};
+use crate::security::{ClientIpKeyExtractor, JwtKeyExtractor, client_ip, extract_token, token_key};
+use crate::security::{MIN_PASSPHRASE_LEN, RsaKeys, check_secret, derive_secret};
+use crate::state::AppState;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, MatchedPath, Request},
+    http::{HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::PgPool;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+use tower::BoxError;
+use tower_governor::{GovernorError, governor::GovernorConfigBuilder};
 
 #[cfg(unix)]
 use tokio::signal::unix::{SignalKind, signal};
 
-use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::header::{
+    ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, AUTHORIZATION, CONTENT_LENGTH,
+    CONTENT_TYPE, ORIGIN, RETRY_AFTER,
+};
 use tokio::sync::RwLock;
 use tower_http::cors::CorsLayer;
 
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::{FmtSubscriber, fmt::writer::BoxMakeWriter};
 
 pub async fn wait_for_shutdown_signal() {
     #[cfg(unix)]
@@ -44,15 +68,170 @@ pub async fn wait_for_shutdown_signal() {
     }
 }
 
-pub fn set_up_jwt() -> Arc<RwLock<JwtConfig>> {
-    Arc::new(RwLock::new(JwtConfig::new(generate_secret())))
+// Set on responses of governors, so their rejections are told apart from other 429s
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimited;
+
+// Error handler of all governors - default response, marked when the limit is hit.
+// Governor floors the wait to whole seconds, so Retry-After is rounded up - a client waiting
+// that long always finds a request back in its budget, and is never told to retry at once
+pub fn mark_rate_limited(mut error: GovernorError) -> Response {
+    let retry_after = match &error {
+        GovernorError::TooManyRequests { wait_time, .. } => Some(wait_time + 1),
+        _ => None,
+    };
+    let mut response = error.as_response();
+    if let Some(retry_after) = retry_after {
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        response.extensions_mut().insert(RateLimited);
+    }
+    response
 }
 
-pub fn set_up_tracing() {
-    std::fs::create_dir_all("logs").expect(
-        "Can't create folder for logs! Logging to file is not working! Server is shutdown!",
-    );
-    let writer = RollingFileAppender::new(Rotation::DAILY, "logs", "serv.log");
+pub fn set_up_health_governor() -> Arc<IpGovernorConfig> {
+    Arc::new(
+        GovernorConfigBuilder::default()
+            .per_millisecond(100)
+            .burst_size(20)
+            .error_handler(mark_rate_limited)
+            .finish()
+            .expect("Unable to set up Governor! Server is shutdown!"),
+    )
+}
+
+pub fn set_up_public_governor() -> Arc<IpGovernorConfig> {
+    Arc::new(
+        GovernorConfigBuilder::default()
+            .per_second(60)
+            .burst_size(3)
+            .error_handler(mark_rate_limited)
+            .finish()
+            .expect("Unable to set up Governor! Server is shutdown!"),
+    )
+}
+
+// One request of the private budget comes back every PRIVATE_PERIOD_SECS
+pub const PRIVATE_PERIOD_SECS: u64 = 60;
+pub const PRIVATE_BURST: u32 = 5;
+
+// Private governors always keep their state in headers, rate_limit_headers decides if clients see it
+pub fn set_up_private_governor(config: &AppConfig) -> Option<Arc<PrivateGovernorConfig>> {
+    config.rate_limit_key.by_token().then(|| {
+        Arc::new(
+            GovernorConfigBuilder::default()
+                .key_extractor(JwtKeyExtractor {
+                    auth_mode: config.auth_mode,
+                    auth_scheme: config.auth_scheme.clone(),
+                })
+                .per_second(PRIVATE_PERIOD_SECS)
+                .burst_size(PRIVATE_BURST)
+                .error_handler(mark_rate_limited)
+                .use_headers()
+                .finish()
+                .expect("Unable to set up Governor! Server is shutdown!"),
+        )
+    })
+}
+
+// Same budget as the token one, so "ip+token" is as lenient as either of them alone
+pub fn set_up_private_ip_governor(config: &AppConfig) -> Option<Arc<ClientIpGovernorConfig>> {
+    config.rate_limit_key.by_ip().then(|| {
+        Arc::new(
+            GovernorConfigBuilder::default()
+                .key_extractor(ClientIpKeyExtractor {
+                    trusted_proxies: Arc::new(set_up_trusted_proxies(config)),
+                })
+                .per_second(PRIVATE_PERIOD_SECS)
+                .burst_size(PRIVATE_BURST)
+                .error_handler(mark_rate_limited)
+                .use_headers()
+                .finish()
+                .expect("Unable to set up Governor! Server is shutdown!"),
+        )
+    })
+}
+
+// Backup and restore move the whole board, so admins get a single call per minute
+pub fn set_up_admin_governor(config: &AppConfig) -> Arc<JwtGovernorConfig> {
+    Arc::new(
+        GovernorConfigBuilder::default()
+            .key_extractor(JwtKeyExtractor {
+                auth_mode: config.auth_mode,
+                auth_scheme: config.auth_scheme.clone(),
+            })
+            .per_second(60)
+            .burst_size(1)
+            .error_handler(mark_rate_limited)
+            .finish()
+            .expect("Unable to set up Governor! Server is shutdown!"),
+    )
+}
+
+// Key material is checked before serving, so a bad secret stops startup instead of failing
+// the first login. Generated secrets are always long enough
+pub fn check_jwt_secret(config: &AppConfig) -> Result<(), ServerError> {
+    load_rsa_keys(config)?;
+    let Some(passphrase) = &config.jwt_passphrase else {
+        return Ok(());
+    };
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(ServerError::Configuration(format!(
+            "JWT_PASSPHRASE must be at least {} characters",
+            MIN_PASSPHRASE_LEN
+        )));
+    }
+    check_secret(&derive_secret(passphrase, config.jwt_key_version))
+}
+
+// Keys are read once more here, after check_jwt_secret has refused the ones that can't be used
+pub fn set_up_jwt(config: &AppConfig) -> Arc<RwLock<JwtConfig>> {
+    let jwt_config = JwtConfig::new(match &config.jwt_passphrase {
+        Some(passphrase) => derive_secret(passphrase, config.jwt_key_version),
+        None => generate_secret(),
+    })
+    .with_required_claims(&config.jwt_required_claims)
+    .with_nbf_leeway(config.jwt_nbf_leeway_secs)
+    .with_access_token_ttl(chrono::Duration::seconds(config.jwt_ttl_secs as i64));
+
+    let rsa_keys = load_rsa_keys(config).expect("Unable to load RSA keys! Server is shutdown!");
+    Arc::new(RwLock::new(match rsa_keys {
+        Some(rsa_keys) => jwt_config.with_rsa_keys(rsa_keys),
+        None => jwt_config,
+    }))
+}
+
+// RS256 is used only with both keys, one of them alone is a mistake rather than a choice
+pub fn load_rsa_keys(config: &AppConfig) -> Result<Option<RsaKeys>, ServerError> {
+    let (private_path, public_path) =
+        match (&config.jwt_private_key_path, &config.jwt_public_key_path) {
+            (Some(private_path), Some(public_path)) => (private_path, public_path),
+            (None, None) => return Ok(None),
+            _ => {
+                return Err(ServerError::Configuration(
+                    "JWT_PRIVATE_KEY_PATH and JWT_PUBLIC_KEY_PATH must be set together".into(),
+                ));
+            }
+        };
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|e| {
+            ServerError::Configuration(format!("Can't read RSA key from {}: {}", path, e))
+        })
+    };
+    RsaKeys::from_pem(&read(private_path)?, &read(public_path)?).map(Some)
+}
+
+// Logs go to log_dir, falling back to stdout when it is not writable (e.g. read-only container)
+pub fn set_up_tracing(log_dir: &str) {
+    let writer = match log_writer(log_dir) {
+        Ok(appender) => BoxMakeWriter::new(appender),
+        Err(e) => {
+            // Tracing is not up yet, so stderr is the only way to report it
+            eprintln!("WARNING: {} Logging to stdout instead!", e);
+            BoxMakeWriter::new(std::io::stdout)
+        }
+    };
 
     let subscriber = FmtSubscriber::builder()
         .with_max_level(tracing::Level::INFO)
@@ -64,14 +243,688 @@ pub fn set_up_tracing() {
         .expect("Loggin not ready! Server is shutdown!");
 }
 
-pub fn set_up_cors() -> CorsLayer {
+fn log_writer(log_dir: &str) -> Result<RollingFileAppender, String> {
+    std::fs::create_dir_all(log_dir)
+        .map_err(|e| format!("Can't create log directory {}: {}.", log_dir, e))?;
+
+    // Building appender opens the log file, so unwritable directory fails here
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .build(log_dir)
+        .map_err(|e| format!("Can't write logs to {}: {}.", log_dir, e))
+}
+
+// Prefix of log files, cleanup never touches other files in the log directory
+const LOG_PREFIX: &str = "serv.log";
+
+// Deletes log files older than max_age, then the oldest ones while total size is over the cap,
+// so whichever limit is hit first applies. Newest file is being written to and is always kept.
+// Returns number of deleted files
+pub fn clean_up_logs(
+    log_dir: &str,
+    max_age: Option<Duration>,
+    max_total_bytes: Option<u64>,
+) -> usize {
+    let entries = match std::fs::read_dir(log_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Can't read log directory {}: {}", log_dir, e);
+            return 0;
+        }
+    };
+
+    let mut files: Vec<(std::path::PathBuf, std::time::SystemTime, u64)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(LOG_PREFIX))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+        })
+        .collect();
+    // Newest first, so it is never a candidate for deletion
+    files.sort_by_key(|(_, modified, _)| std::cmp::Reverse(*modified));
+
+    let mut total: u64 = files.iter().map(|(_, _, len)| len).sum();
+    let mut deleted = 0;
+    for (path, modified, len) in files.iter().skip(1).rev() {
+        let too_old =
+            max_age.is_some_and(|max_age| modified.elapsed().is_ok_and(|age| age > max_age));
+        let over_cap = max_total_bytes.is_some_and(|cap| total > cap);
+        if !too_old && !over_cap {
+            continue;
+        }
+
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                total -= len;
+                deleted += 1;
+            }
+            Err(e) => tracing::warn!("Can't delete log file {}: {}", path.display(), e),
+        }
+    }
+    deleted
+}
+
+pub fn set_up_cors(config: &AppConfig) -> CorsLayer {
+    let origins: Vec<HeaderValue> = config
+        .cors_allowed_origins
+        .iter()
+        .filter_map(|origin| {
+            origin
+                .parse()
+                .inspect_err(|_| tracing::warn!("Invalid CORS origin is skipped: {}", origin))
+                .ok()
+        })
+        .collect();
+
+    let expose_headers: Vec<HeaderName> = config
+        .cors_expose_headers
+        .iter()
+        .filter_map(|name| {
+            name.parse()
+                .inspect_err(|_| tracing::warn!("Invalid CORS exposed header is skipped: {}", name))
+                .ok()
+        })
+        .collect();
+
+    // Allow-Origin header depends on the request origin, so every response varies by it -
+    // also the ones for foreign origins, otherwise a CDN may hand them to an allowed one
     CorsLayer::new()
-        .allow_origin([
-            "http://0.0.0.0:3000".parse().unwrap(),
-            "http://0.0.0.0:8080".parse().unwrap(),
+        .allow_origin(origins)
+        .vary([
+            ORIGIN,
+            ACCESS_CONTROL_REQUEST_METHOD,
+            ACCESS_CONTROL_REQUEST_HEADERS,
         ])
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_headers([AUTHORIZATION, CONTENT_TYPE])
+        .expose_headers(expose_headers)
         .allow_credentials(false)
         .max_age(Duration::from_secs(86400))
 }
+
+pub fn set_up_trusted_proxies(config: &AppConfig) -> Vec<IpAddr> {
+    config
+        .trusted_proxies
+        .iter()
+        .filter_map(|proxy| {
+            proxy
+                .parse()
+                .inspect_err(|_| tracing::warn!("Invalid trusted proxy is skipped: {}", proxy))
+                .ok()
+        })
+        .collect()
+}
+
+pub async fn log_slow_requests(req: Request<Body>, next: Next, threshold: Duration) -> Response {
+    // Matched route is used when possible to group slow requests by endpoint
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let method = req.method().clone();
+
+    let start = Instant::now();
+    let response = next.run(req).await;
+    let elapsed = start.elapsed();
+
+    if elapsed > threshold {
+        tracing::warn!(
+            %method,
+            route,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Slow request!"
+        );
+    }
+
+    response
+}
+
+// Pool state as the request came in, so a slow response can be matched with a busy pool.
+// Only counts of connections are sent, never anything about the database itself
+pub async fn pool_diagnostics(req: Request<Body>, next: Next, pool: PgPool) -> Response {
+    let size = pool.size();
+    let idle = pool.num_idle();
+    let max = pool.options().get_max_connections();
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert("x-db-pool-size", HeaderValue::from(size));
+    headers.insert("x-db-pool-idle", HeaderValue::from(idle));
+    headers.insert("x-db-pool-max", HeaderValue::from(max));
+    response
+}
+
+// Rejections are logged with both keys a governor may use. Token goes as the same hash
+// the limiter keys it by - JWTs share their first bytes and must not be logged whole
+pub async fn log_rate_limited(req: Request<Body>, next: Next, state: AppState) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip());
+    let headers = req.headers().clone();
+
+    let response = next.run(req).await;
+    if response.extensions().get::<RateLimited>().is_none() {
+        return response;
+    }
+
+    let rejected = state.rate_limited.fetch_add(1, Ordering::Relaxed) + 1;
+    let ip = peer
+        .map(|peer| client_ip(&headers, peer, &set_up_trusted_proxies(&state.config)).to_string());
+    let token = extract_token(&headers, state.config.auth_mode, &state.config.auth_scheme)
+        .ok()
+        .map(|token| format!("{:016x}", token_key(token)));
+    tracing::warn!(
+        route,
+        ip = ip.as_deref().unwrap_or("-"),
+        token_key = token.as_deref().unwrap_or("-"),
+        rejected,
+        "Request is rate limited!"
+    );
+
+    response
+}
+
+const RATE_LIMIT_LIMIT: &str = "x-ratelimit-limit";
+const RATE_LIMIT_REMAINING: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_RESET: &str = "x-ratelimit-reset";
+const RATE_LIMIT_WHITELISTED: &str = "x-ratelimit-whitelisted";
+
+// Governors leave limit and remaining on private responses - they are dropped unless exposed,
+// otherwise reset is added as seconds until the whole burst is back.
+// With "ip+token" the tighter of the two budgets is reported
+pub async fn rate_limit_headers(req: Request<Body>, next: Next, expose: bool) -> Response {
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+
+    let lowest = |name: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .min()
+    };
+    let budget = lowest(RATE_LIMIT_LIMIT).zip(lowest(RATE_LIMIT_REMAINING));
+
+    for name in [
+        RATE_LIMIT_LIMIT,
+        RATE_LIMIT_REMAINING,
+        RATE_LIMIT_WHITELISTED,
+    ] {
+        headers.remove(name);
+    }
+    if let Some((limit, remaining)) = budget.filter(|_| expose) {
+        let reset = limit.saturating_sub(remaining) * PRIVATE_PERIOD_SECS;
+        headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(limit));
+        headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(remaining));
+        headers.insert(RATE_LIMIT_RESET, HeaderValue::from(reset));
+    }
+
+    response
+}
+
+// Body with declared length over the limit is refused before a byte of it is read.
+// Requests without Content-Length are left to the streaming body limit
+pub async fn check_content_length(
+    req: Request<Body>,
+    next: Next,
+    limit: usize,
+) -> Result<Response, ServerError> {
+    let declared = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    if declared.is_some_and(|length| length > limit as u64) {
+        tracing::warn!("Body with declared length {:?} is rejected!", declared);
+        return Err(ServerError::PayloadTooLarge(limit));
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Converting errors of load shedding layer into our JSON errors
+pub async fn handle_overload(err: BoxError) -> ServerError {
+    if err.is::<tower::load_shed::error::Overloaded>() {
+        tracing::warn!("Concurrency limit is reached! Request is rejected");
+        ServerError::Unavailable("Server is overloaded, try again later".into())
+    } else {
+        tracing::error!("Unhandled middleware error: {}", err);
+        ServerError::Unavailable(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod core_tests {
+    use super::*;
+    use axum::{Router, middleware, routing::get};
+    use jsonwebtoken::Algorithm;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    // Collects everything written by tracing subscriber to check it later
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl CapturedLogs {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().expect("Can't lock logs").clone())
+                .expect("Logs are not UTF-8")
+        }
+    }
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .expect("Can't lock logs")
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for CapturedLogs {
+        type Writer = CapturedLogs;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn capture_logs() -> (CapturedLogs, tracing::subscriber::DefaultGuard) {
+        let logs = CapturedLogs::default();
+        let subscriber = FmtSubscriber::builder()
+            .with_max_level(tracing::Level::INFO)
+            .with_writer(logs.clone())
+            .with_ansi(false)
+            .finish();
+
+        (logs, tracing::subscriber::set_default(subscriber))
+    }
+
+    fn slow_app(threshold: Duration) -> Router {
+        Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    "Slow"
+                }),
+            )
+            .route("/fast", get(|| async { "Fast" }))
+            .layer(middleware::from_fn(move |req, next| {
+                log_slow_requests(req, next, threshold)
+            }))
+    }
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .method("GET")
+            .body(Body::empty())
+            .expect("Can't create request")
+    }
+
+    #[tokio::test]
+    async fn test_log_writer_fallback() {
+        let base = std::env::temp_dir().join(format!("flappy_logs_{}", std::process::id()));
+        std::fs::create_dir_all(&base).expect("Can't create temp dir");
+
+        // Directory can't be created inside a regular file, even by root
+        let blocker = base.join("not_a_dir");
+        std::fs::write(&blocker, "").expect("Can't create blocker file");
+        let unwritable = blocker.join("logs");
+        assert!(
+            log_writer(unwritable.to_str().unwrap()).is_err(),
+            "Unwritable log dir is accepted!"
+        );
+
+        let writable = base.join("logs");
+        assert!(log_writer(writable.to_str().unwrap()).is_ok());
+        assert!(writable.is_dir(), "Log dir is not created!");
+
+        std::fs::remove_dir_all(&base).expect("Can't clean up temp dir");
+    }
+
+    #[tokio::test]
+    async fn test_clean_up_logs() {
+        let dir = std::env::temp_dir().join(format!("flappy_cleanup_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Can't create temp dir");
+        let now = std::time::SystemTime::now();
+        let fake_log = |name: &str, days_old: u64| {
+            let path = dir.join(name);
+            let file = std::fs::File::create(&path).expect("Can't create log file");
+            file.set_len(1024).expect("Can't fill log file");
+            file.set_modified(now - Duration::from_secs(days_old * 86400))
+                .expect("Can't set log time");
+            path
+        };
+
+        let oldest = fake_log("serv.log.2026-01-01", 4);
+        let older = fake_log("serv.log.2026-01-02", 3);
+        let recent = fake_log("serv.log.2026-01-03", 2);
+        let current = fake_log("serv.log.2026-01-04", 0);
+        let other = fake_log("other.txt", 10);
+
+        // Cap fits two logs only, so the two oldest ones go
+        let deleted = clean_up_logs(dir.to_str().unwrap(), None, Some(2 * 1024));
+        assert_eq!(deleted, 2);
+        assert!(!oldest.exists() && !older.exists(), "Oldest logs are kept!");
+        assert!(recent.exists() && current.exists());
+        assert!(other.exists(), "Not a log file is deleted!");
+
+        // Age limit applies on its own, but never to the file being written
+        let deleted = clean_up_logs(dir.to_str().unwrap(), Some(Duration::ZERO), None);
+        assert_eq!(deleted, 1);
+        assert!(!recent.exists());
+        assert!(current.exists(), "Current log is deleted!");
+
+        std::fs::remove_dir_all(&dir).expect("Can't clean up temp dir");
+    }
+
+    #[tokio::test]
+    async fn test_log_slow_requests() {
+        let (logs, _guard) = capture_logs();
+        let app = slow_app(Duration::from_millis(10));
+
+        app.clone()
+            .oneshot(get_request("/fast"))
+            .await
+            .expect("Can't get response");
+        assert!(
+            !logs.contents().contains("Slow request!"),
+            "Fast request is logged as slow!"
+        );
+
+        app.oneshot(get_request("/slow"))
+            .await
+            .expect("Can't get response");
+        let contents = logs.contents();
+        assert!(contents.contains("WARN"), "Slow request is not a warning!");
+        assert!(
+            contents.contains("Slow request!"),
+            "Slow request is not logged!"
+        );
+        assert!(contents.contains("route=\"/slow\""), "Route is not logged!");
+    }
+
+    #[tokio::test]
+    async fn test_log_rate_limited() {
+        let (logs, _guard) = capture_logs();
+        let state = AppState::new(
+            crate::db_access::get_test_db_pool().await,
+            set_up_jwt(&AppConfig::default()),
+        );
+        let app = Router::new()
+            .route("/limited", get(|| async { "Hello" }))
+            .layer(tower_governor::GovernorLayer {
+                config: set_up_admin_governor(&AppConfig::default()),
+            })
+            .route(
+                "/busy",
+                get(|| async { axum::http::StatusCode::TOO_MANY_REQUESTS }),
+            )
+            .layer(middleware::from_fn({
+                let state = state.clone();
+                move |req, next| log_rate_limited(req, next, state.clone())
+            }));
+        let request = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(AUTHORIZATION, "Bearer some.jwt.token")
+                .body(Body::empty())
+                .expect("Can't create request")
+        };
+
+        let busy = app.clone().oneshot(request("/busy")).await.unwrap();
+        let first = app.clone().oneshot(request("/limited")).await.unwrap();
+        assert_eq!(busy.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(first.status(), axum::http::StatusCode::OK);
+        assert_eq!(state.rate_limited.load(Ordering::Relaxed), 0);
+        assert!(!logs.contents().contains("Request is rate limited!"));
+
+        let limited = app.oneshot(request("/limited")).await.unwrap();
+        assert_eq!(limited.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(state.rate_limited.load(Ordering::Relaxed), 1);
+        let contents = logs.contents();
+        assert!(contents.contains("WARN"), "Rejection is not a warning!");
+        assert!(contents.contains("Request is rate limited!"));
+        assert!(
+            contents.contains("route=\"/limited\""),
+            "Route is not logged!"
+        );
+        assert!(
+            contents.contains(&format!("{:016x}", token_key("some.jwt.token"))),
+            "Key is not logged!"
+        );
+        assert!(
+            !contents.contains("some.jwt.token"),
+            "Token is logged in plain text!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "Slow"
+        };
+        let app = Router::new()
+            .route("/slow", get(slow))
+            .route("/other", get(slow))
+            .layer(
+                tower::ServiceBuilder::new()
+                    .layer(axum::error_handling::HandleErrorLayer::new(handle_overload))
+                    .load_shed()
+                    .layer(tower::limit::GlobalConcurrencyLimitLayer::new(1)),
+            )
+            // Converting handlers into routes once, so all requests share the same limiter
+            .with_state(());
+
+        let in_flight = tokio::spawn(app.clone().oneshot(get_request("/slow")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Limit is global, so a request to another route is rejected too
+        let rejected = app
+            .clone()
+            .oneshot(get_request("/other"))
+            .await
+            .expect("Can't get response");
+        let accepted = in_flight
+            .await
+            .expect("Task failed")
+            .expect("Can't get response");
+        let after = app
+            .oneshot(get_request("/slow"))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(
+            rejected.status(),
+            axum::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(accepted.status(), axum::http::StatusCode::OK);
+        assert_eq!(after.status(), axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_check_jwt_secret() {
+        let with_passphrase = |passphrase: Option<&str>| AppConfig {
+            jwt_passphrase: passphrase.map(str::to_string),
+            ..AppConfig::default()
+        };
+
+        assert!(check_jwt_secret(&with_passphrase(None)).is_ok());
+        assert!(check_jwt_secret(&with_passphrase(Some("correct horse battery staple"))).is_ok());
+        assert!(matches!(
+            check_jwt_secret(&with_passphrase(Some("hunter2"))),
+            Err(ServerError::Configuration(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_load_rsa_keys() {
+        let with_paths = |private: Option<&str>, public: Option<&str>| AppConfig {
+            jwt_private_key_path: private.map(str::to_string),
+            jwt_public_key_path: public.map(str::to_string),
+            ..AppConfig::default()
+        };
+        let private = Some("test_data/jwt/rs256_private.pem");
+        let public = Some("test_data/jwt/rs256_public.pem");
+
+        assert!(matches!(load_rsa_keys(&with_paths(None, None)), Ok(None)));
+        assert!(matches!(
+            load_rsa_keys(&with_paths(private, public)),
+            Ok(Some(_))
+        ));
+        for config in [
+            with_paths(private, None),
+            with_paths(None, public),
+            with_paths(private, Some("test_data/jwt/missing.pem")),
+        ] {
+            assert!(matches!(
+                check_jwt_secret(&config),
+                Err(ServerError::Configuration(_))
+            ));
+        }
+
+        let jwt_config = set_up_jwt(&with_paths(private, public));
+        assert_eq!(jwt_config.read().await.algorithm, Algorithm::RS256);
+    }
+
+    #[tokio::test]
+    async fn test_cors_expose_headers() {
+        let app_with = |cors_expose_headers: Vec<String>| {
+            let config = AppConfig {
+                cors_allowed_origins: vec!["http://0.0.0.0:3000".to_string()],
+                cors_expose_headers,
+                ..AppConfig::default()
+            };
+            Router::new()
+                .route("/test", get(|| async { "Hello" }))
+                .layer(set_up_cors(&config))
+        };
+        let request = || {
+            Request::builder()
+                .uri("/test")
+                .header("Origin", "http://0.0.0.0:3000")
+                .body(Body::empty())
+                .expect("Can't create request")
+        };
+
+        let exposed = app_with(vec![
+            "X-Request-Id".to_string(),
+            "X-Response-Time-Ms".to_string(),
+            "bad header".to_string(),
+        ])
+        .oneshot(request())
+        .await
+        .expect("Can't get response");
+        let default = app_with(vec![])
+            .oneshot(request())
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(
+            exposed
+                .headers()
+                .get("Access-Control-Expose-Headers")
+                .expect("Headers are not exposed!"),
+            "x-request-id,x-response-time-ms"
+        );
+        assert!(
+            !default
+                .headers()
+                .contains_key("Access-Control-Expose-Headers"),
+            "Headers are exposed by default!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_content_length() {
+        let reached = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let app = Router::new()
+            .route(
+                "/upload",
+                axum::routing::post({
+                    let reached = reached.clone();
+                    move |body: String| async move {
+                        reached.store(true, std::sync::atomic::Ordering::SeqCst);
+                        body
+                    }
+                }),
+            )
+            .layer(tower_http::limit::RequestBodyLimitLayer::new(16))
+            .layer(middleware::from_fn(|req, next| {
+                check_content_length(req, next, 16)
+            }));
+        let upload = |length: Option<&str>, body: &'static str| {
+            let mut builder = Request::builder().uri("/upload").method("POST");
+            if let Some(length) = length {
+                builder = builder.header(CONTENT_LENGTH, length);
+            }
+            builder
+                .body(Body::from(body))
+                .expect("Can't create request")
+        };
+
+        let declared = app
+            .clone()
+            .oneshot(upload(Some("1000000"), ""))
+            .await
+            .expect("Can't get response");
+        assert_eq!(declared.status(), axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(
+            !reached.load(std::sync::atomic::Ordering::SeqCst),
+            "Oversized body reached the handler!"
+        );
+
+        let small = app
+            .clone()
+            .oneshot(upload(Some("5"), "small"))
+            .await
+            .expect("Can't get response");
+        assert_eq!(small.status(), axum::http::StatusCode::OK);
+
+        // Streamed body without declared length is still cut by the body limit
+        let streamed = Request::builder()
+            .uri("/upload")
+            .method("POST")
+            .body(Body::from_stream(futures::stream::iter([Ok::<
+                _,
+                std::io::Error,
+            >(
+                "x".repeat(64)
+            )])))
+            .expect("Can't create request");
+        let streamed = app.oneshot(streamed).await.expect("Can't get response");
+        assert_eq!(streamed.status(), axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_log_slow_requests_threshold() {
+        let (logs, _guard) = capture_logs();
+        let app = slow_app(Duration::from_secs(5));
+
+        app.oneshot(get_request("/slow"))
+            .await
+            .expect("Can't get response");
+        assert!(
+            !logs.contents().contains("Slow request!"),
+            "Request under threshold is logged as slow!"
+        );
+    }
+}
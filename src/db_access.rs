@@ -1,25 +1,96 @@
+use chrono::{DateTime, Utc};
 use dotenv::dotenv;
+use futures::{StreamExt, stream::BoxStream};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
-use std::env;
+use sqlx::{PgConnection, PgExecutor, PgPool, Postgres, postgres::PgArguments, query::QueryScalar};
+use std::{collections::HashMap, env, sync::OnceLock, time::Duration};
 use validator::Validate;
 
-use crate::error::ServerError;
+use crate::{
+    backup::{Backup, BackupArchivedScore, BackupScore},
+    config::env_or,
+    error::ServerError,
+    notice::{Notice, NoticeLevel},
+    player_name::PlayerName,
+};
+
+// Range of a valid score, see score_schema for how it is published to clients
+pub const SCORE_MIN: i32 = 0;
+pub const SCORE_MAX: i32 = 1_000_000;
 
 #[derive(sqlx::FromRow, Debug, Deserialize, Serialize, Validate, PartialEq, Clone)]
 pub struct PlayerScore {
-    #[validate(length(min = 3, max = 20))]
-    pub player_name: String,
+    // Opaque handle of a live board row, sent only with EXPOSE_SCORE_IDS. Ids restart after
+    // restore, are shared by boards of all games and have gaps where rows were trimmed, so they
+    // say nothing reliable about submission order or volume and clients must not read into them
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+
+    // Name rules are checked by PlayerName itself on deserialization
+    pub player_name: PlayerName,
+
+    #[validate(range(min = SCORE_MIN, max = SCORE_MAX))]
+    pub player_score: i32,
+
+    // Stable id of the player on client side, optional for clients sending only names
+    #[validate(length(min = 1, max = 64))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_id: Option<String>,
+
+    #[validate(length(min = 1, max = 128))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+
+    // Skill tier claimed by the player, checked against TIER_MAX_SCORES when it is set
+    #[validate(length(min = 1, max = 32))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+
+    // Difficulty the score was played at, checked against DIFFICULTIES
+    #[validate(length(min = 1, max = 32))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<String>,
 
-    #[validate(range(min = 0, max = 1_000_000))]
+    // Submissions of the player counted with dedup, set only by the server
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub play_count: Option<i32>,
+}
+
+// Board entry with its position, equal scores share the rank and the next one skips ahead
+#[derive(sqlx::FromRow, Debug, Serialize, PartialEq, Clone)]
+pub struct RankedPlayerScore {
+    pub rank: i64,
+    pub player_name: PlayerName,
     pub player_score: i32,
 }
 
+#[cfg(test)]
+impl PlayerScore {
+    // Same identity rules as dedup on the board - id when present, name otherwise
+    pub fn same_player(&self, other: &PlayerScore) -> bool {
+        match (&self.player_id, &other.player_id) {
+            (Some(id), Some(other_id)) => id == other_id,
+            (None, None) => self.player_name == other.player_name,
+            _ => false,
+        }
+    }
+
+    // Row ids depend on what the table went through before, so tests compare without them
+    pub fn without_id(self) -> Self {
+        PlayerScore { id: None, ..self }
+    }
+}
+
+// Stripping row ids from the whole board for comparisons in tests
+#[cfg(test)]
+fn without_ids(scores: Vec<PlayerScore>) -> Vec<PlayerScore> {
+    scores.into_iter().map(PlayerScore::without_id).collect()
+}
+
 pub async fn health_db(pool: &PgPool) -> Result<(), ServerError> {
-    sqlx::query!("SELECT 1 AS one")
-        .fetch_one(pool)
+    timed(sqlx::query!("SELECT 1 AS one").fetch_one(pool))
         .await
-        .map_or_else(|e| Err(ServerError::Database(format!("{}", e))), |_| Ok(()))
+        .map(|_| ())
 }
 
 pub async fn connect_to_db() -> Result<PgPool, ServerError> {
@@ -30,295 +101,1992 @@ pub async fn connect_to_db() -> Result<PgPool, ServerError> {
     Ok(pool)
 }
 
-pub async fn flush_scores_db(pool: &PgPool) -> Result<(), ServerError> {
-    sqlx::query!("TRUNCATE TABLE flappy_dragon_score RESTART IDENTITY")
-        .execute(pool)
+// Read replica is optional - without READ_DATABASE_URL all queries go to primary
+pub async fn connect_to_read_db() -> Result<Option<PgPool>, ServerError> {
+    dotenv().ok();
+    match env::var("READ_DATABASE_URL") {
+        Ok(read_database_url) => Ok(Some(PgPool::connect(&read_database_url).await?)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    // Total number of tries, 1 means no retries
+    pub attempts: u32,
+    // Delay before the first retry, doubled for every next one
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(20),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let default = RetryPolicy::default();
+
+        RetryPolicy {
+            attempts: env_or("DB_RETRY_ATTEMPTS", default.attempts).max(1),
+            backoff: Duration::from_millis(env_or(
+                "DB_RETRY_BACKOFF_MS",
+                default.backoff.as_millis() as u64,
+            )),
+        }
+    }
+}
+
+// Read from env once, like database address
+fn retry_policy() -> RetryPolicy {
+    static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+    *POLICY.get_or_init(RetryPolicy::from_env)
+}
+
+// Errors after which the query surely had no effect and may succeed if repeated
+fn is_retryable(error: &sqlx::Error) -> bool {
+    match error {
+        // serialization_failure and deadlock_detected roll the statement back
+        sqlx::Error::Database(e) => matches!(e.code().as_deref(), Some("40001" | "40P01")),
+        sqlx::Error::Io(_) => true,
+        _ => false,
+    }
+}
+
+// Repeats the query on transient failures, other errors are returned right away.
+// Only for queries that are safe to repeat - reads and idempotent writes
+pub async fn retry_query<T, F, Fut>(policy: RetryPolicy, mut query: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut backoff = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        match query().await {
+            Err(e) if attempt < policy.attempts && is_retryable(&e) => {
+                tracing::warn!("Transient DB error, retrying in {:?}: {}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Upper bound of a single DB call, 0 disables it. Read from env once, like retry policy
+fn query_timeout() -> Option<Duration> {
+    static TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+    *TIMEOUT.get_or_init(|| {
+        dotenv().ok();
+        let secs: u64 = env_or("DB_QUERY_TIMEOUT_SECS", 5);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    })
+}
+
+// Gives up on a slow query before the HTTP timeout does, so the caller gets a database error.
+// Dropped query leaves its connection busy until Postgres finishes the statement,
+// the pool tests it on release and takes it back only then
+async fn with_timeout<T, Fut>(limit: Option<Duration>, query: Fut) -> Result<T, ServerError>
+where
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let Some(limit) = limit else {
+        return Ok(query.await?);
+    };
+    match tokio::time::timeout(limit, query).await {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            tracing::error!("DB query timed out after {:?}!", limit);
+            Err(ServerError::Database(format!(
+                "Query timed out after {:?}",
+                limit
+            )))
+        }
+    }
+}
+
+// Query bounded by DB_QUERY_TIMEOUT_SECS
+async fn timed<T, Fut>(query: Fut) -> Result<T, ServerError>
+where
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    with_timeout(query_timeout(), query).await
+}
+
+// Clears the board of one game, boards of other games are kept
+pub async fn flush_scores_db(pool: &PgPool, game: &str) -> Result<(), ServerError> {
+    timed(sqlx::query!("DELETE FROM flappy_dragon_score WHERE game_id = $1", game).execute(pool))
         .await?;
 
     Ok(())
 }
 
-pub async fn get_scores_db(pool: &PgPool) -> Result<Vec<PlayerScore>, ServerError> {
-    let scores_array = sqlx::query_as!(
+// Deleting all rows of the players in one statement, on boards of every game, so a sweep is removed entirely or not at all.
+// Returns number of removed rows per name, names without rows are left out
+pub async fn delete_players_db(
+    pool: &PgPool,
+    names: &[String],
+) -> Result<HashMap<String, i64>, ServerError> {
+    let removed = timed(sqlx::query!(
+        "WITH deleted AS (DELETE FROM flappy_dragon_score WHERE player_name = ANY($1) RETURNING player_name) SELECT player_name, COUNT(*) AS \"count!\" FROM deleted GROUP BY player_name",
+        names
+    )
+    .fetch_all(pool))
+    .await?;
+
+    Ok(removed
+        .into_iter()
+        .map(|row| (row.player_name, row.count))
+        .collect())
+}
+
+// Counts exactly the rows flush_scores_db would delete
+pub async fn count_scores_db(pool: &PgPool, game: &str) -> Result<i64, ServerError> {
+    let count = timed(
+        sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM flappy_dragon_score WHERE game_id = $1",
+            game
+        )
+        .fetch_one(pool),
+    )
+    .await?;
+
+    Ok(count.unwrap_or(0))
+}
+
+pub async fn get_scores_db(pool: &PgPool, game: &str) -> Result<Vec<PlayerScore>, ServerError> {
+    let scores_array = timed(retry_query(retry_policy(), || {
+        sqlx::query_as!(
+            PlayerScore,
+            "SELECT id AS \"id?\", player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, tier, difficulty, play_count FROM flappy_dragon_score WHERE game_id = $1 ORDER BY player_score DESC, id",
+            game
+        )
+        .fetch_all(pool)
+    }))
+    .await?;
+
+    Ok(scores_array)
+}
+
+pub async fn get_scores_paginated_db(
+    pool: &PgPool,
+    game: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<PlayerScore>, ServerError> {
+    let scores_array = timed(retry_query(retry_policy(), || {
+        sqlx::query_as!(
+            PlayerScore,
+            "SELECT id AS \"id?\", player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, tier, difficulty, play_count FROM flappy_dragon_score WHERE game_id = $1 ORDER BY player_score DESC, id LIMIT $2 OFFSET $3",
+            game,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+    }))
+    .await?;
+
+    Ok(scores_array)
+}
+
+// Posted times are stored in UTC without a zone, the column default converts now() to UTC
+// whatever the time zone of the session is, and restore writes them the same way
+pub async fn get_scores_since_db(
+    pool: &PgPool,
+    game: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<PlayerScore>, ServerError> {
+    let scores_array = timed(retry_query(retry_policy(), || {
+        sqlx::query_as!(
+            PlayerScore,
+            "SELECT id AS \"id?\", player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, tier, difficulty, play_count FROM flappy_dragon_score WHERE game_id = $1 AND posted_time >= $2 AT TIME ZONE 'UTC' ORDER BY player_score DESC",
+            game,
+            since
+        )
+        .fetch_all(pool)
+    }))
+    .await?;
+
+    Ok(scores_array)
+}
+
+pub async fn get_ranked_scores_db(
+    pool: &PgPool,
+    game: &str,
+) -> Result<Vec<RankedPlayerScore>, ServerError> {
+    let scores_array = timed(retry_query(retry_policy(), || {
+        sqlx::query_as!(
+            RankedPlayerScore,
+            "SELECT RANK() OVER (ORDER BY player_score DESC) AS \"rank!\", player_name AS \"player_name: PlayerName\", player_score FROM flappy_dragon_score WHERE game_id = $1 ORDER BY player_score DESC, id",
+            game
+        )
+        .fetch_all(pool)
+    }))
+    .await?;
+
+    Ok(scores_array)
+}
+
+// Rows buffered ahead of a slow consumer, reading from the database waits beyond it
+const STREAM_BUFFER: usize = 64;
+
+// Board row by row for very large boards, memory stays bounded by the buffer.
+// Not retried - rows already sent can't be taken back. Not timed either, export of a big board
+// lasts as long as the client takes to read it
+pub fn stream_scores_db(
+    pool: PgPool,
+    game: String,
+) -> BoxStream<'static, Result<PlayerScore, ServerError>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as!(
+            PlayerScore,
+            "SELECT id AS \"id?\", player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, tier, difficulty, play_count FROM flappy_dragon_score WHERE game_id = $1 ORDER BY player_score DESC",
+            game
+        )
+        .fetch(&pool);
+
+        while let Some(row) = rows.next().await {
+            let failed = row.is_err();
+            // Closed channel means the consumer is gone, so the query is dropped
+            if tx.send(row.map_err(ServerError::from)).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    futures::stream::unfold(
+        rx,
+        |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+    )
+    .boxed()
+}
+
+pub async fn season_archived_db(pool: &PgPool, season: i32) -> Result<bool, ServerError> {
+    let exists = timed(
+        sqlx::query_scalar!(
+            "SELECT EXISTS (SELECT 1 FROM flappy_dragon_score_archive WHERE season_id = $1)",
+            season
+        )
+        .fetch_one(pool),
+    )
+    .await?;
+
+    Ok(exists.unwrap_or(false))
+}
+
+// Archived board of the season in the same order as the live one, id keeps equal scores stable
+pub async fn get_archived_scores_db(
+    pool: &PgPool,
+    season: i32,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<PlayerScore>, ServerError> {
+    let scores_array = timed(sqlx::query_as!(
         PlayerScore,
-        "SELECT player_name, player_score FROM flappy_dragon_score ORDER BY player_score DESC"
+        "SELECT NULL::INT AS id, player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, NULL::TEXT AS tier, NULL::TEXT AS difficulty, NULL::INT AS play_count FROM flappy_dragon_score_archive WHERE season_id = $1 ORDER BY player_score DESC, id LIMIT $2 OFFSET $3",
+        season,
+        limit,
+        offset
     )
-    .fetch_all(pool)
+    .fetch_all(pool))
     .await?;
 
     Ok(scores_array)
 }
 
-async fn check_if_record_worthy(pool: &PgPool, score: &PlayerScore) -> Result<bool, ServerError> {
-    let min_score = sqlx::query_scalar("SELECT COALESCE (MIN(player_score), 1) FROM (SELECT player_score FROM flappy_dragon_score ORDER BY player_score DESC LIMIT 10) AS top")
-        .fetch_optional(pool).await?.unwrap_or(1);
+// Lowest score that still makes it to the board. Board with free places takes any score
+// from the floor, full board - scores not lower than its last one
+pub async fn board_cutoff_db(
+    pool: &PgPool,
+    game: &str,
+    floor: i32,
+    board_size: usize,
+) -> Result<i32, ServerError> {
+    let cutoff = timed(retry_query(retry_policy(), || {
+        board_cutoff_query(game, floor, board_size).fetch_one(pool)
+    }))
+    .await?;
+
+    Ok(cutoff)
+}
+
+fn board_cutoff_query(
+    game: &str,
+    floor: i32,
+    board_size: usize,
+) -> QueryScalar<'_, Postgres, i32, PgArguments> {
+    sqlx::query_scalar("SELECT CASE WHEN COUNT(*) < $2 THEN $1 ELSE GREATEST(MIN(player_score), $1) END FROM (SELECT player_score FROM flappy_dragon_score WHERE game_id = $3 ORDER BY player_score DESC LIMIT $2) AS top")
+        .bind(floor)
+        .bind(board_size as i64)
+        .bind(game)
+}
+
+// Position of the row on the board, equal scores share the rank. None if the row is gone
+pub async fn score_rank_db(pool: &PgPool, game: &str, id: i32) -> Result<Option<i64>, ServerError> {
+    let rank = timed(retry_query(retry_policy(), || {
+        sqlx::query_scalar("SELECT 1 + (SELECT COUNT(*) FROM flappy_dragon_score AS above WHERE above.game_id = score.game_id AND above.player_score > score.player_score) FROM flappy_dragon_score AS score WHERE score.id = $1 AND score.game_id = $2")
+            .bind(id)
+            .bind(game)
+            .fetch_optional(pool)
+    }))
+    .await?;
+
+    Ok(rank)
+}
+
+// Takes the transaction of the insert as well, so it is not retried
+async fn check_if_record_worthy<'c>(
+    conn: impl PgExecutor<'c>,
+    game: &str,
+    score: &PlayerScore,
+    floor: i32,
+    board_size: usize,
+) -> Result<bool, ServerError> {
+    let cutoff = timed(board_cutoff_query(game, floor, board_size).fetch_one(conn)).await?;
+    Ok(score.player_score >= cutoff)
+}
+
+// Returns stored representation of the score or None if it is not worthy of the board.
+// With dedup only the best score of the player is kept on the board, with counted plays
+// lower submissions still bump play count of the player's entry.
+// Runs in one transaction, so a failed insert doesn't lose the replaced best. With dedup
+// submissions of the same player wait for each other, so the best they read stays the best
+#[allow(clippy::too_many_arguments)]
+pub async fn add_new_score_db(
+    pool: &PgPool,
+    game: &str,
+    score: PlayerScore,
+    dedup: bool,
+    count_plays: bool,
+    client_version: Option<&str>,
+    floor: i32,
+    board_size: usize,
+) -> Result<Option<PlayerScore>, ServerError> {
+    let mut tx = timed(pool.begin()).await?;
+    if dedup {
+        lock_player_db(&mut tx, game, &score).await?;
+    }
+
+    // Before worthiness, as a lower score of a player on the board may be under the cutoff too
+    if dedup && count_plays && count_lower_play_db(&mut tx, game, &score).await? {
+        timed(tx.commit()).await?;
+        return Ok(None);
+    }
+
+    if !check_if_record_worthy(&mut *tx, game, &score, floor, board_size).await? {
+        return Ok(None);
+    }
+
+    let previous_plays = match dedup {
+        true => match replace_player_best_db(&mut tx, game, &score).await? {
+            Some(plays) => plays,
+            None => return Ok(None),
+        },
+        false => 0,
+    };
+    let play_count = count_plays.then_some(previous_plays + 1);
+
+    // Inserting value. Not retried - after a connection error the score may already be stored
+    let stored = timed(sqlx::query_as!(
+        PlayerScore,
+        "INSERT INTO flappy_dragon_score (player_name, player_score, player_id, avatar, tier, difficulty, client_version, play_count, game_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id AS \"id?\", player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, tier, difficulty, play_count",
+        score.player_name.as_str(),
+        score.player_score,
+        score.player_id.as_deref(),
+        score.avatar.as_deref(),
+        score.tier.as_deref(),
+        score.difficulty.as_deref(),
+        client_version,
+        play_count,
+        game
+    )
+    .fetch_one(&mut *tx))
+    .await?;
+
+    timed(
+        sqlx::query!("DELETE FROM flappy_dragon_score WHERE game_id = $2 AND id NOT IN (SELECT id FROM flappy_dragon_score WHERE game_id = $2 ORDER BY player_score DESC LIMIT $1)", board_size as i64, game)
+            .execute(&mut *tx),
+    )
+    .await?;
+
+    timed(tx.commit()).await?;
+    Ok(Some(stored))
+}
+
+// Lock on the identity of the player until the transaction ends, taken even when the player
+// has no rows yet. Identity rules are the same as for dedup
+async fn lock_player_db(
+    conn: &mut PgConnection,
+    game: &str,
+    score: &PlayerScore,
+) -> Result<(), ServerError> {
+    let identity = match &score.player_id {
+        Some(player_id) => format!("{}/id:{}", game, player_id),
+        None => format!("{}/name:{}", game, score.player_name.as_str()),
+    };
+    timed(
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(identity)
+            .execute(&mut *conn),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// Players with id are matched by id, players without it - by name among other id-less entries.
+// Deletes older entries of the player if the new score beats them and returns their play count
+// (entries stored without counting are one play each), returns None otherwise
+async fn replace_player_best_db(
+    conn: &mut PgConnection,
+    game: &str,
+    score: &PlayerScore,
+) -> Result<Option<i32>, ServerError> {
+    let best = timed(sqlx::query!(
+        "SELECT MAX(player_score) AS best, MAX(COALESCE(play_count, 1)) AS plays FROM flappy_dragon_score WHERE game_id = $3 AND player_id IS NOT DISTINCT FROM $1 AND ($1::text IS NOT NULL OR player_name = $2)",
+        score.player_id.as_deref(),
+        score.player_name.as_str(),
+        game
+    )
+    .fetch_one(&mut *conn))
+    .await?;
+
+    if best.best.is_some_and(|best| best >= score.player_score) {
+        return Ok(None);
+    }
+
+    timed(sqlx::query!(
+        "DELETE FROM flappy_dragon_score WHERE game_id = $3 AND player_id IS NOT DISTINCT FROM $1 AND ($1::text IS NOT NULL OR player_name = $2)",
+        score.player_id.as_deref(),
+        score.player_name.as_str(),
+        game
+    )
+    .execute(&mut *conn))
+    .await?;
+
+    Ok(Some(best.plays.unwrap_or(0)))
+}
+
+// Best score of the player on the board, same identity rules as dedup
+pub async fn player_best_db(
+    pool: &PgPool,
+    game: &str,
+    score: &PlayerScore,
+) -> Result<Option<i32>, ServerError> {
+    let best = timed(retry_query(retry_policy(), || {
+        sqlx::query_scalar!(
+            "SELECT MAX(player_score) FROM flappy_dragon_score WHERE game_id = $3 AND player_id IS NOT DISTINCT FROM $1 AND ($1::text IS NOT NULL OR player_name = $2)",
+            score.player_id.as_deref(),
+            score.player_name.as_str(),
+            game
+        )
+        .fetch_one(pool)
+    }))
+    .await?;
+
+    Ok(best)
+}
+
+// Best entry stored under the name, the earliest one among equal scores
+pub async fn get_player_best_db(
+    pool: &PgPool,
+    game: &str,
+    name: &PlayerName,
+) -> Result<Option<PlayerScore>, ServerError> {
+    let best = timed(retry_query(retry_policy(), || {
+        sqlx::query_as!(
+            PlayerScore,
+            "SELECT id AS \"id?\", player_name AS \"player_name: PlayerName\", player_score, player_id, avatar, tier, difficulty, play_count FROM flappy_dragon_score WHERE game_id = $2 AND player_name = $1 ORDER BY player_score DESC, id LIMIT 1",
+            name.as_str(),
+            game
+        )
+        .fetch_optional(pool)
+    }))
+    .await?;
+
+    Ok(best)
+}
+
+// Counts a play on the player's entry when it is not beaten by the new score.
+// Returns false when the player has no such entry
+async fn count_lower_play_db(
+    conn: &mut PgConnection,
+    game: &str,
+    score: &PlayerScore,
+) -> Result<bool, ServerError> {
+    let counted = timed(sqlx::query!(
+        "UPDATE flappy_dragon_score SET play_count = COALESCE(play_count, 1) + 1 WHERE game_id = $4 AND player_id IS NOT DISTINCT FROM $1 AND ($1::text IS NOT NULL OR player_name = $2) AND player_score >= $3",
+        score.player_id.as_deref(),
+        score.player_name.as_str(),
+        score.player_score,
+        game
+    )
+    .execute(&mut *conn))
+    .await?;
+
+    Ok(counted.rows_affected() > 0)
+}
+
+// Rows in insertion order, so equal scores keep their order after restore
+pub async fn backup_tables_db(
+    pool: &PgPool,
+) -> Result<(Vec<BackupScore>, Vec<BackupArchivedScore>), ServerError> {
+    let scores = timed(sqlx::query_as!(
+        BackupScore,
+        "SELECT game_id, player_name, player_score, player_id, avatar, tier, difficulty, client_version, play_count, posted_time AT TIME ZONE 'UTC' AS \"posted_time: DateTime<Utc>\" FROM flappy_dragon_score ORDER BY id"
+    )
+    .fetch_all(pool))
+    .await?;
+
+    let archive = timed(sqlx::query_as!(
+        BackupArchivedScore,
+        "SELECT season_id, player_name, player_score, player_id, avatar, posted_time AT TIME ZONE 'UTC' AS \"posted_time: DateTime<Utc>\" FROM flappy_dragon_score_archive ORDER BY id"
+    )
+    .fetch_all(pool))
+    .await?;
 
-    Ok(score.player_score >= min_score)
+    Ok((scores, archive))
 }
 
-pub async fn add_new_score_db(pool: &PgPool, score: PlayerScore) -> Result<(), ServerError> {
-    if check_if_record_worthy(pool, &score).await? {
-        // Inserting value
+// Replaces scores and archive with the backup in one transaction, so a failed restore
+// leaves the old data in place. Notice is written too when it is given
+pub async fn restore_tables_db(
+    pool: &PgPool,
+    backup: &Backup,
+    notice: Option<&Notice>,
+) -> Result<(), ServerError> {
+    let mut tx = pool.begin().await?;
+
+    timed(
         sqlx::query!(
-            "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ($1, $2)",
-            score.player_name,
-            score.player_score
+            "TRUNCATE TABLE flappy_dragon_score, flappy_dragon_score_archive RESTART IDENTITY"
+        )
+        .execute(&mut *tx),
+    )
+    .await?;
+
+    let scores = &backup.scores;
+    timed(sqlx::query!(
+        "INSERT INTO flappy_dragon_score (player_name, player_score, player_id, avatar, tier, difficulty, client_version, play_count, posted_time, game_id) SELECT name, score, id, avatar, tier, difficulty, version, plays, posted AT TIME ZONE 'UTC', game FROM UNNEST($1::text[], $2::int[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::int[], $9::timestamptz[], $10::text[]) AS rows(name, score, id, avatar, tier, difficulty, version, plays, posted, game)",
+        &scores.iter().map(|s| s.player_name.clone()).collect::<Vec<_>>(),
+        &scores.iter().map(|s| s.player_score).collect::<Vec<_>>(),
+        &scores.iter().map(|s| s.player_id.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &scores.iter().map(|s| s.avatar.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &scores.iter().map(|s| s.tier.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &scores.iter().map(|s| s.difficulty.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &scores.iter().map(|s| s.client_version.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &scores.iter().map(|s| s.play_count).collect::<Vec<_>>() as &[Option<i32>],
+        &scores.iter().map(|s| s.posted_time).collect::<Vec<_>>() as &[Option<DateTime<Utc>>],
+        &scores.iter().map(|s| s.game_id.clone()).collect::<Vec<_>>()
+    )
+    .execute(&mut *tx))
+    .await?;
+
+    let archive = &backup.archive;
+    timed(sqlx::query!(
+        "INSERT INTO flappy_dragon_score_archive (season_id, player_name, player_score, player_id, avatar, posted_time) SELECT season, name, score, id, avatar, posted AT TIME ZONE 'UTC' FROM UNNEST($1::int[], $2::text[], $3::int[], $4::text[], $5::text[], $6::timestamptz[]) AS rows(season, name, score, id, avatar, posted)",
+        &archive.iter().map(|s| s.season_id).collect::<Vec<_>>(),
+        &archive.iter().map(|s| s.player_name.clone()).collect::<Vec<_>>(),
+        &archive.iter().map(|s| s.player_score).collect::<Vec<_>>(),
+        &archive.iter().map(|s| s.player_id.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &archive.iter().map(|s| s.avatar.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &archive.iter().map(|s| s.posted_time).collect::<Vec<_>>() as &[Option<DateTime<Utc>>]
+    )
+    .execute(&mut *tx))
+    .await?;
+
+    if let Some(notice) = notice {
+        timed(sqlx::query!(
+            "INSERT INTO server_notice (id, message, level) VALUES (1, $1, $2) ON CONFLICT (id) DO UPDATE SET message = EXCLUDED.message, level = EXCLUDED.level",
+            notice.message.as_deref(),
+            notice.level.as_str()
         )
-        .execute(pool)
+        .execute(&mut *tx))
         .await?;
+    }
 
-        sqlx::query!("DELETE FROM flappy_dragon_score WHERE id NOT IN (SELECT id FROM flappy_dragon_score ORDER BY player_score DESC LIMIT 10)")
-            .execute(pool)
-            .await?;
+    timed(tx.commit()).await?;
+    Ok(())
+}
+
+pub async fn get_notice_db(pool: &PgPool) -> Result<Option<Notice>, ServerError> {
+    let row = timed(
+        sqlx::query!("SELECT message, level FROM server_notice WHERE id = 1").fetch_optional(pool),
+    )
+    .await?;
+
+    Ok(row.map(|row| Notice {
+        message: row.message,
+        level: row.level.parse().unwrap_or_else(|e| {
+            tracing::warn!("{}! Stored notice is shown as info", e);
+            NoticeLevel::Info
+        }),
+    }))
+}
+
+// Notice table holds a single row, cleared notice is stored without message
+pub async fn set_notice_db(pool: &PgPool, notice: &Notice) -> Result<(), ServerError> {
+    timed(sqlx::query!(
+        "INSERT INTO server_notice (id, message, level) VALUES (1, $1, $2) ON CONFLICT (id) DO UPDATE SET message = EXCLUDED.message, level = EXCLUDED.level",
+        notice.message.as_deref(),
+        notice.level.as_str()
+    )
+    .execute(pool))
+    .await?;
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct UserRow {
+    pub id: i32,
+    pub argon2_hash: String,
+    pub role: String,
+}
+
+pub async fn get_user_db(pool: &PgPool, username: &str) -> Result<Option<UserRow>, ServerError> {
+    let user = timed(retry_query(retry_policy(), || {
+        sqlx::query_as!(
+            UserRow,
+            "SELECT id, argon2_hash, role FROM users WHERE username = $1",
+            username
+        )
+        .fetch_optional(pool)
+    }))
+    .await?;
+
+    Ok(user)
+}
+
+// Usernames are unique, a taken one is reported as a conflict rather than a database failure
+pub async fn create_user_db(
+    pool: &PgPool,
+    username: &str,
+    argon2_hash: &str,
+) -> Result<i32, ServerError> {
+    let id = timed(async {
+        let inserted = sqlx::query_scalar!(
+            "INSERT INTO users (username, argon2_hash) VALUES ($1, $2) RETURNING id",
+            username,
+            argon2_hash
+        )
+        .fetch_one(pool)
+        .await;
+        match inserted {
+            Err(e)
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                Ok(None)
+            }
+            inserted => inserted.map(Some),
+        }
+    })
+    .await?;
+
+    id.ok_or_else(|| ServerError::Conflict(format!("Username {} is taken", username)))
+}
+
+// Login of "user" with "password", hashed with cheap params so tests don't wait on argon2
+#[cfg(test)]
+pub const TEST_USER_HASH: &str =
+    "$argon2id$v=19$m=8,t=1,p=1$j0cVGVnR9eLHXE/tJ7Ajtg$jNa/0iUyz2rr8fVVmzz0ch2i5mnyGGrjzh+CWKNIrVI";
+
+#[cfg(test)]
+pub async fn seed_test_user(pool: &PgPool) {
+    sqlx::query!(
+        "INSERT INTO users (username, argon2_hash) VALUES ('user', $1) ON CONFLICT (username) DO NOTHING",
+        TEST_USER_HASH
+    )
+    .execute(pool)
+    .await
+    .expect("Can't seed test user!");
+}
+
+#[cfg(test)]
+pub async fn get_test_db_pool() -> PgPool {
+    dotenv().ok();
+    let path = env::var("TEST_DATABASE_URL").expect("Test db path is not found!");
+    PgPool::connect(&path)
+        .await
+        .expect("Cant connect to test DB!")
+}
+
+#[cfg(test)]
+mod db_tests {
+    use super::*;
+    use crate::{
+        config::{DEFAULT_LEADERBOARD_SIZE, DEFAULT_SCORE_FLOOR},
+        game::DEFAULT_GAME,
+    };
+    use serial_test::serial;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_posted_time_is_utc() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        let mut conn = pool.acquire().await.expect("Can't get connection");
+        sqlx::query("SET TIME ZONE 'Asia/Tokyo'")
+            .execute(&mut *conn)
+            .await
+            .expect("Can't set time zone");
+        sqlx::query!(
+            "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ('Tokyo', 1)"
+        )
+        .execute(&mut *conn)
+        .await
+        .expect("Can't add score to test DB!");
+        sqlx::query("RESET TIME ZONE")
+            .execute(&mut *conn)
+            .await
+            .expect("Can't reset time zone");
+        drop(conn);
+
+        let posted = sqlx::query_scalar!(
+            "SELECT posted_time AT TIME ZONE 'UTC' AS \"posted: DateTime<Utc>\" FROM flappy_dragon_score WHERE player_name = 'Tokyo'"
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Can't get posted time!")
+        .expect("Posted time is missing!");
+        assert!(
+            (Utc::now() - posted).num_minutes().abs() < 1,
+            "Posted time {} is not UTC!",
+            posted
+        );
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_dedup_concurrent_submissions() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        let submissions = (1..=10).map(|player_score| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                add_new_score_db(
+                    &pool,
+                    DEFAULT_GAME,
+                    PlayerScore {
+                        id: None,
+                        player_name: "Racer".parse().unwrap(),
+                        player_score,
+                        player_id: None,
+                        avatar: None,
+                        tier: None,
+                        difficulty: None,
+                        play_count: None,
+                    },
+                    true,
+                    true,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE,
+                )
+                .await
+            })
+        });
+        for submission in futures::future::join_all(submissions).await {
+            submission
+                .expect("Task failed")
+                .expect("Can't add score to test DB!");
+        }
+
+        let scores = get_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't get scores from DB!");
+        assert_eq!(scores.len(), 1, "Racer is on the board more than once!");
+        assert_eq!(scores[0].player_score, 10);
+        assert_eq!(scores[0].play_count, Some(10), "Plays are lost!");
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_add_new_score() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        let mut players_vector: Vec<PlayerScore> = vec![];
+
+        for i in 0..12 {
+            let player = PlayerScore {
+                id: None,
+                player_name: "Dull".parse().unwrap(),
+                player_score: i,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            };
+
+            if i > 0 && i <= 10 {
+                players_vector.insert(0, player.clone());
+            };
+
+            add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                player.clone(),
+                false,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .expect("Can't add player to test DB!");
+
+            if i > 0 && i <= 10 {
+                assert_eq!(
+                    players_vector,
+                    without_ids(
+                        get_scores_db(&pool, DEFAULT_GAME)
+                            .await
+                            .expect("Can't get scores from DB!")
+                    ),
+                    "Vectors of players doesn't match!"
+                );
+            }
+        }
+
+        assert!(
+            get_scores_db(&pool, DEFAULT_GAME)
+                .await
+                .expect("Can't get scores from test DB!")
+                .len()
+                == 10,
+            "Final length is more than 10!"
+        );
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        //Let's check if right entries is deleted//
+
+        assert!(
+            get_scores_db(&pool, DEFAULT_GAME)
+                .await
+                .expect("Can't get scores from test DB!")
+                .is_empty()
+        );
+
+        let mut players_vector = vec![];
+
+        for i in 1..11 {
+            let player = PlayerScore {
+                id: None,
+                player_name: "Dull".parse().unwrap(),
+                player_score: i,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            };
+
+            players_vector.insert(0, player.clone());
+            add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                player.clone(),
+                false,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .expect("Can't add new score to test DB!");
+        }
+        assert_eq!(
+            players_vector,
+            without_ids(
+                get_scores_db(&pool, DEFAULT_GAME)
+                    .await
+                    .expect("Can't get scores from DB!")
+            ),
+            "Vectors of players doesn't match!"
+        );
+
+        let db_scores: Vec<PlayerScore> = without_ids(
+            get_scores_db(&pool, DEFAULT_GAME)
+                .await
+                .expect("Can't get scores from test DB!"),
+        );
+
+        let first_db_player = db_scores.first().expect("Can't get first one!").clone();
+        let last_db_player = db_scores.last().expect("Can't get last one!").clone();
+
+        let first_vec_player = players_vector
+            .first()
+            .expect("Can't get first one!")
+            .clone();
+        let last_vec_player = players_vector.last().expect("Can't get last one!").clone();
+
+        assert!(first_vec_player.player_score == 10);
+        assert_eq!(first_db_player, first_vec_player);
+        assert!(last_db_player.player_score == 1);
+        assert_eq!(last_db_player, last_vec_player);
+
+        //Now adding one on top!
+        players_vector.insert(
+            0,
+            PlayerScore {
+                id: None,
+                player_name: "Dull".parse().unwrap(),
+                player_score: 10,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            },
+        );
+
+        add_new_score_db(
+            &pool,
+            DEFAULT_GAME,
+            PlayerScore {
+                id: None,
+                player_name: "Dull".parse().unwrap(),
+                player_score: 10,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            },
+            false,
+            false,
+            None,
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
+        )
+        .await
+        .expect("Can't add score!");
+
+        players_vector.pop();
+
+        let db_scores: Vec<PlayerScore> = without_ids(
+            get_scores_db(&pool, DEFAULT_GAME)
+                .await
+                .expect("Can't get scores from test DB!"),
+        );
+
+        let first_db_player = db_scores.first().expect("Can't get first one!").clone();
+        let second_db_player = db_scores.get(1).expect("Can't get second one!").clone();
+        let last_db_player = db_scores.last().expect("Can't get last one!").clone();
+
+        let first_vec_player = players_vector
+            .first()
+            .expect("Can't get first one!")
+            .clone();
+        let second_vec_player = players_vector
+            .get(1)
+            .expect("Can't get second one!")
+            .clone();
+        let last_vec_player = players_vector.last().expect("Can't get last one!").clone();
+
+        assert!(first_vec_player.player_score == 10);
+        assert_eq!(first_db_player, first_vec_player);
+        assert!(last_db_player.player_score == 2);
+        assert_eq!(last_db_player, last_vec_player);
+        assert!(second_db_player.player_score == 10);
+        assert_eq!(second_db_player, second_vec_player);
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush scores in test DB!");
+    }
+
+    async fn populate_db_with_mock_data(pool: &PgPool, range: std::ops::Range<i32>) {
+        for i in range {
+            sqlx::query!(
+                "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ($1, $2)",
+                "TestMike",
+                i,
+            )
+            .execute(pool)
+            .await
+            .expect("Cant insert test data!");
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_board_size() {
+        let pool = get_test_db_pool().await;
+        let score = |player_score: i32| PlayerScore {
+            id: None,
+            player_name: "Sized".parse().unwrap(),
+            player_score,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        };
+
+        for board_size in [3, 5] {
+            flush_scores_db(&pool, DEFAULT_GAME)
+                .await
+                .expect("Can't flush test db!");
+            for player_score in 1..=8 {
+                add_new_score_db(
+                    &pool,
+                    DEFAULT_GAME,
+                    score(player_score),
+                    false,
+                    false,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    board_size,
+                )
+                .await
+                .expect("Can't add score!");
+            }
+
+            let board = get_scores_db(&pool, DEFAULT_GAME)
+                .await
+                .expect("Can't get scores!");
+            assert_eq!(
+                board.iter().map(|s| s.player_score).collect::<Vec<_>>(),
+                (9 - board_size as i32..=8).rev().collect::<Vec<_>>(),
+                "Board of {} is not trimmed!",
+                board_size
+            );
+            let cutoff = board_cutoff_db(&pool, DEFAULT_GAME, DEFAULT_SCORE_FLOOR, board_size)
+                .await
+                .expect("Can't get cutoff!");
+            assert_eq!(cutoff, 9 - board_size as i32);
+        }
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_delete_players() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        populate_db_with_mock_data(&pool, 1..4).await;
+        add_new_score_db(
+            &pool,
+            DEFAULT_GAME,
+            PlayerScore {
+                id: None,
+                player_name: "Keeper".parse().unwrap(),
+                player_score: 9,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            },
+            false,
+            false,
+            None,
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
+        )
+        .await
+        .expect("Can't add score!");
+
+        let removed = delete_players_db(&pool, &["TestMike".to_string(), "Ghost".to_string()])
+            .await
+            .expect("Can't delete players!");
+
+        assert_eq!(removed, HashMap::from([("TestMike".to_string(), 3)]));
+        let left = get_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't get scores!");
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].player_name.as_str(), "Keeper");
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_stream_scores() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        let board_size = STREAM_BUFFER as i32 * 10;
+        populate_db_with_mock_data(&pool, 0..board_size).await;
+
+        // Counting consumer never holds more than one row
+        let mut stream = stream_scores_db(pool.clone(), DEFAULT_GAME.to_string());
+        let mut count = 0;
+        let mut previous = i32::MAX;
+        while let Some(row) = stream.next().await {
+            let row = row.expect("Can't stream row!");
+            assert!(row.player_score <= previous, "Rows are out of order!");
+            previous = row.player_score;
+            count += 1;
+        }
+        assert_eq!(count, board_size);
+
+        // Slow consumer leaves the query waiting on the full buffer with the connection held,
+        // reading the whole board would have given the connection back to the pool
+        let in_use = |pool: &PgPool| pool.size() as usize - pool.num_idle();
+        let mut stream = stream_scores_db(pool.clone(), DEFAULT_GAME.to_string());
+        let first = stream
+            .next()
+            .await
+            .expect("Stream is empty!")
+            .expect("Can't stream row!");
+        assert_eq!(first.player_score, board_size - 1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(in_use(&pool), 1, "Board is read past the buffer!");
+
+        // Dropping stream early stops the query instead of reading the rest
+        drop(stream);
+        let released = async {
+            while in_use(&pool) > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(1), released)
+            .await
+            .expect("Query is not stopped after stream is dropped!");
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_add_new_score_returns_stored() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        let player = PlayerScore {
+            id: None,
+            player_name: "Echo".parse().unwrap(),
+            player_score: 42,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        };
+        let stored = add_new_score_db(
+            &pool,
+            DEFAULT_GAME,
+            player.clone(),
+            false,
+            false,
+            None,
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
+        )
+        .await
+        .expect("Can't add score!");
+        assert!(
+            stored.as_ref().is_some_and(|s| s.id.is_some()),
+            "Row id is not returned!"
+        );
+        assert_eq!(stored.map(PlayerScore::without_id), Some(player));
+
+        let unworthy = add_new_score_db(
+            &pool,
+            DEFAULT_GAME,
+            PlayerScore {
+                id: None,
+                player_name: "Echo".parse().unwrap(),
+                player_score: 0,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            },
+            false,
+            false,
+            None,
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
+        )
+        .await
+        .expect("Can't add score!");
+        assert_eq!(unworthy, None, "Unworthy score is reported as stored!");
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_dedup_counts_plays() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        let play = |player_score: i32| PlayerScore {
+            id: None,
+            player_name: "Ace".parse().unwrap(),
+            player_score,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        };
+
+        for (player_score, stored) in [(50, true), (30, false), (60, true), (60, false), (0, false)]
+        {
+            let result = add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                play(player_score),
+                true,
+                true,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .expect("Can't add score!");
+            assert_eq!(
+                result.is_some(),
+                stored,
+                "Wrong result for {}",
+                player_score
+            );
+        }
+
+        let scores = get_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't get scores!");
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].player_score, 60, "Best score is not kept!");
+        assert_eq!(scores[0].play_count, Some(5), "Plays are not counted!");
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_dedup_by_player_id() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        let player = |name: &str, score: i32, id: Option<&str>| PlayerScore {
+            id: None,
+            player_name: name.parse().unwrap(),
+            player_score: score,
+            player_id: id.map(str::to_owned),
+            avatar: Some("dragon_red".to_string()),
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        };
+
+        for record in [
+            player("Ace", 5, None),
+            // Same name with an id is a different player
+            player("Ace", 10, Some("id-2")),
+            player("Ace", 20, Some("id-1")),
+            // Renamed player with the same id is still the same player
+            player("Ace Renamed", 30, Some("id-1")),
+        ] {
+            add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                record,
+                true,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .expect("Can't add score!");
+        }
+
+        let lower = add_new_score_db(
+            &pool,
+            DEFAULT_GAME,
+            player("Ace", 25, Some("id-1")),
+            true,
+            false,
+            None,
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
+        )
+        .await
+        .expect("Can't add score!");
+        assert_eq!(lower, None, "Lower score of the same player is stored!");
+
+        assert_eq!(
+            without_ids(
+                get_scores_db(&pool, DEFAULT_GAME)
+                    .await
+                    .expect("Can't get scores!")
+            ),
+            vec![
+                player("Ace Renamed", 30, Some("id-1")),
+                player("Ace", 10, Some("id-2")),
+                player("Ace", 5, None),
+            ]
+        );
+        for (record, expected) in [
+            // Id wins over name, so renamed player keeps the best score
+            (player("Anyone", 1, Some("id-1")), Some(30)),
+            (player("Ace", 1, None), Some(5)),
+            (player("Ace", 1, Some("id-3")), None),
+        ] {
+            let best = player_best_db(&pool, DEFAULT_GAME, &record)
+                .await
+                .expect("Can't get player's best!");
+            assert_eq!(best, expected);
+        }
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_notice_persistence() {
+        let pool = get_test_db_pool().await;
+        let notice = Notice {
+            message: Some("Downtime at 22:00".to_string()),
+            level: NoticeLevel::Warn,
+        };
+
+        set_notice_db(&pool, &notice)
+            .await
+            .expect("Can't store notice!");
+        assert_eq!(
+            get_notice_db(&pool).await.expect("Can't get notice!"),
+            Some(notice)
+        );
+
+        set_notice_db(&pool, &Notice::default())
+            .await
+            .expect("Can't clear notice!");
+        assert_eq!(
+            get_notice_db(&pool).await.expect("Can't get notice!"),
+            Some(Notice::default())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_db_retry_serialization_failure() {
+        let pool = get_test_db_pool().await;
+        let policy = RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(1),
+        };
+
+        let mut calls = 0;
+        let result = retry_query(policy, || {
+            calls += 1;
+            // First call fails the same way a conflicting serializable transaction does
+            let sql = match calls {
+                1 => "DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = '40001'; END $$",
+                _ => "SELECT 1",
+            };
+            sqlx::query(sql).execute(&pool)
+        })
+        .await;
+        assert!(result.is_ok(), "Query is not retried: {:?}", result);
+        assert_eq!(calls, 2);
+
+        let mut calls = 0;
+        let result = retry_query(policy, || {
+            calls += 1;
+            sqlx::query("SELECT * FROM no_such_table").execute(&pool)
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1, "Not retryable error is retried!");
+
+        let mut calls = 0;
+        let result = retry_query(policy, || {
+            calls += 1;
+            sqlx::query("DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = '40001'; END $$")
+                .execute(&pool)
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3, "Retries are not bounded!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_client_version_persisted() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+
+        for (player_score, client_version) in [(5, None), (7, Some("1.4.2-beta"))] {
+            let player = PlayerScore {
+                id: None,
+                player_name: "Tester".parse().unwrap(),
+                player_score,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
+            };
+            add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                player,
+                false,
+                false,
+                client_version,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .expect("Can't add score!");
+        }
+
+        let versions = sqlx::query_scalar!(
+            "SELECT client_version FROM flappy_dragon_score ORDER BY player_score DESC"
+        )
+        .fetch_all(&pool)
+        .await
+        .expect("Can't get versions!");
+        assert_eq!(versions, vec![Some("1.4.2-beta".to_string()), None]);
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_db_connection() {
+        assert!(connect_to_db().await.is_ok());
     }
 
-    Ok(())
-}
-
-#[cfg(test)]
-mod db_tests {
-    use super::*;
-    use serial_test::serial;
+    #[tokio::test]
+    #[serial]
+    async fn test_db_health_check() {
+        let pool = connect_to_db()
+            .await
+            .expect("Can't connect to database - can't get a pool");
+        assert!(health_db(&pool).await.is_ok(), "Health is not Ok");
+    }
 
     #[tokio::test]
     #[serial]
-    async fn test_db_add_new_score() {
+    async fn test_db_flush() {
         let pool = get_test_db_pool().await;
-        flush_scores_db(&pool).await.expect("Can't flush test db!");
-
-        let mut players_vector: Vec<PlayerScore> = vec![];
-
-        for i in 0..12 {
-            let player = PlayerScore {
-                player_name: "Dull".to_string(),
-                player_score: i,
-            };
-
-            if i > 0 && i <= 10 {
-                players_vector.insert(0, player.clone());
-            };
-
-            add_new_score_db(&pool, player.clone())
-                .await
-                .expect("Can't add player to test DB!");
-
-            if i > 0 && i <= 10 {
-                assert_eq!(
-                    players_vector,
-                    get_scores_db(&pool)
-                        .await
-                        .expect("Can't get scores from DB!"),
-                    "Vectors of players doesn't match!"
-                );
-            }
-        }
+        pool.begin().await.expect("Transaction failed!");
 
         assert!(
-            get_scores_db(&pool)
-                .await
-                .expect("Can't get scores from test DB!")
-                .len()
-                == 10,
-            "Final length is more than 10!"
+            flush_scores_db(&pool, DEFAULT_GAME).await.is_ok(),
+            "Flush is not worked out!"
         );
 
-        flush_scores_db(&pool).await.expect("Can't flush test db!");
-
-        //Let's check if right entries is deleted//
+        let scores = get_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Failed to fetch data after flush!");
+        assert!(scores.is_empty(), "Scores are not empty after flush!");
+    }
 
-        assert!(
-            get_scores_db(&pool)
+    #[tokio::test]
+    #[serial]
+    async fn test_db_count_scores() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        assert_eq!(
+            count_scores_db(&pool, DEFAULT_GAME)
                 .await
-                .expect("Can't get scores from test DB!")
-                .is_empty()
+                .expect("Can't count scores!"),
+            0
         );
 
-        let mut players_vector = vec![];
-
-        for i in 1..11 {
-            let player = PlayerScore {
-                player_name: "Dull".to_string(),
-                player_score: i,
-            };
-
-            players_vector.insert(0, player.clone());
-            add_new_score_db(&pool, player.clone())
+        populate_db_with_mock_data(&pool, 1..6).await;
+        assert_eq!(
+            count_scores_db(&pool, DEFAULT_GAME)
                 .await
-                .expect("Can't add new score to test DB!");
-        }
+                .expect("Can't count scores!"),
+            5
+        );
         assert_eq!(
-            players_vector,
-            get_scores_db(&pool)
+            get_scores_db(&pool, DEFAULT_GAME)
                 .await
-                .expect("Can't get scores from DB!"),
-            "Vectors of players doesn't match!"
+                .expect("Can't get scores!")
+                .len(),
+            5,
+            "Counting changed the board!"
         );
 
-        let db_scores: Vec<PlayerScore> = get_scores_db(&pool)
+        flush_scores_db(&pool, DEFAULT_GAME)
             .await
-            .expect("Can't get scores from test DB!");
+            .expect("Can't flush test db!");
+    }
 
-        let first_db_player = db_scores.first().expect("Can't get first one!").clone();
-        let last_db_player = db_scores.last().expect("Can't get last one!").clone();
+    #[tokio::test]
+    async fn test_db_query_timeout() {
+        let pool = get_test_db_pool().await;
+        let limit = Duration::from_millis(50);
 
-        let first_vec_player = players_vector
-            .first()
-            .expect("Can't get first one!")
-            .clone();
-        let last_vec_player = players_vector.last().expect("Can't get last one!").clone();
+        let timed_out = with_timeout(
+            Some(limit),
+            sqlx::query("SELECT pg_sleep(1)").execute(&pool),
+        )
+        .await
+        .expect_err("Slow query is not timed out!");
+        assert!(
+            matches!(&timed_out, ServerError::Database(msg) if msg.contains("timed out")),
+            "Unexpected error: {:?}",
+            timed_out
+        );
 
-        assert!(first_vec_player.player_score == 10);
-        assert_eq!(first_db_player, first_vec_player);
-        assert!(last_db_player.player_score == 1);
-        assert_eq!(last_db_player, last_vec_player);
+        with_timeout(Some(limit), sqlx::query("SELECT 1").execute(&pool))
+            .await
+            .expect("Fast query is timed out!");
+        with_timeout(None, sqlx::query("SELECT pg_sleep(0.1)").execute(&pool))
+            .await
+            .expect("Query is timed out without limit!");
+    }
 
-        //Now adding one on top!
-        players_vector.insert(
-            0,
-            PlayerScore {
-                player_name: "Dull".to_string(),
-                player_score: 10,
-            },
-        );
+    #[tokio::test]
+    async fn test_db_read_only_write() {
+        use axum::{http::StatusCode, response::IntoResponse};
 
-        add_new_score_db(
-            &pool,
-            PlayerScore {
-                player_name: "Dull".to_string(),
-                player_score: 10,
-            },
+        let pool = get_test_db_pool().await;
+        let mut tx = pool.begin().await.expect("Can't begin transaction");
+        sqlx::query("SET TRANSACTION READ ONLY")
+            .execute(&mut *tx)
+            .await
+            .expect("Can't make transaction read-only");
+
+        let refused: ServerError = sqlx::query(
+            "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ('Dragon', 5)",
         )
+        .execute(&mut *tx)
         .await
-        .expect("Can't add score!");
+        .expect_err("Write is accepted by read-only transaction!")
+        .into();
+        assert!(
+            matches!(&refused, ServerError::Unavailable(_)),
+            "Unexpected error: {:?}",
+            refused
+        );
+        assert_eq!(
+            refused.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        tx.rollback().await.expect("Can't roll back");
 
-        players_vector.pop();
+        let mut tx = pool.begin().await.expect("Can't begin transaction");
+        sqlx::query("SET TRANSACTION READ ONLY")
+            .execute(&mut *tx)
+            .await
+            .expect("Can't make transaction read-only");
+        sqlx::query("SELECT COUNT(*) FROM flappy_dragon_score")
+            .execute(&mut *tx)
+            .await
+            .expect("Read is refused by read-only transaction!");
+    }
 
-        let db_scores: Vec<PlayerScore> = get_scores_db(&pool)
+    #[tokio::test]
+    #[serial]
+    async fn test_db_ranked_scores() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
             .await
-            .expect("Can't get scores from test DB!");
+            .expect("Can't flush test db!");
+        for score in [50, 40, 50, 10] {
+            populate_db_with_mock_data(&pool, score..score + 1).await;
+        }
 
-        let first_db_player = db_scores.first().expect("Can't get first one!").clone();
-        let second_db_player = db_scores.get(1).expect("Can't get second one!").clone();
-        let last_db_player = db_scores.last().expect("Can't get last one!").clone();
+        let ranked = get_ranked_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't get ranked scores!");
+        let ranks: Vec<(i64, i32)> = ranked
+            .iter()
+            .map(|score| (score.rank, score.player_score))
+            .collect();
+        assert_eq!(ranks, vec![(1, 50), (1, 50), (3, 40), (4, 10)]);
 
-        let first_vec_player = players_vector
-            .first()
-            .expect("Can't get first one!")
-            .clone();
-        let second_vec_player = players_vector
-            .get(1)
-            .expect("Can't get second one!")
-            .clone();
-        let last_vec_player = players_vector.last().expect("Can't get last one!").clone();
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
 
-        assert!(first_vec_player.player_score == 10);
-        assert_eq!(first_db_player, first_vec_player);
-        assert!(last_db_player.player_score == 2);
-        assert_eq!(last_db_player, last_vec_player);
-        assert!(second_db_player.player_score == 10);
-        assert_eq!(second_db_player, second_vec_player);
+    #[tokio::test]
+    #[serial]
+    async fn test_db_score_rank() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        populate_db_with_mock_data(&pool, 1..6).await;
 
-        flush_scores_db(&pool)
+        let scores = get_scores_db(&pool, DEFAULT_GAME)
             .await
-            .expect("Can't flush scores in test DB!");
+            .expect("Can't get scores!");
+        for (position, score) in scores.iter().enumerate() {
+            let id = score.id.expect("Row id is not returned!");
+            assert_eq!(
+                score_rank_db(&pool, DEFAULT_GAME, id)
+                    .await
+                    .expect("Can't get rank!"),
+                Some(position as i64 + 1)
+            );
+        }
+        assert_eq!(
+            score_rank_db(&pool, DEFAULT_GAME, -1)
+                .await
+                .expect("Can't get rank!"),
+            None
+        );
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
     }
 
-    async fn populate_db_with_mock_data(pool: &PgPool, range: std::ops::Range<i32>) {
-        for i in range {
+    #[tokio::test]
+    #[serial]
+    async fn test_db_archived_scores_paging() {
+        let pool = get_test_db_pool().await;
+        sqlx::query!("TRUNCATE TABLE flappy_dragon_score_archive")
+            .execute(&pool)
+            .await
+            .expect("Can't clear archive!");
+        for (season, score) in (1..=5).map(|i| (7, i)).chain([(8, 100)]) {
             sqlx::query!(
-                "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ($1, $2)",
-                "TestMike",
-                i,
+                "INSERT INTO flappy_dragon_score_archive (season_id, player_name, player_score) VALUES ($1, $2, $3)",
+                season,
+                "Veteran",
+                score,
             )
-            .execute(pool)
+            .execute(&pool)
             .await
-            .expect("Cant insert test data!");
+            .expect("Can't seed archive!");
         }
-    }
 
-    async fn get_test_db_pool() -> PgPool {
-        dotenv().ok();
-        let path = env::var("TEST_DATABASE_URL").expect("Test db path is not found!");
-        PgPool::connect(&path)
+        assert!(
+            season_archived_db(&pool, 7)
+                .await
+                .expect("Can't check season!")
+        );
+        assert!(
+            !season_archived_db(&pool, 9)
+                .await
+                .expect("Can't check season!")
+        );
+
+        let page_scores = |page: Vec<PlayerScore>| -> Vec<i32> {
+            page.into_iter().map(|s| s.player_score).collect()
+        };
+        let first = get_archived_scores_db(&pool, 7, 2, 0)
+            .await
+            .expect("Can't get archive!");
+        let last = get_archived_scores_db(&pool, 7, 2, 4)
+            .await
+            .expect("Can't get archive!");
+        let beyond = get_archived_scores_db(&pool, 7, 2, 6)
             .await
-            .expect("Cant connect to test DB!")
+            .expect("Can't get archive!");
+
+        assert_eq!(page_scores(first), vec![5, 4]);
+        assert_eq!(page_scores(last), vec![1], "Other season leaked into page!");
+        assert!(beyond.is_empty());
+
+        sqlx::query!("TRUNCATE TABLE flappy_dragon_score_archive")
+            .execute(&pool)
+            .await
+            .expect("Can't clear archive!");
     }
 
     #[tokio::test]
     #[serial]
-    async fn test_db_connection() {
-        assert!(connect_to_db().await.is_ok());
+    async fn test_db_get_scores() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Couldn't flush db!");
+        assert!(
+            get_scores_db(&pool, DEFAULT_GAME).await.is_ok(),
+            "Can't get scores!"
+        );
+        assert!(
+            add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                PlayerScore {
+                    id: None,
+                    player_name: "Bobby".parse().unwrap(),
+                    player_score: 50,
+                    player_id: None,
+                    avatar: None,
+                    tier: None,
+                    difficulty: None,
+                    play_count: None,
+                },
+                false,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE
+            )
+            .await
+            .is_ok(),
+            "Can't add new score"
+        );
+
+        let score: Vec<PlayerScore> = get_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't get scores!");
+        assert!(score.len() == 1, "Wrong population!");
     }
 
     #[tokio::test]
     #[serial]
-    async fn test_db_health_check() {
-        let pool = connect_to_db()
+    async fn test_db_get_scores_paginated() {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
             .await
-            .expect("Can't connect to database - can't get a pool");
-        assert!(health_db(&pool).await.is_ok(), "Health is not Ok");
+            .expect("Can't flush db!");
+        // Ties across page borders, so pages have to break them like the full board does
+        for player_score in [7, 9, 7, 9, 7, 5] {
+            populate_db_with_mock_data(&pool, player_score..player_score + 1).await;
+        }
+
+        let board = get_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't get scores!");
+        let page = |limit: i64, offset: i64| {
+            let pool = pool.clone();
+            async move {
+                get_scores_paginated_db(&pool, DEFAULT_GAME, limit, offset)
+                    .await
+                    .expect("Can't get page!")
+            }
+        };
+
+        assert_eq!(page(2, 0).await, board[0..2]);
+        assert_eq!(page(2, 3).await, board[3..5]);
+        assert_eq!(page(2, 5).await, board[5..6], "Last page is not short!");
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush db!");
     }
 
     #[tokio::test]
     #[serial]
-    async fn test_db_flush() {
+    async fn test_db_board_cutoff() {
         let pool = get_test_db_pool().await;
-        pool.begin().await.expect("Transaction failed!");
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush db!");
+        assert_eq!(
+            board_cutoff_db(
+                &pool,
+                DEFAULT_GAME,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE
+            )
+            .await
+            .expect("Can't get cutoff!"),
+            1
+        );
 
-        assert!(
-            flush_scores_db(&pool).await.is_ok(),
-            "Flush is not worked out!"
+        // Under-full board has free places, so its lowest score is not a cutoff yet
+        populate_db_with_mock_data(&pool, 50..55).await;
+        assert_eq!(
+            board_cutoff_db(
+                &pool,
+                DEFAULT_GAME,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE
+            )
+            .await
+            .expect("Can't get cutoff!"),
+            1
         );
 
-        let scores = get_scores_db(&pool)
+        populate_db_with_mock_data(&pool, 55..62).await;
+        assert_eq!(
+            board_cutoff_db(
+                &pool,
+                DEFAULT_GAME,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE
+            )
             .await
-            .expect("Failed to fetch data after flush!");
-        assert!(scores.is_empty(), "Scores are not empty after flush!");
+            .expect("Can't get cutoff!"),
+            52
+        );
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush db!");
     }
 
     #[tokio::test]
     #[serial]
-    async fn test_db_get_scores() {
+    async fn test_db_zero_score_with_zero_floor() {
         let pool = get_test_db_pool().await;
-        flush_scores_db(&pool).await.expect("Couldn't flush db!");
-        assert!(get_scores_db(&pool).await.is_ok(), "Can't get scores!");
-        assert!(
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush db!");
+        let zero = PlayerScore {
+            id: None,
+            player_name: "Zero".parse().unwrap(),
+            player_score: 0,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        };
+
+        assert_eq!(
+            board_cutoff_db(&pool, DEFAULT_GAME, 0, DEFAULT_LEADERBOARD_SIZE)
+                .await
+                .expect("Can't get cutoff!"),
+            0
+        );
+        assert_eq!(
             add_new_score_db(
                 &pool,
-                PlayerScore {
-                    player_name: "Bobby".to_string(),
-                    player_score: 50
-                }
+                DEFAULT_GAME,
+                zero.clone(),
+                false,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE
             )
             .await
-            .is_ok(),
-            "Can't add new score"
+            .expect("Can't add score!"),
+            None,
+            "Zero is stored under default floor!"
+        );
+        assert_eq!(
+            add_new_score_db(
+                &pool,
+                DEFAULT_GAME,
+                zero.clone(),
+                false,
+                false,
+                None,
+                0,
+                DEFAULT_LEADERBOARD_SIZE
+            )
+            .await
+            .expect("Can't add score!")
+            .map(PlayerScore::without_id),
+            Some(zero),
+            "Zero is not stored on empty board under zero floor!"
         );
 
-        let score: Vec<PlayerScore> = get_scores_db(&pool).await.expect("Can't get scores!");
-        assert!(score.len() == 1, "Wrong population!");
+        // Full board still cuts off by its last score
+        populate_db_with_mock_data(&pool, 50..60).await;
+        assert_eq!(
+            board_cutoff_db(&pool, DEFAULT_GAME, 0, DEFAULT_LEADERBOARD_SIZE)
+                .await
+                .expect("Can't get cutoff!"),
+            50
+        );
+
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush db!");
     }
 
     #[tokio::test]
     #[serial]
     async fn test_db_is_worthy() {
         let pool = get_test_db_pool().await;
-        flush_scores_db(&pool).await.expect("Can't flush db!");
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush db!");
         let pre_player_zero = check_if_record_worthy(
             &pool,
+            DEFAULT_GAME,
             &PlayerScore {
-                player_name: "Max".to_string(),
+                id: None,
+                player_name: "Max".parse().unwrap(),
                 player_score: 0,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
             },
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
         )
         .await
         .expect("Cant check DB");
         let pre_player_one = check_if_record_worthy(
             &pool,
+            DEFAULT_GAME,
             &PlayerScore {
-                player_name: "Max".to_string(),
+                id: None,
+                player_name: "Max".parse().unwrap(),
                 player_score: 1,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
             },
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
         )
         .await
         .expect("Cant check DB");
@@ -330,37 +2098,73 @@ mod db_tests {
 
         let first_player = check_if_record_worthy(
             &pool,
+            DEFAULT_GAME,
             &PlayerScore {
-                player_name: "Max".to_string(),
+                id: None,
+                player_name: "Max".parse().unwrap(),
                 player_score: 0,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
             },
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
         )
         .await
         .expect("Cant check DB");
         let second_player = check_if_record_worthy(
             &pool,
+            DEFAULT_GAME,
             &PlayerScore {
-                player_name: "Max".to_string(),
+                id: None,
+                player_name: "Max".parse().unwrap(),
                 player_score: 1,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
             },
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
         )
         .await
         .expect("Cant check DB");
         let third_player = check_if_record_worthy(
             &pool,
+            DEFAULT_GAME,
             &PlayerScore {
-                player_name: "Max".to_string(),
+                id: None,
+                player_name: "Max".parse().unwrap(),
                 player_score: 10,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
             },
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
         )
         .await
         .expect("Cant check DB");
         let fourth_player = check_if_record_worthy(
             &pool,
+            DEFAULT_GAME,
             &PlayerScore {
-                player_name: "Max".to_string(),
+                id: None,
+                player_name: "Max".parse().unwrap(),
                 player_score: 11,
+                player_id: None,
+                avatar: None,
+                tier: None,
+                difficulty: None,
+                play_count: None,
             },
+            DEFAULT_SCORE_FLOOR,
+            DEFAULT_LEADERBOARD_SIZE,
         )
         .await
         .expect("Cant check DB");
@@ -370,7 +2174,7 @@ mod db_tests {
         assert!(fourth_player, "Fourth");
 
         //Clearing up test db after tests
-        flush_scores_db(&pool)
+        flush_scores_db(&pool, DEFAULT_GAME)
             .await
             .expect("Cant flush test database!");
     }
@@ -1,11 +1,23 @@
 use axum::{http::StatusCode, response::IntoResponse};
 use serde_json::json;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ServerError {
     Validation(String),
     Database(String),
     Authentication(String),
+    Unavailable(String),
+    OriginNotAllowed(String),
+    Forbidden(String),
+    HttpsRequired,
+    NotFound(String),
+    TooManyRequests(String),
+    Unprocessable(String),
+    PayloadTooLarge(usize),
+    Gone(String),
+    Conflict(String),
+    // Settings the server can't start with, reported before serving
+    Configuration(String),
 }
 
 impl IntoResponse for ServerError {
@@ -26,13 +38,82 @@ impl IntoResponse for ServerError {
                 json!({"error:": "Internal Server Error", "details:": msg}).to_string(),
             )
                 .into_response(),
+            ServerError::Unavailable(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                json!({"error:": "Service unavailable", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::OriginNotAllowed(origin) => (
+                StatusCode::FORBIDDEN,
+                json!({"error:": "Origin is not allowed", "details:": format!("Origin {} is not permitted by CORS policy", origin)}).to_string(),
+            )
+                .into_response(),
+            ServerError::Forbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                json!({"error:": "Access is denied!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::NotFound(msg) => (
+                StatusCode::NOT_FOUND,
+                json!({"error:": "Resource is not found!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::TooManyRequests(msg) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                json!({"error:": "Too many requests!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::Unprocessable(msg) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({"error:": "Score is rejected!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::PayloadTooLarge(limit) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                json!({"error:": "Payload is too large!", "details:": format!("Body can't be larger than {} bytes", limit)}).to_string(),
+            )
+                .into_response(),
+            ServerError::Gone(msg) => (
+                StatusCode::GONE,
+                json!({"error:": "Resource is gone!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::Conflict(msg) => (
+                StatusCode::CONFLICT,
+                json!({"error:": "Resource already exists!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::Configuration(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({"error:": "Server is misconfigured!", "details:": msg}).to_string(),
+            )
+                .into_response(),
+            ServerError::HttpsRequired => (
+                StatusCode::BAD_REQUEST,
+                json!({"error:": "HTTPS is required", "details:": "Plaintext requests are not accepted, use HTTPS"}).to_string(),
+            )
+                .into_response(),
         }
     }
 }
 
+// SQLSTATE of a write sent to a read-only server, e.g. a replica during failover
+const READ_ONLY_SQL_TRANSACTION: &str = "25006";
+
+// Writes refused by a read-only database are a temporary outage, not a server fault
 impl From<sqlx::error::Error> for ServerError {
     fn from(value: sqlx::error::Error) -> Self {
-        ServerError::Database(value.to_string())
+        let read_only = value
+            .as_database_error()
+            .and_then(|e| e.code())
+            .is_some_and(|code| code == READ_ONLY_SQL_TRANSACTION);
+        match read_only {
+            true => {
+                tracing::warn!("Write is refused by read-only database: {}", value);
+                ServerError::Unavailable("Writes are temporarily unavailable".into())
+            }
+            false => ServerError::Database(value.to_string()),
+        }
     }
 }
 
@@ -42,6 +123,17 @@ impl std::fmt::Display for ServerError {
             ServerError::Validation(msg) => write!(f, "Validation error: {}", msg),
             ServerError::Database(msg) => write!(f, "Database error: {}", msg),
             ServerError::Authentication(msg) => write!(f, "Authentication error: {}", msg),
+            ServerError::Unavailable(msg) => write!(f, "Unavailable error: {}", msg),
+            ServerError::OriginNotAllowed(origin) => write!(f, "Origin is not allowed: {}", origin),
+            ServerError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            ServerError::HttpsRequired => write!(f, "HTTPS is required"),
+            ServerError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            ServerError::TooManyRequests(msg) => write!(f, "Too many requests: {}", msg),
+            ServerError::Unprocessable(msg) => write!(f, "Unprocessable: {}", msg),
+            ServerError::PayloadTooLarge(limit) => write!(f, "Payload is larger than {}", limit),
+            ServerError::Gone(msg) => write!(f, "Gone: {}", msg),
+            ServerError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            ServerError::Configuration(msg) => write!(f, "Configuration error: {}", msg),
         }
     }
 }
@@ -54,6 +146,10 @@ pub enum JwtError {
     InvalidTokenFormat,
     DecodeError(jsonwebtoken::errors::Error),
     _EncodingError(jsonwebtoken::errors::Error),
+    TimestampOverflow,
+    // Refresh token sent to a protected route or access token sent to /refresh
+    WrongTokenType,
+    Revoked,
 }
 
 impl IntoResponse for JwtError {
@@ -78,6 +174,20 @@ impl IntoResponse for JwtError {
                 (StatusCode::UNAUTHORIZED,
                 json!({"error:": "Validation failed!", "details:": "Invalid or expired token"}).to_string())
             }
+            JwtError::WrongTokenType => (
+                StatusCode::UNAUTHORIZED,
+                json!({"error:": "Validation failed!", "details:": "Token can't be used here"})
+                    .to_string(),
+            ),
+            JwtError::Revoked => (
+                StatusCode::UNAUTHORIZED,
+                json!({"error:": "Validation failed!", "details:": "Token is revoked"}).to_string(),
+            ),
+            JwtError::TimestampOverflow => {
+                tracing::error!("JWT expiration timestamp is out of range!");
+                (StatusCode::INTERNAL_SERVER_ERROR,
+                json!({"error:": "Internal Server Error", "details:": "Can't compute token expiration"}).to_string())
+            }
         };
 
         (status, message).into_response()
@@ -93,6 +203,9 @@ impl std::fmt::Display for JwtError {
             }
             JwtError::DecodeError(_) => write!(f, "Invalid or expired token"),
             JwtError::_EncodingError(_) => write!(f, "Invalid or expired token"),
+            JwtError::TimestampOverflow => write!(f, "Can't compute token expiration"),
+            JwtError::WrongTokenType => write!(f, "Token can't be used here"),
+            JwtError::Revoked => write!(f, "Token is revoked"),
         }
     }
 }
@@ -0,0 +1,274 @@
+use std::io::Write;
+
+use axum::http::HeaderMap;
+use flate2::{Compression, write::GzEncoder};
+use futures::{StreamExt, stream::BoxStream};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::db_access::PlayerScore;
+
+#[derive(Deserialize, Default)]
+pub struct ExportQuery {
+    // Replacing player names with salted hashes, raw names are exported by default
+    #[serde(default)]
+    pub anonymize: bool,
+    pub compress: Option<ExportCompression>,
+    // Trailing line with SHA-256 of everything above it, missing line means a cut download
+    #[serde(default)]
+    pub checksum: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportCompression {
+    Gzip,
+}
+
+impl ExportQuery {
+    // Explicit query parameter wins, Accept-Encoding is honored otherwise
+    pub fn gzip(&self, headers: &HeaderMap) -> bool {
+        self.compress == Some(ExportCompression::Gzip) || accepts_gzip(headers)
+    }
+}
+
+// Gzip listed without q=0, wildcards are not taken as a request for compression
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(axum::http::header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let is_gzip = params
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("gzip"));
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            is_gzip && !refused
+        })
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ExportRow {
+    pub player_name: String,
+    pub player_score: i32,
+}
+
+impl ExportRow {
+    pub fn from_score(score: &PlayerScore, salt: Option<&str>) -> Self {
+        ExportRow {
+            player_name: match salt {
+                Some(salt) => anonymize_name(score.player_name.as_str(), salt),
+                None => score.player_name.to_string(),
+            },
+            player_score: score.player_score,
+        }
+    }
+}
+
+// HMAC keeps hashes stable between exports with the same salt, while short names
+// can't be brute-forced back without knowing the salt
+pub fn anonymize_name(name: &str, salt: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(name.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub const CSV_HEADER: &str = "player_name,player_score\n";
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "scores.csv",
+            ExportFormat::Ndjson => "scores.ndjson",
+        }
+    }
+
+    pub fn header(self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Csv => Some(CSV_HEADER),
+            ExportFormat::Ndjson => None,
+        }
+    }
+
+    pub fn line(self, row: &ExportRow) -> String {
+        match self {
+            ExportFormat::Csv => csv_line(row),
+            ExportFormat::Ndjson => ndjson_line(row),
+        }
+    }
+
+    // Comment in CSV, so it is not mistaken for a row by readers which skip comments
+    pub fn checksum_line(self, digest: &str) -> String {
+        match self {
+            ExportFormat::Csv => format!("# sha256:{}\n", digest),
+            ExportFormat::Ndjson => format!("{}\n", json!({"sha256": digest})),
+        }
+    }
+}
+
+// Rows stored before the name rules can hold anything, so names are escaped as in RFC 4180
+pub fn csv_line(row: &ExportRow) -> String {
+    format!("{},{}\n", csv_field(&row.player_name), row.player_score)
+}
+
+// Quoted only when needed, so regular names are exported as they are. Field starting like
+// a formula is prefixed with an apostrophe, so spreadsheets show it as text. Leading '#'
+// is quoted too, so the row is not taken for the checksum comment
+fn csv_field(field: &str) -> String {
+    let formula = field.starts_with(['=', '+', '-', '@', '\t', '\r']);
+    if !formula && !field.starts_with('#') && !field.contains([',', '"', '\n', '\r']) {
+        return field.to_string();
+    }
+    let prefix = if formula { "'" } else { "" };
+    format!("\"{}{}\"", prefix, field.replace('"', "\"\""))
+}
+
+// Single NDJSON line with trailing newline, so rows can be streamed one by one
+pub fn ndjson_line(row: &ExportRow) -> String {
+    let mut line =
+        json!({"player_name": row.player_name, "player_score": row.player_score}).to_string();
+    line.push('\n');
+    line
+}
+
+// Hashing lines as they are sent and appending the digest once the last one is out.
+// Hash covers the uncompressed lines, as they are after gzip is undone by the client
+pub fn checksum_stream<E: Send + 'static>(
+    lines: BoxStream<'static, Result<String, E>>,
+    format: ExportFormat,
+) -> BoxStream<'static, Result<String, E>> {
+    futures::stream::unfold(Some((lines, Sha256::new())), move |state| async move {
+        let (mut lines, mut hasher) = state?;
+        match lines.next().await {
+            Some(Ok(line)) => {
+                hasher.update(line.as_bytes());
+                Some((Ok(line), Some((lines, hasher))))
+            }
+            Some(Err(e)) => Some((Err(e), None)),
+            None => {
+                let digest = hex::encode(hasher.finalize());
+                Some((Ok(format.checksum_line(&digest)), None))
+            }
+        }
+    })
+    .boxed()
+}
+
+// Compressing lines as they come, so memory stays flat for any size of export.
+// Encoder holds back what it can't compress yet, chunks are sent once it lets them out
+pub fn gzip_stream<E: Send + 'static>(
+    lines: BoxStream<'static, Result<String, E>>,
+) -> BoxStream<'static, Result<Vec<u8>, E>> {
+    let encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+    futures::stream::unfold(Some((lines, encoder)), |state| async move {
+        let (mut lines, mut encoder) = state?;
+        loop {
+            match lines.next().await {
+                Some(Ok(line)) => {
+                    encoder
+                        .write_all(line.as_bytes())
+                        .expect("Writing into memory can't fail");
+                    let chunk = std::mem::take(encoder.get_mut());
+                    if !chunk.is_empty() {
+                        return Some((Ok(chunk), Some((lines, encoder))));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => {
+                    let tail = encoder.finish().expect("Writing into memory can't fail");
+                    return Some((Ok(tail), None));
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_anonymize_name() {
+        let dragon = anonymize_name("Dragon", "salt");
+
+        assert_eq!(
+            dragon,
+            anonymize_name("Dragon", "salt"),
+            "Hash is not stable!"
+        );
+        assert_ne!(dragon, anonymize_name("Wyvern", "salt"));
+        assert_ne!(
+            dragon,
+            anonymize_name("Dragon", "pepper"),
+            "Salt is ignored!"
+        );
+        assert_eq!(dragon.len(), 64);
+        assert!(!dragon.contains("Dragon"));
+    }
+
+    #[tokio::test]
+    async fn test_export_formats() {
+        let rows = [
+            ExportRow {
+                player_name: "Big Dragon".to_string(),
+                player_score: 42,
+            },
+            ExportRow {
+                player_name: "Wyvern".to_string(),
+                player_score: 7,
+            },
+        ];
+
+        assert_eq!(
+            CSV_HEADER.to_string() + &rows.iter().map(csv_line).collect::<String>(),
+            "player_name,player_score\nBig Dragon,42\nWyvern,7\n"
+        );
+        assert_eq!(
+            rows.iter().map(ndjson_line).collect::<String>(),
+            "{\"player_name\":\"Big Dragon\",\"player_score\":42}\n{\"player_name\":\"Wyvern\",\"player_score\":7}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csv_escaping() {
+        let line = |name: &str| {
+            csv_line(&ExportRow {
+                player_name: name.to_string(),
+                player_score: 1,
+            })
+        };
+
+        assert_eq!(line("Dragon"), "Dragon,1\n");
+        assert_eq!(line("Smith, John"), "\"Smith, John\",1\n");
+        assert_eq!(line("The \"Dragon\""), "\"The \"\"Dragon\"\"\",1\n");
+        assert_eq!(line("Two\nlines"), "\"Two\nlines\",1\n");
+        assert_eq!(line("=1+1"), "\"'=1+1\",1\n");
+        assert_eq!(line("@SUM(A1)"), "\"'@SUM(A1)\",1\n");
+        assert_eq!(line("#1 Dragon"), "\"#1 Dragon\",1\n");
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::ServerError;
+
+// Board of the routes without a game id, rows stored before boards of several games are on it
+pub const DEFAULT_GAME: &str = "default";
+
+pub const GAME_ID_MAX_LEN: usize = 32;
+
+// Id of a game with its own board, 1 to 32 of lowercase ASCII letters, digits and underscores
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameId(String);
+
+impl GameId {
+    pub fn try_new(raw: &str) -> Result<Self, ServerError> {
+        let valid = (1..=GAME_ID_MAX_LEN).contains(&raw.len())
+            && raw
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(ServerError::Validation(format!(
+                "Game id must be 1 to {} lowercase letters, digits or underscores",
+                GAME_ID_MAX_LEN
+            )));
+        }
+        Ok(GameId(raw.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for GameId {
+    fn default() -> Self {
+        GameId(DEFAULT_GAME.to_string())
+    }
+}
+
+impl FromStr for GameId {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GameId::try_new(s)
+    }
+}
+
+impl fmt::Display for GameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod game_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_game_id_rules() {
+        for valid in ["default", "flappy_2", "a", &"x".repeat(GAME_ID_MAX_LEN)] {
+            assert_eq!(
+                GameId::try_new(valid)
+                    .expect("Valid game id is refused!")
+                    .as_str(),
+                valid
+            );
+        }
+        for invalid in [
+            "",
+            "Flappy",
+            "flappy-dragon",
+            "dragon game",
+            "ünicode",
+            &"x".repeat(33),
+        ] {
+            assert!(
+                GameId::try_new(invalid).is_err(),
+                "Invalid game id {:?} is accepted!",
+                invalid
+            );
+        }
+    }
+}
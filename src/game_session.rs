@@ -0,0 +1,197 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::http::HeaderName;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::error::ServerError;
+
+pub const GAME_SESSION_HEADER: HeaderName = HeaderName::from_static("x-game-session");
+
+// Keeps session signatures apart from anything else signed with the same secret
+const SESSION_DOMAIN: &[u8] = b"game-session";
+
+// Token is "<issued unix secs>.<nonce hex>.<hmac hex>". Only nonces of used tokens are
+// stored on the server, so a token signed with a rotated secret is refused like a forged one.
+// It is signed with the HS256 secret, which is rotated every 24h without JWT_PASSPHRASE,
+// so a game played across the rotation can't be submitted
+pub fn issue_session(secret: &str, now: DateTime<Utc>) -> String {
+    let mut nonce = [0u8; 16];
+    rand::rng().fill_bytes(&mut nonce);
+    let payload = format!("{}.{}", now.timestamp(), hex::encode(nonce));
+    let signature = hex::encode(session_mac(secret, &payload).finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+// Nonces of sessions a score was accepted with, kept until the session expires
+#[derive(Debug, Default)]
+pub struct UsedSessions {
+    nonces: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl UsedSessions {
+    // Returns false when the nonce is already used. Expired nonces are dropped,
+    // their tokens are refused anyway
+    fn claim(&self, nonce: &str, expires: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let mut nonces = self.nonces.lock().expect("Used sessions are poisoned!");
+
+        nonces.retain(|_, expires| *expires >= now);
+        if nonces.contains_key(nonce) {
+            return false;
+        }
+        nonces.insert(nonce.to_owned(), expires);
+        true
+    }
+
+    // Gives the session back when its score is not stored, so the run can still be submitted
+    pub fn release(&self, nonce: &str) {
+        self.nonces
+            .lock()
+            .expect("Used sessions are poisoned!")
+            .remove(nonce);
+    }
+}
+
+// Score must be reachable in the time since the session was issued, at most
+// max_score_per_sec points per second of play. Each session is good for one score only,
+// its claimed nonce is returned to be released if the score is not stored
+pub fn verify_session(
+    secret: &str,
+    used: &UsedSessions,
+    token: &str,
+    now: DateTime<Utc>,
+    ttl: Duration,
+    score: i32,
+    max_score_per_sec: u32,
+) -> Result<String, ServerError> {
+    let invalid = || ServerError::Forbidden("Game session is invalid".into());
+
+    let (payload, signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+    let signature = hex::decode(signature).map_err(|_| invalid())?;
+    session_mac(secret, payload)
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+
+    let (issued, nonce) = payload.split_once('.').ok_or_else(invalid)?;
+    let issued = issued
+        .parse::<i64>()
+        .ok()
+        .and_then(|issued| DateTime::from_timestamp(issued, 0))
+        .ok_or_else(invalid)?;
+    let elapsed = now - issued;
+    if elapsed > ttl {
+        return Err(ServerError::Forbidden("Game session is expired".into()));
+    }
+
+    let reachable = elapsed.num_seconds().max(0) * i64::from(max_score_per_sec);
+    if i64::from(score) > reachable {
+        return Err(ServerError::Unprocessable(format!(
+            "Score {} can't be reached in {}s of play",
+            score,
+            elapsed.num_seconds()
+        )));
+    }
+
+    if !used.claim(nonce, issued + ttl, now) {
+        return Err(ServerError::Forbidden(
+            "Game session is already used".into(),
+        ));
+    }
+    Ok(nonce.to_owned())
+}
+
+fn session_mac(secret: &str, payload: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(SESSION_DOMAIN);
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod game_session_tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret-which-is-long-enough!";
+    const TTL: Duration = Duration::hours(1);
+
+    fn issued_at() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_session() {
+        let token = issue_session(SECRET, issued_at());
+        let now = issued_at() + Duration::seconds(30);
+
+        verify_session(SECRET, &UsedSessions::default(), &token, now, TTL, 60, 2)
+            .expect("Valid session is refused!");
+        let too_fast = verify_session(SECRET, &UsedSessions::default(), &token, now, TTL, 61, 2)
+            .expect_err("Implausible score is accepted!");
+        assert!(matches!(too_fast, ServerError::Unprocessable(_)));
+        assert_ne!(
+            token,
+            issue_session(SECRET, issued_at()),
+            "Nonce is reused!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replayed_session() {
+        let used = UsedSessions::default();
+        let token = issue_session(SECRET, issued_at());
+        let now = issued_at() + Duration::seconds(30);
+
+        let nonce = verify_session(SECRET, &used, &token, now, TTL, 60, 2)
+            .expect("Valid session is refused!");
+        let replayed = verify_session(SECRET, &used, &token, now, TTL, 10, 2)
+            .expect_err("Replayed session is accepted!");
+        assert!(matches!(replayed, ServerError::Forbidden(msg) if msg.contains("used")));
+
+        used.release(&nonce);
+        verify_session(SECRET, &used, &token, now, TTL, 60, 2)
+            .expect("Released session is refused!");
+
+        let other = issue_session(SECRET, issued_at());
+        verify_session(SECRET, &used, &other, now, TTL, 60, 2).expect("Other session is refused!");
+    }
+
+    #[tokio::test]
+    async fn test_expired_session() {
+        let token = issue_session(SECRET, issued_at());
+        let now = issued_at() + TTL + Duration::seconds(1);
+
+        let expired = verify_session(SECRET, &UsedSessions::default(), &token, now, TTL, 1, 2)
+            .expect_err("Expired session is accepted!");
+        assert!(matches!(expired, ServerError::Forbidden(msg) if msg.contains("expired")));
+    }
+
+    #[tokio::test]
+    async fn test_forged_session() {
+        let now = issued_at() + Duration::seconds(30);
+        let token = issue_session(SECRET, issued_at());
+
+        // Backdated issue time with the original signature
+        let (payload, signature) = token.rsplit_once('.').unwrap();
+        let (_, nonce) = payload.split_once('.').unwrap();
+        let backdated = format!("{}.{}.{}", issued_at().timestamp() - 3000, nonce, signature);
+
+        for forged in [
+            backdated,
+            issue_session("another-secret-which-is-long-enough", issued_at()),
+            "garbage".to_string(),
+            format!("{}.zz", payload),
+        ] {
+            let refused = verify_session(SECRET, &UsedSessions::default(), &forged, now, TTL, 1, 2)
+                .expect_err("Forged session is accepted!");
+            assert!(
+                matches!(refused, ServerError::Forbidden(ref msg) if msg.contains("invalid")),
+                "Unexpected error for {}: {:?}",
+                forged,
+                refused
+            );
+        }
+    }
+}
@@ -1,18 +1,42 @@
 use crate::{
     RealTime,
-    db_access::{PlayerScore, add_new_score_db, flush_scores_db, get_scores_db, health_db},
+    audit::AuditEvent,
+    backup::{BACKUP_VERSION, Backup, LEGACY_BACKUP_VERSION},
+    board_page::render_board,
+    config::{AppConfig, AuthMode},
+    db_access::{
+        PlayerScore, RankedPlayerScore, backup_tables_db, restore_tables_db, set_notice_db,
+    },
     error::ServerError,
-    security::{generate_jwt, validate_user},
+    export::{ExportFormat, ExportQuery, ExportRow, checksum_stream, gzip_stream},
+    game::GameId,
+    game_session::{GAME_SESSION_HEADER, issue_session, verify_session},
+    health::{ServiceHealth, collect_health, collect_readiness},
+    notice::Notice,
+    player_name::PlayerName,
+    sanitize::Sanitized,
+    score_schema::score_schema,
+    security::{
+        AuthSubject, AuthTokenId, Introspection, SCORE_SIGNATURE_HEADER, TimeProvider,
+        ValidationSettings, ValidationUpdate, auth_cookie, extract_token, generate_jwt_pair,
+        introspect_token, refresh_access_token, register_user, revoke_refresh_token, token_key,
+        validate_user, verify_score_signature,
+    },
     state::AppState,
+    timestamp::rfc3339,
 };
 use axum::{
-    Json,
-    extract::State,
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderName, StatusCode, header},
+    response::{Html, IntoResponse, Response},
 };
+use chrono::{DateTime, Datelike, NaiveTime, Utc};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
+use std::collections::HashMap;
 use validator::Validate;
 
 #[derive(Deserialize)]
@@ -21,71 +45,845 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+pub const PASSWORD_MIN_LEN: u64 = 8;
+
+#[derive(Deserialize, Validate)]
+pub struct RegisterRequest {
+    #[validate(length(min = 3, max = 20), custom(function = "alphanumeric"))]
+    pub username: String,
+    #[validate(length(min = PASSWORD_MIN_LEN))]
+    pub password: String,
+}
+
+fn alphanumeric(username: &str) -> Result<(), validator::ValidationError> {
+    match username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        true => Ok(()),
+        false => Err(validator::ValidationError::new("alphanumeric")),
+    }
+}
+
 #[derive(Serialize)]
 pub struct LoginResponse {
     pub token: String,
+    #[serde(with = "rfc3339")]
+    pub expires_at: DateTime<Utc>,
+    // Sent in the body in both auth modes, clients keep it until they call /refresh
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct RefreshResponse {
+    pub token: String,
+    #[serde(with = "rfc3339")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct IntrospectRequest {
+    pub token: String,
+}
+
+// Upper limit of tokens checked by one batch validation
+pub const VALIDATE_BATCH_MAX: usize = 100;
+
+#[derive(Deserialize)]
+pub struct ValidateBatchRequest {
+    pub tokens: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ValidateBatchResponse {
+    // Same order as tokens in the request
+    pub results: Vec<Introspection>,
+}
+
+// Upper limit of players removed by one request
+pub const DELETE_MAX_NAMES: usize = 100;
+
+#[derive(Deserialize)]
+pub struct DeletePlayersRequest {
+    pub names: Vec<PlayerName>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ScoresQuery {
+    // Board version the client already has, matching one gets 304
+    pub since_version: Option<u64>,
+    // Season the client believes is live, checked only with ARCHIVED_SEASON_GONE
+    pub season: Option<i32>,
+    // Indented body for reading by hand, compact otherwise
+    #[serde(default)]
+    pub pretty: bool,
+    // Only scores played at the difficulty, all scores without it
+    pub difficulty: Option<String>,
+    // Page of the board, whole board when neither is given
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    // Only scores posted within the period, all-time board without it
+    #[serde(default)]
+    pub period: Period,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Daily,
+    Weekly,
+    #[default]
+    All,
+}
+
+impl Period {
+    // Periods follow UTC calendar - day from midnight, week from Monday midnight.
+    // Board keeps only its top places of all time, so a period shows those posted within it
+    pub fn since(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = now.date_naive();
+        let start = match self {
+            Period::Daily => today,
+            Period::Weekly => {
+                today - chrono::Duration::days(today.weekday().num_days_from_monday().into())
+            }
+            Period::All => return None,
+        };
+        Some(start.and_time(NaiveTime::MIN).and_utc())
+    }
+}
+
+// JSON body like axum's Json, but it can be indented on request for debugging
+pub struct FormattedJson<T> {
+    pub value: T,
+    pub pretty: bool,
+}
+
+impl<T: Serialize> IntoResponse for FormattedJson<T> {
+    fn into_response(self) -> Response {
+        let body = match self.pretty {
+            true => serde_json::to_string_pretty(&self.value),
+            false => serde_json::to_string(&self.value),
+        };
+        match body {
+            Ok(body) => ([(header::CONTENT_TYPE, "application/json")], body).into_response(),
+            Err(e) => {
+                tracing::error!("Can't serialize response body: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            }
+        }
+    }
+}
+
+// Score as sent to clients. Row id and links are kept only with EXPOSE_SCORE_IDS
+#[derive(Serialize, Debug)]
+pub struct ScoreEntry {
+    #[serde(flatten)]
+    pub score: PlayerScore,
+    #[serde(rename = "_links", skip_serializing_if = "Option::is_none")]
+    pub links: Option<ScoreLinks>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScoreLinks {
+    pub rank: String,
+}
+
+impl ScoreEntry {
+    pub fn new(score: PlayerScore, expose_ids: bool) -> Self {
+        match (expose_ids, score.id) {
+            (true, Some(id)) => ScoreEntry {
+                links: Some(ScoreLinks {
+                    rank: format!("/api/scores/{}/rank", id),
+                }),
+                score,
+            },
+            _ => ScoreEntry {
+                score: PlayerScore { id: None, ..score },
+                links: None,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct FlushQuery {
+    // Reporting how many scores would be deleted without deleting them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    #[serde(default = "default_page_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+impl Default for PageQuery {
+    fn default() -> Self {
+        PageQuery {
+            limit: default_page_limit(),
+            offset: 0,
+        }
+    }
+}
+
+pub const PAGE_MAX_LIMIT: i64 = 100;
+
+// One board per page by default
+fn default_page_limit() -> i64 {
+    10
+}
+
+pub const CLIENT_VERSION_HEADER: HeaderName = HeaderName::from_static("x-client-version");
+pub const CLIENT_VERSION_MAX_LEN: usize = 32;
+
+// Version of the game build is optional, but when sent it has to be short and printable
+fn client_version(headers: &HeaderMap) -> Result<Option<&str>, ServerError> {
+    let Some(value) = headers.get(CLIENT_VERSION_HEADER) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|version| !version.is_empty() && version.len() <= CLIENT_VERSION_MAX_LEN)
+        .map(Some)
+        .ok_or_else(|| {
+            ServerError::Validation(format!(
+                "{} must be from 1 to {} printable characters",
+                CLIENT_VERSION_HEADER, CLIENT_VERSION_MAX_LEN
+            ))
+        })
 }
 
+pub const BOARD_VERSION_HEADER: HeaderName = HeaderName::from_static("x-board-version");
+
 /////////////////////////////////// HANDLERS ///////////////////////////////////
 
 pub async fn handler_404() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "Resource is not found!").into_response()
 }
 
-pub async fn health_check(State(state): State<AppState>) -> Json<Value> {
-    let db_health: String = health_db(&state.pool)
-        .await
-        .map_or("DOWN".into(), |_| "OK".into());
+pub async fn health_check(State(state): State<AppState>) -> ServiceHealth {
+    collect_health(&state).await
+}
+
+// Process is up and serving, nothing else is checked
+pub async fn liveness_check() -> Json<Value> {
+    Json(json!({"status": "OK"}))
+}
 
-    Json(json!({"status": "OK",
-    "services": {
-        "server": "OK",
-        "database": db_health
-    }}))
+pub async fn readiness_check(State(state): State<AppState>) -> ServiceHealth {
+    collect_readiness(&state).await
 }
 
 pub async fn login(
     State(state): State<AppState>,
-    Json(credentials): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, Response> {
-    let user = validate_user(&credentials.username, &credentials.password)
+    Sanitized(credentials): Sanitized<LoginRequest>,
+) -> Result<Response, Response> {
+    let user = validate_user(&state.pool, &credentials.username, &credentials.password)
         .await
         .map_err(|e| {
-            tracing::warn!("User is not validated!");
-            (ServerError::Authentication(e.to_string())).into_response()
+            tracing::warn!("User is not validated: {:?}", e);
+            ServerError::from(e).into_response()
         })?;
 
-    let secret = &state.jwt_config.read().await.secret;
-    let temp_role = "default";
-    let token = generate_jwt(&user.id, secret, temp_role, &RealTime).map_err(|e| {
-        tracing::warn!("Can't generate JWT Token!");
-        (ServerError::Authentication(e.to_string())).into_response()
+    let jwt_config = state.jwt_config.read().await;
+    let ttl = jwt_config.access_token_ttl;
+    // Taken before the token, so the reported expiry is never later than the real one
+    let expires_at = RealTime.now() + ttl;
+    let (token, refresh_token) = generate_jwt_pair(&user.id, &jwt_config, &user.role, &RealTime)
+        .map_err(|e| {
+            tracing::warn!("Can't generate JWT Token!");
+            e.into_response()
+        })?;
+    drop(jwt_config);
+
+    // Browser clients get token in HttpOnly cookie, so scripts can't read it
+    let cookie = match state.config.auth_mode {
+        AuthMode::Cookie => Some([(header::SET_COOKIE, auth_cookie(&token, ttl))]),
+        AuthMode::Header => None,
+    };
+
+    Ok((
+        cookie,
+        Json(LoginResponse {
+            token,
+            expires_at,
+            refresh_token,
+        }),
+    )
+        .into_response())
+}
+
+pub async fn refresh(
+    State(state): State<AppState>,
+    Sanitized(request): Sanitized<RefreshRequest>,
+) -> Result<Response, Response> {
+    let jwt_config = state.jwt_config.read().await;
+    let ttl = jwt_config.access_token_ttl;
+    let expires_at = RealTime.now() + ttl;
+    let token = refresh_access_token(
+        &jwt_config,
+        &state.revoked_tokens,
+        request.refresh_token.trim(),
+        &RealTime,
+    )
+    .map_err(|e| {
+        tracing::warn!("Token is not refreshed: {}", e);
+        e.into_response()
     })?;
+    drop(jwt_config);
+
+    let cookie = match state.config.auth_mode {
+        AuthMode::Cookie => Some([(header::SET_COOKIE, auth_cookie(&token, ttl))]),
+        AuthMode::Header => None,
+    };
+
+    Ok((cookie, Json(RefreshResponse { token, expires_at })).into_response())
+}
+
+pub async fn register(
+    State(state): State<AppState>,
+    Sanitized(request): Sanitized<RegisterRequest>,
+) -> Result<(StatusCode, Json<Value>), Response> {
+    if let Err(e) = request.validate() {
+        tracing::warn!("Registration is rejected!");
+        return Err(ServerError::Validation(format!(
+            "{} - Fields errors: {:?}",
+            e,
+            e.field_errors()
+        ))
+        .into_response());
+    }
+
+    register_user(&state.pool, &request.username, &request.password)
+        .await
+        .map_err(|e| {
+            tracing::warn!("Can't register user: {}", e);
+            e.into_response()
+        })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"username": request.username})),
+    ))
+}
+
+// Revokes the token the request is made with and the refresh token sent in the body,
+// other tokens of the subject stay valid. Body is optional, empty one revokes only the first
+pub async fn logout(
+    State(state): State<AppState>,
+    Extension(AuthSubject(subject)): Extension<AuthSubject>,
+    Extension(AuthTokenId { jti, exp }): Extension<AuthTokenId>,
+    body: Bytes,
+) -> Result<StatusCode, Response> {
+    let Some(jti) = jti else {
+        return Err(
+            ServerError::Validation("Token has no id and can't be revoked".into()).into_response(),
+        );
+    };
+
+    if !body.is_empty() {
+        let request: LogoutRequest = serde_json::from_slice(&body).map_err(|e| {
+            ServerError::Validation(format!("Invalid logout body: {}", e)).into_response()
+        })?;
+        let jwt_config = state.jwt_config.read().await;
+        revoke_refresh_token(
+            &jwt_config,
+            &state.revoked_tokens,
+            request.refresh_token.trim(),
+            &subject,
+            &RealTime,
+        )
+        .map_err(|e| {
+            tracing::warn!("Refresh token is not revoked: {}", e);
+            e.into_response()
+        })?;
+    }
+    state.revoked_tokens.revoke(jti, exp, &RealTime);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn introspect(
+    State(state): State<AppState>,
+    Json(request): Json<IntrospectRequest>,
+) -> Json<Value> {
+    let jwt_config = state.jwt_config.read().await;
+    Json(json!(introspect_token(
+        &jwt_config,
+        &state.revoked_tokens,
+        request.token.trim()
+    )))
+}
 
-    Ok(Json(LoginResponse { token }))
+// Whole batch is checked under one read guard, so a rotation can't split it between secrets
+pub async fn validate_batch(
+    State(state): State<AppState>,
+    Json(request): Json<ValidateBatchRequest>,
+) -> Result<Json<ValidateBatchResponse>, Response> {
+    if request.tokens.len() > VALIDATE_BATCH_MAX {
+        return Err(ServerError::Validation(format!(
+            "At most {} tokens can be validated at once",
+            VALIDATE_BATCH_MAX
+        ))
+        .into_response());
+    }
+
+    let jwt_config = state.jwt_config.read().await;
+    let results = request
+        .tokens
+        .iter()
+        .map(|token| introspect_token(&jwt_config, &state.revoked_tokens, token.trim()))
+        .collect();
+
+    Ok(Json(ValidateBatchResponse { results }))
+}
+
+fn scores_cache_control(config: &AppConfig) -> String {
+    match config.scores_max_age_secs {
+        0 => "no-store".to_string(),
+        max_age => format!(
+            "public, max-age={}, stale-while-revalidate={}",
+            max_age, config.scores_stale_secs
+        ),
+    }
+}
+
+pub async fn get_scores(
+    State(state): State<AppState>,
+    Query(query): Query<ScoresQuery>,
+) -> Result<Response, Response> {
+    get_scores_at(state, query, RealTime).await
 }
 
-pub async fn get_scores(State(state): State<AppState>) -> Result<Json<Vec<PlayerScore>>, Response> {
-    get_scores_db(&state.pool).await.map(Json).map_err(|e| {
+// Clock is passed in, so tests can read period boards at any moment
+async fn get_scores_at(
+    state: AppState,
+    query: ScoresQuery,
+    time: impl TimeProvider,
+) -> Result<Response, Response> {
+    // Version is taken before the query, so a concurrent write makes it only older, never newer.
+    // Replica lag is not covered, see AppState::board_version
+    let version = state.board_version();
+    let board_headers = [
+        (BOARD_VERSION_HEADER, version.to_string()),
+        (header::CACHE_CONTROL, scores_cache_control(&state.config)),
+    ];
+
+    if let Some(season) = query.season {
+        check_season_live(&state, season)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    if query.since_version == Some(version) {
+        return Ok((StatusCode::NOT_MODIFIED, board_headers).into_response());
+    }
+
+    let page = (query.limit.is_some() || query.offset.is_some()).then(|| PageQuery {
+        limit: query.limit.unwrap_or_else(default_page_limit),
+        offset: query.offset.unwrap_or(0),
+    });
+    if let Some(page) = &page {
+        check_page(page, &state.config).map_err(IntoResponse::into_response)?;
+        // Filter after the database page would leave pages short and their offsets meaningless
+        if query.difficulty.is_some() || query.period != Period::All {
+            return Err(ServerError::Validation(
+                "Difficulty and period can't be combined with limit and offset".into(),
+            )
+            .into_response());
+        }
+    }
+    let since = query.period.since(time.now());
+
+    // Query shared by requests which saw the same version, so its board is never older than that.
+    // Pages are cheap and differ between requests, they always run on their own
+    let scores = match (page, since, state.config.coalesce_reads) {
+        (Some(page), _, _) => state.scores.get_scores_page(page.limit, page.offset).await,
+        (None, Some(since), _) => state.scores.get_scores_since(since).await,
+        (None, None, true) => {
+            let store = state.scores.clone();
+            state
+                .board_reads
+                .run((state.game.clone(), version), async move {
+                    store.get_scores().await
+                })
+                .await
+        }
+        (None, None, false) => state.scores.get_scores().await,
+    };
+    let scores = scores.map_err(|e| {
         tracing::error!("Can't get scores!");
         e.into_response()
+    })?;
+    let expose_ids = state.config.expose_score_ids;
+    let entries: Vec<ScoreEntry> = scores
+        .into_iter()
+        .filter(|score| {
+            query.difficulty.is_none() || score.difficulty.as_deref() == query.difficulty.as_deref()
+        })
+        .map(|score| ScoreEntry::new(score, expose_ids))
+        .collect();
+
+    let body = FormattedJson {
+        value: entries,
+        pretty: query.pretty,
+    };
+    Ok((board_headers, body).into_response())
+}
+
+// Stale links to an ended season are pointed to its archive rather than the new season's board
+async fn check_season_live(state: &AppState, season: i32) -> Result<(), ServerError> {
+    if !state.config.archived_season_gone {
+        return Ok(());
+    }
+    if state.scores.season_archived(season).await? {
+        tracing::info!("Live board is asked for archived season {}", season);
+        return Err(ServerError::Gone(format!(
+            "Season {} is over, its board is at /api/seasons/{}/scores",
+            season, season
+        )));
+    }
+    Ok(())
+}
+
+// Board with positions ready for display, players with equal scores share the rank
+pub async fn get_ranked_scores(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RankedPlayerScore>>, Response> {
+    state.scores.ranked_scores().await.map(Json).map_err(|e| {
+        tracing::error!("Can't get ranked scores!");
+        e.into_response()
+    })
+}
+
+// Name goes through the same normalization as submissions, so it finds what was stored
+pub async fn get_player_best(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<ScoreEntry>, Response> {
+    let name = PlayerName::try_new(&name)
+        .and_then(|name| stored_name(&state.config, name))
+        .map_err(IntoResponse::into_response)?;
+
+    let best = state.scores.best_by_name(&name).await.map_err(|e| {
+        tracing::error!("Can't get best score of the player!");
+        e.into_response()
+    })?;
+
+    best.map(|score| Json(ScoreEntry::new(score, state.config.expose_score_ids)))
+        .ok_or_else(|| {
+            ServerError::NotFound(format!("Player {} has no score on the board", name))
+                .into_response()
+        })
+}
+
+// Routes of a game's own board run the same handlers on the state scoped to the game
+fn game_state(state: AppState, game_id: &str) -> Result<AppState, ServerError> {
+    GameId::try_new(game_id).map(|game| state.for_game(game))
+}
+
+pub async fn get_game_scores(
+    State(state): State<AppState>,
+    Path(game_id): Path<String>,
+    query: Query<ScoresQuery>,
+) -> Result<Response, Response> {
+    let state = game_state(state, &game_id).map_err(IntoResponse::into_response)?;
+    get_scores(State(state), query).await
+}
+
+pub async fn get_game_ranked_scores(
+    State(state): State<AppState>,
+    Path(game_id): Path<String>,
+) -> Result<Json<Vec<RankedPlayerScore>>, Response> {
+    let state = game_state(state, &game_id).map_err(IntoResponse::into_response)?;
+    get_ranked_scores(State(state)).await
+}
+
+pub async fn commit_game_record(
+    State(state): State<AppState>,
+    Path(game_id): Path<String>,
+    headers: HeaderMap,
+    record: Sanitized<PlayerScore>,
+) -> Result<Json<Value>, Response> {
+    let state = game_state(state, &game_id).map_err(IntoResponse::into_response)?;
+    commit_record(State(state), headers, record).await
+}
+
+pub async fn flush_game(
+    State(state): State<AppState>,
+    Path(game_id): Path<String>,
+    admin: Extension<AuthSubject>,
+    query: Query<FlushQuery>,
+) -> Result<Json<Value>, Response> {
+    let state = game_state(state, &game_id).map_err(IntoResponse::into_response)?;
+    flush(State(state), admin, query).await
+}
+
+// Ids are opaque handles from get-scores, a score pushed off the board gets 404
+pub async fn get_score_rank(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Value>, Response> {
+    let rank = state.scores.rank(id).await.map_err(|e| {
+        tracing::error!("Can't get rank of the score!");
+        e.into_response()
+    })?;
+
+    rank.map(|rank| Json(json!({"id": id, "rank": rank})))
+        .ok_or_else(|| {
+            ServerError::NotFound(format!("Score {} is not on the board", id)).into_response()
+        })
+}
+
+pub async fn board_page(State(state): State<AppState>) -> Result<Html<String>, Response> {
+    let scores = state.scores.get_scores().await.map_err(|e| {
+        tracing::error!("Can't get scores for board page!");
+        e.into_response()
+    })?;
+
+    Ok(Html(render_board(&scores)))
+}
+
+pub async fn get_score_schema(State(state): State<AppState>) -> Json<Value> {
+    Json(score_schema(&state.config))
+}
+
+// Rows of the live board, names are hashed with export salt when anonymized
+fn export_salt(state: &AppState, query: &ExportQuery) -> Result<Option<String>, ServerError> {
+    match (query.anonymize, &state.config.export_salt) {
+        (false, _) => Ok(None),
+        (true, Some(salt)) => Ok(Some(salt.clone())),
+        (true, None) => {
+            tracing::warn!("Anonymized export is requested without EXPORT_SALT!");
+            Err(ServerError::Unavailable(
+                "Anonymized export is not configured".into(),
+            ))
+        }
+    }
+}
+
+// Streamed row by row, so export of a big board doesn't load it into memory.
+// Database error in the middle cuts the body short, status is already sent by then
+fn export_response(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &ExportQuery,
+    format: ExportFormat,
+) -> Result<Response, ServerError> {
+    let salt = export_salt(state, query)?;
+
+    let rows = state.scores.stream_scores().map(move |score| {
+        score
+            .map(|score| format.line(&ExportRow::from_score(&score, salt.as_deref())))
+            .inspect_err(|_| tracing::error!("Can't stream scores for export!"))
+    });
+    let mut lines = futures::stream::iter(format.header().map(|header| Ok(header.to_string())))
+        .chain(rows)
+        .boxed();
+    if query.checksum {
+        lines = checksum_stream(lines, format);
+    }
+
+    let content_type = [(header::CONTENT_TYPE, format.content_type())];
+    Ok(match query.gzip(headers) {
+        true => (
+            content_type,
+            [
+                (header::CONTENT_ENCODING, "gzip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.gz\"", format.file_name()),
+                ),
+            ],
+            Body::from_stream(gzip_stream(lines)),
+        )
+            .into_response(),
+        false => (
+            content_type,
+            [(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", format.file_name()),
+            )],
+            Body::from_stream(lines),
+        )
+            .into_response(),
+    })
+}
+
+pub async fn export_csv(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, Response> {
+    export_response(&state, &headers, &query, ExportFormat::Csv)
+        .map_err(IntoResponse::into_response)
+}
+
+pub async fn export_ndjson(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, Response> {
+    export_response(&state, &headers, &query, ExportFormat::Ndjson)
+        .map_err(IntoResponse::into_response)
+}
+
+pub async fn get_season_scores(
+    State(state): State<AppState>,
+    Path(season): Path<i32>,
+    Query(page): Query<PageQuery>,
+) -> Result<Json<Vec<PlayerScore>>, Response> {
+    check_page(&page, &state.config).map_err(IntoResponse::into_response)?;
+
+    let scores = state
+        .scores
+        .get_archived_scores(season, page.limit, page.offset)
+        .await
+        .map_err(|e| {
+            tracing::error!("Can't get archived scores!");
+            e.into_response()
+        })?;
+
+    scores.map(Json).ok_or_else(|| {
+        ServerError::NotFound(format!("Season {} is not archived", season)).into_response()
     })
 }
 
-pub async fn flush(State(state): State<AppState>) -> Result<Json<Value>, Response> {
-    flush_scores_db(&state.pool)
+fn check_page(page: &PageQuery, config: &AppConfig) -> Result<(), ServerError> {
+    if !(1..=PAGE_MAX_LIMIT).contains(&page.limit) || page.offset < 0 {
+        return Err(ServerError::Validation(format!(
+            "Limit must be from 1 to {} and offset must be non-negative",
+            PAGE_MAX_LIMIT
+        )));
+    }
+    // Postgres reads and drops every skipped row, so deep offsets cost like the whole archive
+    if page.offset > config.max_page_offset {
+        tracing::warn!("Page offset {} is over the limit", page.offset);
+        return Err(ServerError::Validation(format!(
+            "Offset can't be over {}",
+            config.max_page_offset
+        )));
+    }
+    Ok(())
+}
+
+pub async fn flush(
+    State(state): State<AppState>,
+    Extension(AuthSubject(admin)): Extension<AuthSubject>,
+    Query(query): Query<FlushQuery>,
+) -> Result<Json<Value>, Response> {
+    if query.dry_run {
+        return state
+            .scores
+            .count()
+            .await
+            .map(|count| Json(json!({"would_delete": count})))
+            .map_err(|e| {
+                tracing::error!("Can't count scores for flush dry run!");
+                e.into_response()
+            });
+    }
+
+    state
+        .scores
+        .flush()
         .await
-        .map(|_| Json(json!({"status": "Ok"})))
+        .map(|_| {
+            state.bump_board_version();
+            state
+                .audit
+                .record(&admin, "flush", "All scores are deleted", None);
+            Json(json!({"status": "Ok"}))
+        })
         .map_err(|e| {
             tracing::error!("Can't flush scores!");
             e.into_response()
         })
 }
 
+pub async fn delete_players(
+    State(state): State<AppState>,
+    Extension(AuthSubject(admin)): Extension<AuthSubject>,
+    Json(request): Json<DeletePlayersRequest>,
+) -> Result<Json<Value>, Response> {
+    if request.names.is_empty() || request.names.len() > DELETE_MAX_NAMES {
+        return Err(ServerError::Validation(format!(
+            "From 1 to {} names can be deleted at once",
+            DELETE_MAX_NAMES
+        ))
+        .into_response());
+    }
+
+    let names: Vec<String> = request
+        .names
+        .into_iter()
+        .map(|name| stored_name(&state.config, name).map(|name| name.to_string()))
+        .collect::<Result<_, _>>()
+        .map_err(|e| {
+            tracing::error!("Name to delete can't be normalized!");
+            e.into_response()
+        })?;
+
+    let removed = state.scores.delete_players(&names).await.map_err(|e| {
+        tracing::error!("Can't delete players!");
+        e.into_response()
+    })?;
+
+    let deleted: serde_json::Map<String, Value> = names
+        .iter()
+        .map(|name| (name.clone(), json!(removed.get(name).copied().unwrap_or(0))))
+        .collect();
+
+    if !removed.is_empty() {
+        state.bump_board_version();
+    }
+    let mut removed_names: Vec<&String> = removed.keys().collect();
+    removed_names.sort();
+    state.audit.record(
+        &admin,
+        "delete_players",
+        &format!("Removed {:?}", removed_names),
+        Some(removed.values().sum::<i64>() as u64),
+    );
+
+    Ok(Json(json!({"deleted": deleted})))
+}
+
 pub async fn commit_record(
     State(state): State<AppState>,
-    Json(record): Json<PlayerScore>,
+    headers: HeaderMap,
+    Sanitized(mut record): Sanitized<PlayerScore>,
 ) -> Result<Json<Value>, Response> {
+    state.submissions.record_submission();
+
+    let client_version = client_version(&headers).map_err(|e| {
+        tracing::error!("Invalid client version header!");
+        e.into_response()
+    })?;
+
+    // Before normalization, the client signs the name it has sent
+    check_score_signature(&state.config, &headers, &record).map_err(|e| {
+        tracing::warn!("Score without a valid signature is rejected!");
+        e.into_response()
+    })?;
+
+    record.player_name = stored_name(&state.config, record.player_name).map_err(|e| {
+        tracing::error!("Name is rejected after normalization!");
+        e.into_response()
+    })?;
+
     if let Err(e) = record.validate() {
         tracing::error!("Validation of commited score data failed!");
         return Err(ServerError::Validation(format!(
@@ -96,11 +894,2665 @@ pub async fn commit_record(
         .into_response());
     }
 
-    add_new_score_db(&state.pool, record)
+    check_name_limit(&state, &headers, &record).map_err(|e| {
+        tracing::warn!("Token exceeded distinct names limit!");
+        e.into_response()
+    })?;
+
+    check_tier_ceiling(&state.config.tier_max_scores, &record).map_err(|e| {
+        tracing::warn!("Score above tier ceiling is rejected!");
+        e.into_response()
+    })?;
+
+    check_difficulty(&state.config.difficulties, &record).map_err(|e| {
+        tracing::warn!("Score with unknown difficulty is rejected!");
+        e.into_response()
+    })?;
+
+    // Claimed after the checks above, so a rejected score doesn't use up the session
+    let session = check_game_session(&state, &headers, &record)
+        .await
+        .map_err(|e| {
+            tracing::warn!("Score without a valid game session is rejected!");
+            e.into_response()
+        })?;
+
+    let repeat_token = repeat_token(&state, &headers);
+    if is_repeated_submission(&state, repeat_token, &record) {
+        tracing::info!("Repeated submission is skipped");
+        return Ok(Json(json!({"status": "Ok"})));
+    }
+
+    // Failed submission is not seen, so a retry within the window is stored instead of skipped.
+    // Its game session is given back as well
+    let (name, score) = (record.player_name.as_str().to_owned(), record.player_score);
+    store_record(&state, record, client_version)
+        .await
+        .inspect_err(|_| {
+            if let Some(token) = repeat_token {
+                state.recent.forget(token, &name, score);
+            }
+            if let Some(nonce) = &session {
+                state.used_sessions.release(nonce);
+            }
+        })
+}
+
+async fn store_record(
+    state: &AppState,
+    record: PlayerScore,
+    client_version: Option<&str>,
+) -> Result<Json<Value>, Response> {
+    if is_below_player_best(state, &record)
+        .await
+        .map_err(IntoResponse::into_response)?
+    {
+        tracing::info!("Submission below player's best is ignored");
+        return Ok(Json(json!({"status": "Ok", "ignored": true})));
+    }
+
+    check_row_cap(state)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // Monotonic scores keep one entry per player like dedup, lower ones never reach the store
+    let dedup = state.config.dedup_players || state.config.monotonic_scores;
+    let stored = state
+        .scores
+        .add_new_score(
+            record,
+            dedup,
+            dedup && state.config.count_plays,
+            client_version,
+            state.config.score_floor,
+            state.config.board_size(&state.game),
+        )
         .await
-        .map(|_| Json(json!({"status": "Ok"})))
         .map_err(|e| {
             tracing::error!("Adding new score error!");
             e.into_response()
+        })?;
+
+    state.submissions.record_outcome(stored.is_some());
+    if stored.is_some() {
+        state.bump_board_version();
+    }
+
+    match state.config.echo_stored_score {
+        true => Ok(Json(json!({
+            "status": "Ok",
+            "record": stored.map(|score| ScoreEntry::new(score, state.config.expose_score_ids)),
+        }))),
+        false => Ok(Json(json!({"status": "Ok"}))),
+    }
+}
+
+// Checked apart from worthiness - it is about the player's own entry, not the whole board
+async fn is_below_player_best(state: &AppState, record: &PlayerScore) -> Result<bool, ServerError> {
+    if !state.config.monotonic_scores {
+        return Ok(false);
+    }
+    let best = state.scores.player_best(record).await?;
+    Ok(best.is_some_and(|best| record.player_score < best))
+}
+
+// Requests without a token can't be told apart, so they are never treated as repeats
+fn repeat_token(state: &AppState, headers: &HeaderMap) -> Option<u64> {
+    if state.config.duplicate_window_secs == 0 {
+        return None;
+    }
+    extract_token(headers, state.config.auth_mode, &state.config.auth_scheme)
+        .ok()
+        .map(token_key)
+}
+
+fn is_repeated_submission(state: &AppState, token: Option<u64>, record: &PlayerScore) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+
+    state.recent.is_repeat(
+        token,
+        record.player_name.as_str(),
+        record.player_score,
+        std::time::Duration::from_secs(state.config.duplicate_window_secs),
+    )
+}
+
+async fn check_row_cap(state: &AppState) -> Result<(), ServerError> {
+    let rows = state.scores.count().await.inspect_err(|_| {
+        tracing::error!("Can't count scores for row cap!");
+    })?;
+    if rows >= state.config.hard_max_rows {
+        tracing::error!(
+            "ALERT: board holds {} rows, HARD_MAX_ROWS {} is reached! Submissions are refused",
+            rows,
+            state.config.hard_max_rows
+        );
+        return Err(ServerError::Unavailable(
+            "Leaderboard is full, submissions are paused".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Name as it is stored, so submissions and deletions of the same player always match
+fn stored_name(config: &AppConfig, name: PlayerName) -> Result<PlayerName, ServerError> {
+    let name = name.normalized(&config.name_normalize)?;
+    Ok(match config.fold_name_case {
+        true => name.folded(),
+        false => name,
+    })
+}
+
+// Tier is taken from the submission, tokens don't carry one. Submissions without tier
+// are not checked, unknown tiers are rejected while any ceiling is configured
+fn check_tier_ceiling(
+    ceilings: &HashMap<String, i32>,
+    record: &PlayerScore,
+) -> Result<(), ServerError> {
+    let Some(tier) = record.tier.as_deref() else {
+        return Ok(());
+    };
+    if ceilings.is_empty() {
+        return Ok(());
+    }
+
+    match ceilings.get(tier) {
+        Some(&ceiling) if record.player_score <= ceiling => Ok(()),
+        Some(&ceiling) => Err(ServerError::Unprocessable(format!(
+            "Score {} is above the ceiling {} of tier {}",
+            record.player_score, ceiling, tier
+        ))),
+        None => Err(ServerError::Unprocessable(format!("Unknown tier {}", tier))),
+    }
+}
+
+// Returns the nonce of the claimed session, none without GAME_SESSIONS
+async fn check_game_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    record: &PlayerScore,
+) -> Result<Option<String>, ServerError> {
+    if !state.config.game_sessions {
+        return Ok(None);
+    }
+    let token = headers
+        .get(GAME_SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| ServerError::Forbidden("Game session is missing".into()))?;
+
+    let secret = state.jwt_config.read().await.secret.clone();
+    verify_session(
+        &secret,
+        &state.used_sessions,
+        token,
+        RealTime.now(),
+        chrono::Duration::seconds(state.config.game_session_ttl_secs as i64),
+        record.player_score,
+        state.config.max_score_per_sec,
+    )
+    .map(Some)
+}
+
+// Signature is required only when SCORE_SIGNING_SECRET is set
+fn check_score_signature(
+    config: &AppConfig,
+    headers: &HeaderMap,
+    record: &PlayerScore,
+) -> Result<(), ServerError> {
+    let Some(secret) = config.score_signing_secret.as_deref() else {
+        return Ok(());
+    };
+    let signature = headers
+        .get(SCORE_SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| ServerError::Authentication("Score signature is missing".into()))?;
+    match verify_score_signature(record, signature, secret) {
+        true => Ok(()),
+        false => Err(ServerError::Authentication(
+            "Score signature is invalid".into(),
+        )),
+    }
+}
+
+// Scores without difficulty are always accepted, they were played before it was sent
+fn check_difficulty(difficulties: &[String], record: &PlayerScore) -> Result<(), ServerError> {
+    match record.difficulty.as_deref() {
+        Some(difficulty) if !difficulties.iter().any(|known| known == difficulty) => Err(
+            ServerError::Unprocessable(format!("Unknown difficulty {}", difficulty)),
+        ),
+        _ => Ok(()),
+    }
+}
+
+// Requests without a token never reach here through the router, so they are not limited
+fn check_name_limit(
+    state: &AppState,
+    headers: &HeaderMap,
+    record: &PlayerScore,
+) -> Result<(), ServerError> {
+    let max_names = state.config.max_names_per_token;
+    if max_names == 0 {
+        return Ok(());
+    }
+    let Ok(token) = extract_token(headers, state.config.auth_mode, &state.config.auth_scheme)
+    else {
+        return Ok(());
+    };
+
+    let window = std::time::Duration::from_secs(state.config.name_window_secs);
+    match state.names.check(
+        token_key(token),
+        record.player_name.as_str(),
+        max_names,
+        window,
+    ) {
+        true => Ok(()),
+        false => Err(ServerError::TooManyRequests(format!(
+            "Token can't submit more than {} different player names",
+            max_names
+        ))),
+    }
+}
+
+pub async fn get_cutoff(State(state): State<AppState>) -> Result<Json<Value>, Response> {
+    state
+        .scores
+        .cutoff(
+            state.config.score_floor,
+            state.config.board_size(&state.game),
+        )
+        .await
+        .map(|cutoff| Json(json!({"cutoff": cutoff})))
+        .map_err(|e| {
+            tracing::error!("Can't get board cutoff!");
+            e.into_response()
+        })
+}
+
+// Session is sent back with the score in X-Game-Session when the game ends
+pub async fn start_game(State(state): State<AppState>) -> Json<Value> {
+    let secret = state.jwt_config.read().await.secret.clone();
+    Json(json!({
+        "session": issue_session(&secret, RealTime.now()),
+        "expires_in": state.config.game_session_ttl_secs,
+    }))
+}
+
+pub async fn get_notice(State(state): State<AppState>) -> Json<Notice> {
+    Json(state.notice.read().await.clone())
+}
+
+// Setting notice without message clears it
+pub async fn set_notice(
+    State(state): State<AppState>,
+    Extension(AuthSubject(admin)): Extension<AuthSubject>,
+    Json(notice): Json<Notice>,
+) -> Result<Json<Notice>, Response> {
+    if let Err(e) = notice.validate() {
+        tracing::error!("Validation of notice failed!");
+        return Err(ServerError::Validation(format!(
+            "{} - Fields errors: {:?}",
+            e,
+            e.field_errors()
+        ))
+        .into_response());
+    }
+
+    if state.config.persist_notice {
+        set_notice_db(&state.pool, &notice).await.map_err(|e| {
+            tracing::error!("Can't store notice!");
+            e.into_response()
+        })?;
+    }
+
+    *state.notice.write().await = notice.clone();
+    state.audit.record(
+        &admin,
+        "set_notice",
+        notice.message.as_deref().unwrap_or("Notice is cleared"),
+        None,
+    );
+    Ok(Json(notice))
+}
+
+pub async fn backup(
+    State(state): State<AppState>,
+    Extension(AuthSubject(admin)): Extension<AuthSubject>,
+) -> Result<Json<Backup>, Response> {
+    let (scores, archive) = backup_tables_db(&state.pool).await.map_err(|e| {
+        tracing::error!("Can't read tables for backup!");
+        e.into_response()
+    })?;
+    let notice = state.notice.read().await.clone();
+
+    state.audit.record(
+        &admin,
+        "backup",
+        &format!("{} scores, {} archived", scores.len(), archive.len()),
+        Some((scores.len() + archive.len()) as u64),
+    );
+    Ok(Json(Backup {
+        version: BACKUP_VERSION,
+        scores,
+        archive,
+        notice,
+    }))
+}
+
+// Whole board is replaced, nothing from before the restore is kept.
+// Rows breaking the score rules refuse the restore, or with RESTORE_SKIP_INVALID
+// are left out and listed in the response
+pub async fn restore(
+    State(state): State<AppState>,
+    Extension(AuthSubject(admin)): Extension<AuthSubject>,
+    Json(mut backup): Json<Backup>,
+) -> Result<Json<Value>, Response> {
+    if ![BACKUP_VERSION, LEGACY_BACKUP_VERSION].contains(&backup.version) {
+        return Err(ServerError::Validation(format!(
+            "Backup version {} is not supported, expected {}",
+            backup.version, BACKUP_VERSION
+        ))
+        .into_response());
+    }
+    if let Err(e) = backup.notice.validate() {
+        return Err(ServerError::Validation(format!(
+            "{} - Fields errors: {:?}",
+            e,
+            e.field_errors()
+        ))
+        .into_response());
+    }
+
+    let rejected = backup.take_invalid_rows();
+    if !rejected.is_empty() {
+        tracing::warn!("Backup has {} invalid rows", rejected.len());
+        if !state.config.restore_skip_invalid {
+            let rows: Vec<String> = rejected.iter().map(ToString::to_string).collect();
+            return Err(ServerError::Validation(format!(
+                "Backup has invalid rows - {}",
+                rows.join("; ")
+            ))
+            .into_response());
+        }
+    }
+
+    let persisted_notice = state.config.persist_notice.then_some(&backup.notice);
+    restore_tables_db(&state.pool, &backup, persisted_notice)
+        .await
+        .map_err(|e| {
+            tracing::error!("Can't restore backup!");
+            e.into_response()
+        })?;
+
+    *state.notice.write().await = backup.notice.clone();
+    state.bump_board_version();
+    state.audit.record(
+        &admin,
+        "restore",
+        &format!(
+            "Restored {} scores, {} archived, {} rejected",
+            backup.scores.len(),
+            backup.archive.len(),
+            rejected.len()
+        ),
+        Some((backup.scores.len() + backup.archive.len()) as u64),
+    );
+    Ok(Json(json!({
+        "scores": backup.scores.len(),
+        "archive": backup.archive.len(),
+        "rejected": rejected
+    })))
+}
+
+pub async fn update_jwt_validation(
+    State(state): State<AppState>,
+    Extension(AuthSubject(admin)): Extension<AuthSubject>,
+    Json(update): Json<ValidationUpdate>,
+) -> Result<Json<ValidationSettings>, Response> {
+    let settings = state
+        .jwt_config
+        .write()
+        .await
+        .apply_update(update)
+        .map_err(|e| {
+            tracing::warn!("JWT validation update is rejected!");
+            e.into_response()
+        })?;
+
+    state.audit.record(
+        &admin,
+        "jwt_validation",
+        &format!(
+            "Set leeway {}, nbf leeway {}, validate exp {}, required claims [{}]",
+            settings.leeway,
+            settings.nbf_leeway,
+            settings.validate_exp,
+            settings.required_claims.join(", ")
+        ),
+        None,
+    );
+    Ok(Json(settings))
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    #[serde(default = "default_audit_limit")]
+    pub limit: usize,
+}
+
+pub const AUDIT_MAX_LIMIT: usize = 200;
+
+fn default_audit_limit() -> usize {
+    50
+}
+
+pub async fn get_audit(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEvent>>, Response> {
+    if !(1..=AUDIT_MAX_LIMIT).contains(&query.limit) {
+        return Err(ServerError::Validation(format!(
+            "Limit must be from 1 to {}",
+            AUDIT_MAX_LIMIT
+        ))
+        .into_response());
+    }
+
+    Ok(Json(state.audit.recent(query.limit)))
+}
+
+#[cfg(test)]
+mod handlers_tests {
+    use super::*;
+    use crate::store::ScoreStore;
+    use crate::{
+        backup::BackupScore,
+        config::AppConfig,
+        db_access::{flush_scores_db, get_test_db_pool, seed_test_user},
+        game::DEFAULT_GAME,
+        notice::NoticeLevel,
+        player_name::NameStep,
+        security::{JwtConfig, LoginError, MockTime, generate_jwt},
+        set_up_jwt,
+        state::SubmissionSnapshot,
+        store::MockScoreStore,
+    };
+    use chrono::TimeZone;
+    use serial_test::serial;
+    use std::sync::Arc;
+
+    async fn get_test_state(config: AppConfig) -> AppState {
+        let pool = get_test_db_pool().await;
+        flush_scores_db(&pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        AppState::new(pool, set_up_jwt(&AppConfig::default())).with_config(config)
+    }
+
+    fn test_score(player_name: &str, player_score: i32) -> PlayerScore {
+        PlayerScore {
+            id: None,
+            player_name: player_name.parse().unwrap(),
+            player_score,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_record_echo() {
+        let state = get_test_state(AppConfig {
+            echo_stored_score: true,
+            ..AppConfig::default()
+        })
+        .await;
+
+        let Json(body) = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Echo", 77)),
+        )
+        .await
+        .expect("Can't commit record!");
+        assert_eq!(body["status"], "Ok");
+        assert_eq!(body["record"]["player_name"], "Echo");
+        assert_eq!(body["record"]["player_score"], 77);
+
+        let Json(body) = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Echo", 0)),
+        )
+        .await
+        .expect("Can't commit record!");
+        assert!(body["record"].is_null(), "Unworthy record is echoed!");
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_record_echo_normalized() {
+        let state = get_test_state(AppConfig {
+            echo_stored_score: true,
+            fold_name_case: true,
+            ..AppConfig::default()
         })
+        .await;
+
+        let record: PlayerScore =
+            serde_json::from_str(r#"{"player_name": "  Big   Red Dragon ", "player_score": 50}"#)
+                .expect("Can't parse record");
+        let Json(body) = commit_record(State(state.clone()), HeaderMap::new(), Sanitized(record))
+            .await
+            .expect("Can't commit record!");
+        assert_eq!(body["record"]["player_name"], "big red dragon");
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    async fn test_delete_players() {
+        let state = mock_state(get_test_db_pool().await);
+        for (name, score) in [("Dragon", 5), ("Wyvern", 4), ("Dragon", 3), ("Drake", 2)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        let version = state.board_version();
+
+        let request: DeletePlayersRequest =
+            serde_json::from_str(r#"{"names": ["Dragon", "Ghost", " Wyvern "]}"#)
+                .expect("Can't parse request");
+        let Json(body) = delete_players(
+            State(state.clone()),
+            Extension(AuthSubject("admin".to_string())),
+            Json(request),
+        )
+        .await
+        .expect("Can't delete players!");
+
+        assert_eq!(
+            body,
+            json!({"deleted": {"Dragon": 2, "Ghost": 0, "Wyvern": 1}})
+        );
+        assert_eq!(
+            state
+                .scores
+                .get_scores()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(PlayerScore::without_id)
+                .collect::<Vec<_>>(),
+            vec![test_score("Drake", 2)]
+        );
+        assert!(state.board_version() > version, "Version is not bumped!");
+
+        let too_many = DeletePlayersRequest {
+            names: vec!["Dragon".parse().unwrap(); DELETE_MAX_NAMES + 1],
+        };
+        let rejected = delete_players(
+            State(state.clone()),
+            Extension(AuthSubject("admin".to_string())),
+            Json(too_many),
+        )
+        .await
+        .expect_err("Too many names are accepted!");
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_introspect() {
+        let pool = get_test_db_pool().await;
+        let state = mock_state(pool);
+        let secret = state.jwt_config.read().await.secret.clone();
+        let introspect_json =
+            |token: String| introspect(State(state.clone()), Json(IntrospectRequest { token }));
+
+        let active_token = generate_jwt(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't generate token");
+        let Json(active) = introspect_json(active_token).await;
+        assert_eq!(
+            active,
+            json!({
+                "active": true,
+                "sub": "player",
+                "exp": active["exp"],
+                "role": "default",
+                "token_type": "access",
+                "jti": active["jti"]
+            })
+        );
+        assert!(active["exp"].is_u64(), "Expiration is missing!");
+        assert!(active["jti"].is_string(), "Token id is missing!");
+
+        let expired_token = generate_jwt(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &MockTime,
+        )
+        .expect("Can't generate token");
+        let Json(expired) = introspect_json(expired_token).await;
+        assert_eq!(
+            expired,
+            json!({"active": false}),
+            "Claims of inactive token are returned!"
+        );
+
+        let foreign_token = generate_jwt(
+            "player",
+            &JwtConfig::new("other_secret".to_string()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't generate token");
+        let Json(foreign) = introspect_json(foreign_token).await;
+        assert_eq!(foreign, json!({"active": false}));
+    }
+
+    #[tokio::test]
+    async fn test_validate_batch() {
+        let state = mock_state(get_test_db_pool().await);
+        let secret = state.jwt_config.read().await.secret.clone();
+        let valid = generate_jwt(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't generate token");
+        let expired = generate_jwt(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &MockTime,
+        )
+        .expect("Can't generate token");
+
+        let Json(response) = validate_batch(
+            State(state.clone()),
+            Json(ValidateBatchRequest {
+                tokens: vec![valid, expired, "garbage".to_string()],
+            }),
+        )
+        .await
+        .expect("Can't validate batch!");
+        let results = json!(response.results);
+        assert_eq!(
+            results,
+            json!([
+                {
+                    "active": true,
+                    "sub": "player",
+                    "exp": results[0]["exp"],
+                    "role": "default",
+                    "token_type": "access",
+                    "jti": results[0]["jti"]
+                },
+                {"active": false},
+                {"active": false},
+            ])
+        );
+
+        let too_many = validate_batch(
+            State(state.clone()),
+            Json(ValidateBatchRequest {
+                tokens: vec!["garbage".to_string(); VALIDATE_BATCH_MAX + 1],
+            }),
+        )
+        .await
+        .expect_err("Too big batch is accepted!");
+        assert_eq!(too_many.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_name_limit() {
+        let pool = get_test_db_pool().await;
+        let state = mock_state(pool).with_config(AppConfig {
+            max_names_per_token: 2,
+            ..AppConfig::default()
+        });
+        let headers_for = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            headers
+        };
+        let submit = |token: &str, name: &str| {
+            commit_record(
+                State(state.clone()),
+                headers_for(token),
+                Sanitized(test_score(name, 10)),
+            )
+        };
+
+        assert!(submit("token_a", "Dragon").await.is_ok());
+        assert!(submit("token_a", "Wyvern").await.is_ok());
+        assert!(submit("token_a", "Dragon").await.is_ok());
+        let blocked = submit("token_a", "Drake")
+            .await
+            .expect_err("Third name is accepted!");
+        assert_eq!(blocked.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        assert!(
+            submit("token_b", "Drake").await.is_ok(),
+            "Other token is blocked!"
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_record_client_version() {
+        let state = get_test_state(AppConfig::default()).await;
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_VERSION_HEADER, " 2.0.1+build7 ".parse().unwrap());
+
+        let _ = commit_record(
+            State(state.clone()),
+            headers,
+            Sanitized(test_score("Echo", 77)),
+        )
+        .await
+        .expect("Can't commit record!");
+        let version = sqlx::query_scalar!("SELECT client_version FROM flappy_dragon_score")
+            .fetch_one(&state.pool)
+            .await
+            .expect("Can't get version!");
+        assert_eq!(version.as_deref(), Some("2.0.1+build7"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_VERSION_HEADER, "9".repeat(33).parse().unwrap());
+        let rejected = commit_record(
+            State(state.clone()),
+            headers,
+            Sanitized(test_score("Echo", 80)),
+        )
+        .await
+        .expect_err("Too long client version is accepted!");
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_record_no_echo() {
+        let state = get_test_state(AppConfig::default()).await;
+
+        let Json(body) = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Echo", 77)),
+        )
+        .await
+        .expect("Can't commit record!");
+        assert_eq!(body, json!({"status": "Ok"}));
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    async fn response_json<T: serde::de::DeserializeOwned>(response: Response) -> T {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Can't read body");
+        serde_json::from_slice(&body).expect("Body is not a valid JSON")
+    }
+
+    fn admin() -> Extension<AuthSubject> {
+        Extension(AuthSubject("admin".into()))
+    }
+
+    fn mock_state(pool: sqlx::PgPool) -> AppState {
+        AppState::new(pool, set_up_jwt(&AppConfig::default()))
+            .with_score_store(Arc::new(MockScoreStore::default()))
+    }
+
+    fn board_version(response: &Response) -> u64 {
+        response
+            .headers()
+            .get(BOARD_VERSION_HEADER)
+            .expect("Version header is missing!")
+            .to_str()
+            .expect("Version is not a string")
+            .parse()
+            .expect("Version is not a number")
+    }
+
+    #[tokio::test]
+    async fn test_get_scores_since_version() {
+        let state = mock_state(get_test_db_pool().await);
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Mock", 5)),
+        )
+        .await
+        .expect("Can't commit record!");
+
+        let response = get_scores(State(state.clone()), Query(ScoresQuery::default()))
+            .await
+            .expect("Can't get scores!");
+        let version = board_version(&response);
+        assert_eq!(version, 1);
+
+        let matching = get_scores(
+            State(state.clone()),
+            Query(ScoresQuery {
+                since_version: Some(version),
+                ..ScoresQuery::default()
+            }),
+        )
+        .await
+        .expect("Can't get scores!");
+        assert_eq!(matching.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(board_version(&matching), version);
+
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Mock", 7)),
+        )
+        .await
+        .expect("Can't commit record!");
+        let stale = get_scores(
+            State(state.clone()),
+            Query(ScoresQuery {
+                since_version: Some(version),
+                ..ScoresQuery::default()
+            }),
+        )
+        .await
+        .expect("Can't get scores!");
+        assert_eq!(stale.status(), StatusCode::OK);
+        assert_eq!(board_version(&stale), version + 1);
+        assert_eq!(response_json::<Vec<PlayerScore>>(stale).await.len(), 2);
+
+        let _ = flush(State(state.clone()), admin(), Query(FlushQuery::default()))
+            .await
+            .expect("Can't flush!");
+        assert!(
+            state.board_version() > version + 1,
+            "Flush didn't change version!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_scores_pretty() {
+        let state = mock_state(get_test_db_pool().await);
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Mock", 5)),
+        )
+        .await
+        .expect("Can't commit record!");
+
+        let body = |pretty: bool| {
+            let state = state.clone();
+            async move {
+                let response = get_scores(
+                    State(state),
+                    Query(ScoresQuery {
+                        pretty,
+                        ..ScoresQuery::default()
+                    }),
+                )
+                .await
+                .expect("Can't get scores!");
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("Can't read body");
+                String::from_utf8(bytes.to_vec()).expect("Body is not UTF-8")
+            }
+        };
+
+        let compact = body(false).await;
+        let pretty = body(true).await;
+        assert!(!compact.contains('\n'));
+        assert!(pretty.contains("\n  {\n    \"player_name\": \"Mock\""));
+        assert_eq!(
+            serde_json::from_str::<Value>(&compact).unwrap(),
+            serde_json::from_str::<Value>(&pretty).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_scores_paginated() {
+        let state = mock_state(get_test_db_pool().await);
+        for score in [50, 40, 30, 20, 10] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(&format!("Mock{}", score), score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+
+        let page = |limit: Option<i64>, offset: Option<i64>| {
+            let state = state.clone();
+            async move {
+                get_scores(
+                    State(state),
+                    Query(ScoresQuery {
+                        limit,
+                        offset,
+                        ..ScoresQuery::default()
+                    }),
+                )
+                .await
+            }
+        };
+        let scores_of = |response: Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("Can't read body");
+            serde_json::from_slice::<Vec<Value>>(&bytes)
+                .expect("Body is not a board")
+                .iter()
+                .map(|entry| entry["player_score"].as_i64().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let first = page(Some(2), None).await.expect("Can't get first page!");
+        assert_eq!(scores_of(first).await, vec![50, 40]);
+        let middle = page(Some(2), Some(2))
+            .await
+            .expect("Can't get middle page!");
+        assert_eq!(scores_of(middle).await, vec![30, 20]);
+        let whole = page(None, None).await.expect("Can't get whole board!");
+        assert_eq!(scores_of(whole).await.len(), 5);
+
+        for (limit, offset) in [(Some(101), None), (Some(0), None), (None, Some(-1))] {
+            let refused = page(limit, offset)
+                .await
+                .expect_err("Out of range page is accepted!");
+            assert_eq!(refused.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_ranked_scores() {
+        let state = mock_state(get_test_db_pool().await);
+        for (name, score) in [("First", 50), ("Second", 50), ("Third", 40)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+
+        let Json(ranked) = get_ranked_scores(State(state))
+            .await
+            .expect("Can't get ranked scores!");
+        let ranks: Vec<(i64, &str)> = ranked
+            .iter()
+            .map(|score| (score.rank, score.player_name.as_str()))
+            .collect();
+        assert_eq!(ranks, vec![(1, "First"), (1, "Second"), (3, "Third")]);
+    }
+
+    #[tokio::test]
+    async fn test_get_scores_ids_and_links() {
+        let hidden = mock_state(get_test_db_pool().await);
+        let exposed = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            expose_score_ids: true,
+            ..AppConfig::default()
+        });
+        for state in [&hidden, &exposed] {
+            for player_score in [5, 9] {
+                let _ = commit_record(
+                    State(state.clone()),
+                    HeaderMap::new(),
+                    Sanitized(test_score("Mock", player_score)),
+                )
+                .await
+                .expect("Can't commit record!");
+            }
+        }
+
+        let response = get_scores(State(hidden.clone()), Query(ScoresQuery::default()))
+            .await
+            .expect("Can't get scores!");
+        let board = response_json::<Value>(response).await;
+        assert_eq!(board, json!([test_score("Mock", 9), test_score("Mock", 5)]));
+
+        let response = get_scores(State(exposed.clone()), Query(ScoresQuery::default()))
+            .await
+            .expect("Can't get scores!");
+        let board = response_json::<Value>(response).await;
+        for (position, entry) in board
+            .as_array()
+            .expect("Board is not a list")
+            .iter()
+            .enumerate()
+        {
+            let id = entry["id"].as_i64().expect("Id is missing!") as i32;
+            let link = entry["_links"]["rank"]
+                .as_str()
+                .expect("Rank link is missing!");
+            assert_eq!(link, format!("/api/scores/{}/rank", id));
+
+            let Json(rank) = get_score_rank(State(exposed.clone()), Path(id))
+                .await
+                .expect("Can't get rank!");
+            assert_eq!(rank, json!({"id": id, "rank": position + 1}));
+        }
+
+        let missing = get_score_rank(State(exposed.clone()), Path(-1))
+            .await
+            .expect_err("Rank of missing score is returned!");
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_player_id_dedup() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            dedup_players: true,
+            ..AppConfig::default()
+        });
+        let record = |player_score: i32| -> PlayerScore {
+            serde_json::from_value(json!({
+                "player_name": "Mock",
+                "player_score": player_score,
+                "player_id": "client-42",
+                "avatar": "dragon_green",
+            }))
+            .expect("Can't parse record")
+        };
+
+        for player_score in [5, 9, 7] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(record(player_score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        let response = get_scores(State(state.clone()), Query(ScoresQuery::default()))
+            .await
+            .expect("Can't get scores!");
+        assert_eq!(
+            response_json::<Vec<PlayerScore>>(response).await,
+            vec![record(9)]
+        );
+
+        let too_long_id = PlayerScore {
+            player_id: Some("x".repeat(65)),
+            ..record(10)
+        };
+        let rejected = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(too_long_id),
+        )
+        .await
+        .expect_err("Too long player id is accepted!");
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_monotonic_scores() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            monotonic_scores: true,
+            count_plays: true,
+            ..AppConfig::default()
+        });
+        let submit = |player_score: i32| {
+            commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score("Mock", player_score)),
+            )
+        };
+
+        let Json(stored) = submit(9).await.expect("Can't commit record!");
+        assert_eq!(stored, json!({"status": "Ok"}));
+        let Json(ignored) = submit(5).await.expect("Can't commit record!");
+        assert_eq!(ignored, json!({"status": "Ok", "ignored": true}));
+
+        let scores = state.scores.get_scores().await.expect("Can't get scores!");
+        assert_eq!(
+            scores
+                .into_iter()
+                .map(PlayerScore::without_id)
+                .collect::<Vec<_>>(),
+            vec![PlayerScore {
+                play_count: Some(1),
+                ..test_score("Mock", 9)
+            }],
+            "Lower submission changed the player's entry!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_game_session() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            game_sessions: true,
+            ..AppConfig::default()
+        });
+        let secret = state.jwt_config.read().await.secret.clone();
+        let submit = |session: Option<String>| {
+            let mut headers = HeaderMap::new();
+            if let Some(session) = session {
+                headers.insert(GAME_SESSION_HEADER, session.parse().unwrap());
+            }
+            commit_record(
+                State(state.clone()),
+                headers,
+                Sanitized(test_score("Mock", 5)),
+            )
+        };
+
+        let missing = submit(None)
+            .await
+            .expect_err("Score without session is accepted!");
+        assert_eq!(missing.status(), StatusCode::FORBIDDEN);
+
+        let Json(started) = start_game(State(state.clone())).await;
+        let fresh = started["session"].as_str().expect("Session is missing!");
+        let too_fast = submit(Some(fresh.to_string()))
+            .await
+            .expect_err("Score reached in no time is accepted!");
+        assert_eq!(too_fast.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let played = issue_session(&secret, RealTime.now() - chrono::Duration::seconds(10));
+        let _ = submit(Some(played.clone()))
+            .await
+            .expect("Score with valid session is rejected!");
+        let replayed = submit(Some(played))
+            .await
+            .expect_err("Replayed session is accepted!");
+        assert_eq!(replayed.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_rejected_score_keeps_session() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            game_sessions: true,
+            hard_max_rows: 0,
+            ..AppConfig::default()
+        });
+        let secret = state.jwt_config.read().await.secret.clone();
+        let session = issue_session(&secret, RealTime.now() - chrono::Duration::seconds(10));
+        let mut headers = HeaderMap::new();
+        headers.insert(GAME_SESSION_HEADER, session.parse().unwrap());
+        let submit = |state: &AppState, difficulty: Option<&str>| {
+            let mut record = test_score("Mock", 5);
+            record.difficulty = difficulty.map(str::to_string);
+            commit_record(State(state.clone()), headers.clone(), Sanitized(record))
+        };
+
+        let unknown = submit(&state, Some("nightmare"))
+            .await
+            .expect_err("Score with unknown difficulty is accepted!");
+        assert_eq!(unknown.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let full = submit(&state, None)
+            .await
+            .expect_err("Submission past the hard cap is accepted!");
+        assert_eq!(full.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Same used sessions and store, with room on the board
+        let state = state.with_config(AppConfig {
+            game_sessions: true,
+            ..AppConfig::default()
+        });
+        let _ = submit(&state, None)
+            .await
+            .expect("Session of a rejected score is used up!");
+        assert_eq!(state.scores.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_score_signature() {
+        const SECRET: &str = "score-signing-secret";
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            score_signing_secret: Some(SECRET.to_string()),
+            ..AppConfig::default()
+        });
+        let sign = |message: &str| {
+            use hmac::{Hmac, Mac};
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+            mac.update(message.as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        };
+        let submit = |score: i32, signature: Option<String>| {
+            let mut headers = HeaderMap::new();
+            if let Some(signature) = signature {
+                headers.insert(SCORE_SIGNATURE_HEADER, signature.parse().unwrap());
+            }
+            commit_record(
+                State(state.clone()),
+                headers,
+                Sanitized(test_score("Mock", score)),
+            )
+        };
+
+        let _ = submit(5, Some(sign("Mock:5")))
+            .await
+            .expect("Score with valid signature is rejected!");
+
+        let tampered = submit(999_999, Some(sign("Mock:5")))
+            .await
+            .expect_err("Tampered score is accepted!");
+        assert_eq!(tampered.status(), StatusCode::UNAUTHORIZED);
+
+        let missing = submit(5, None)
+            .await
+            .expect_err("Score without signature is accepted!");
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+
+        let scores = state.scores.get_scores().await.expect("Can't get scores!");
+        assert_eq!(scores.len(), 1, "Refused score is stored!");
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_submission_stats() {
+        let state = mock_state(get_test_db_pool().await);
+
+        for player_score in [5, 9, 0] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score("Mock", player_score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        let invalid = PlayerScore {
+            player_score: -1,
+            ..test_score("Mock", 0)
+        };
+        let _ = commit_record(State(state.clone()), HeaderMap::new(), Sanitized(invalid))
+            .await
+            .expect_err("Invalid record is accepted!");
+
+        assert_eq!(
+            state.submissions.snapshot(),
+            SubmissionSnapshot {
+                total: 4,
+                recorded: 2,
+                rejected: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flush_dry_run() {
+        let state = mock_state(get_test_db_pool().await);
+        for player_score in [5, 9] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score("Mock", player_score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        let version = state.board_version();
+
+        let Json(body) = flush(
+            State(state.clone()),
+            admin(),
+            Query(FlushQuery { dry_run: true }),
+        )
+        .await
+        .expect("Can't flush!");
+        assert_eq!(body, json!({"would_delete": 2}));
+        assert_eq!(
+            state
+                .scores
+                .get_scores()
+                .await
+                .expect("Can't get scores!")
+                .len(),
+            2,
+            "Dry run deleted scores!"
+        );
+        assert_eq!(state.board_version(), version, "Dry run changed version!");
+
+        let _ = flush(State(state.clone()), admin(), Query(FlushQuery::default()))
+            .await
+            .expect("Can't flush!");
+        assert!(
+            state
+                .scores
+                .get_scores()
+                .await
+                .expect("Can't get scores!")
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_scores_archived_season_gone() {
+        let store = Arc::new(MockScoreStore::default());
+        store.archive_season(3, vec![test_score("Old", 5)]);
+        let state = AppState::new(get_test_db_pool().await, set_up_jwt(&AppConfig::default()))
+            .with_score_store(store);
+        let for_season = |state: AppState, season: i32| {
+            get_scores(
+                State(state),
+                Query(ScoresQuery {
+                    season: Some(season),
+                    ..ScoresQuery::default()
+                }),
+            )
+        };
+
+        let ignored = for_season(state.clone(), 3)
+            .await
+            .expect("Season is checked without ARCHIVED_SEASON_GONE!");
+        assert_eq!(ignored.status(), StatusCode::OK);
+
+        let state = state.with_config(AppConfig {
+            archived_season_gone: true,
+            ..AppConfig::default()
+        });
+        let gone = for_season(state.clone(), 3)
+            .await
+            .expect_err("Archived season is served from the live board!");
+        assert_eq!(gone.status(), StatusCode::GONE);
+        let body = response_json::<Value>(gone).await;
+        assert!(
+            body["details:"]
+                .as_str()
+                .expect("Details are missing")
+                .contains("/api/seasons/3/scores"),
+            "Archive route is not pointed to!"
+        );
+
+        let live = for_season(state, 4)
+            .await
+            .expect("Live season is rejected!");
+        assert_eq!(live.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_get_season_scores() {
+        let pool = get_test_db_pool().await;
+        let store = Arc::new(MockScoreStore::default());
+        store.archive_season(3, (1..=5).rev().map(|i| test_score("Old", i)).collect());
+        let state = AppState::new(pool, set_up_jwt(&AppConfig::default())).with_score_store(store);
+
+        let Json(page) = get_season_scores(
+            State(state.clone()),
+            Path(3),
+            Query(PageQuery {
+                limit: 2,
+                offset: 2,
+            }),
+        )
+        .await
+        .expect("Can't get season scores!");
+        assert_eq!(page, vec![test_score("Old", 3), test_score("Old", 2)]);
+
+        let missing = get_season_scores(State(state.clone()), Path(4), Query(PageQuery::default()))
+            .await
+            .expect_err("Unknown season is found!");
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let bad_limit = get_season_scores(
+            State(state.clone()),
+            Path(3),
+            Query(PageQuery {
+                limit: 0,
+                offset: 0,
+            }),
+        )
+        .await
+        .expect_err("Zero limit is accepted!");
+        assert_eq!(bad_limit.status(), StatusCode::BAD_REQUEST);
+
+        let capped = state.clone().with_config(AppConfig {
+            max_page_offset: 2,
+            ..AppConfig::default()
+        });
+        let deep_page = |offset: i64| {
+            get_season_scores(
+                State(capped.clone()),
+                Path(3),
+                Query(PageQuery { limit: 2, offset }),
+            )
+        };
+        assert!(deep_page(2).await.is_ok());
+        let too_deep = deep_page(3)
+            .await
+            .expect_err("Too deep offset is accepted!");
+        assert_eq!(too_deep.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_backup_round_trip() {
+        let state = get_test_state(AppConfig::default()).await;
+        for (name, score) in [("Dragon", 5), ("Wyvern", 4), ("Drake", 4)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(PlayerScore {
+                    player_id: Some(format!("{}-id", name)),
+                    ..test_score(name, score)
+                }),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        sqlx::query!("TRUNCATE TABLE flappy_dragon_score_archive")
+            .execute(&state.pool)
+            .await
+            .expect("Can't clear archive!");
+        sqlx::query!(
+            "INSERT INTO flappy_dragon_score_archive (season_id, player_name, player_score) VALUES (2, 'Veteran', 50)"
+        )
+        .execute(&state.pool)
+        .await
+        .expect("Can't seed archive!");
+        let notice = Notice {
+            message: Some("Back soon".into()),
+            level: NoticeLevel::Warn,
+        };
+        *state.notice.write().await = notice.clone();
+
+        let Json(snapshot) = backup(State(state.clone()), admin())
+            .await
+            .expect("Can't take backup!");
+        assert_eq!(snapshot.version, BACKUP_VERSION);
+        assert_eq!(snapshot.scores.len(), 3);
+        assert_eq!(snapshot.archive.len(), 1);
+        assert_eq!(snapshot.notice, notice);
+
+        // Losing everything the backup is supposed to bring back
+        state.scores.flush().await.expect("Can't flush scores!");
+        sqlx::query!("TRUNCATE TABLE flappy_dragon_score_archive")
+            .execute(&state.pool)
+            .await
+            .expect("Can't clear archive!");
+        *state.notice.write().await = Notice::default();
+
+        let version_before = state.board_version();
+        let Json(body) = restore(State(state.clone()), admin(), Json(snapshot.clone()))
+            .await
+            .expect("Can't restore backup!");
+        assert_eq!(body["scores"], 3);
+        assert!(state.board_version() > version_before);
+
+        let Json(restored) = backup(State(state.clone()), admin())
+            .await
+            .expect("Can't take backup!");
+        assert_eq!(restored, snapshot, "Restored board differs from backup!");
+
+        let unsupported = restore(
+            State(state.clone()),
+            admin(),
+            Json(Backup {
+                version: BACKUP_VERSION + 1,
+                ..snapshot
+            }),
+        )
+        .await
+        .expect_err("Unknown backup version is restored!");
+        assert_eq!(unsupported.status(), StatusCode::BAD_REQUEST);
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        sqlx::query!("TRUNCATE TABLE flappy_dragon_score_archive")
+            .execute(&state.pool)
+            .await
+            .expect("Can't clear archive!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_get_player_best() {
+        let state = get_test_state(AppConfig::default()).await;
+        for (name, score) in [("Dragon", 30), ("Dragon", 70), ("Wyvern", 90)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+
+        let Json(best) = get_player_best(State(state.clone()), Path("Dragon".to_string()))
+            .await
+            .expect("Can't get best score!");
+        assert_eq!(best.score.player_name.as_str(), "Dragon");
+        assert_eq!(best.score.player_score, 70);
+
+        let missing = get_player_best(State(state.clone()), Path("Nobody".to_string()))
+            .await
+            .expect_err("Absent player has a best score!");
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    async fn test_period_since() {
+        // MockTime is Sunday noon, its week started on Monday
+        let at = |day: u32| Utc.with_ymd_and_hms(2015, 3, day, 0, 0, 0).single();
+        assert_eq!(Period::Daily.since(MockTime.now()), at(15));
+        assert_eq!(Period::Weekly.since(MockTime.now()), at(9));
+        assert_eq!(Period::All.since(MockTime.now()), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_get_scores_by_period() {
+        let state = get_test_state(AppConfig::default()).await;
+        // MockTime is Sunday 2015-03-15 noon, its week started on Monday 2015-03-09
+        for (name, score, posted) in [
+            ("Today", 10, (15, 9)),
+            ("ThisWeek", 20, (10, 8)),
+            ("LastWeek", 30, (8, 23)),
+        ] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Can't commit record!");
+            let (day, hour) = posted;
+            sqlx::query!(
+                "UPDATE flappy_dragon_score SET posted_time = $1 AT TIME ZONE 'UTC' WHERE player_name = $2",
+                Utc.with_ymd_and_hms(2015, 3, day, hour, 0, 0).unwrap(),
+                name
+            )
+            .execute(&state.pool)
+            .await
+            .expect("Can't set posted time!");
+        }
+
+        let names = |period: Period| {
+            let state = state.clone();
+            async move {
+                let response = get_scores_at(
+                    state,
+                    ScoresQuery {
+                        period,
+                        ..ScoresQuery::default()
+                    },
+                    MockTime,
+                )
+                .await
+                .expect("Can't get scores!");
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("Can't read body");
+                serde_json::from_slice::<Vec<Value>>(&bytes)
+                    .expect("Body is not a board")
+                    .iter()
+                    .map(|entry| entry["player_name"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(names(Period::Daily).await, vec!["Today"]);
+        assert_eq!(names(Period::Weekly).await, vec!["ThisWeek", "Today"]);
+        assert_eq!(
+            names(Period::All).await,
+            vec!["LastWeek", "ThisWeek", "Today"]
+        );
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_game_board_sizes() {
+        let state = get_test_state(AppConfig {
+            leaderboard_size: 3,
+            leaderboard_sizes: HashMap::from([
+                ("endless".to_string(), 5),
+                ("time_attack".to_string(), 2),
+            ]),
+            ..AppConfig::default()
+        })
+        .await;
+        for game in ["endless", "time_attack", "classic"] {
+            for score in 1..=6 {
+                let _ = commit_game_record(
+                    State(state.clone()),
+                    Path(game.to_string()),
+                    HeaderMap::new(),
+                    Sanitized(test_score("Sized", score)),
+                )
+                .await
+                .expect("Can't commit record!");
+            }
+        }
+
+        let board_len = |game: &str| {
+            let state = state.clone().for_game(game.parse().unwrap());
+            async move {
+                state
+                    .scores
+                    .get_scores()
+                    .await
+                    .expect("Can't get scores!")
+                    .len()
+            }
+        };
+        assert_eq!(board_len("endless").await, 5);
+        assert_eq!(board_len("time_attack").await, 2);
+        assert_eq!(board_len("classic").await, 3, "Default size is not used!");
+
+        for game in ["endless", "time_attack", "classic"] {
+            flush_scores_db(&state.pool, game)
+                .await
+                .expect("Can't flush test db!");
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_game_boards_are_independent() {
+        let state = get_test_state(AppConfig {
+            leaderboard_size: 3,
+            ..AppConfig::default()
+        })
+        .await;
+        let submit = |game: &str, name: &str, score: i32| {
+            commit_game_record(
+                State(state.clone()),
+                Path(game.to_string()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+        };
+        for score in [10, 20, 30, 40] {
+            let _ = submit("alpha", "Alpha", score)
+                .await
+                .expect("Can't commit record!");
+        }
+        for score in [1, 2] {
+            let _ = submit("beta", "Beta", score)
+                .await
+                .expect("Can't commit record!");
+        }
+
+        let board = |game: &str| {
+            let state = state.clone();
+            let game = game.to_string();
+            async move {
+                let response =
+                    get_game_scores(State(state), Path(game), Query(ScoresQuery::default()))
+                        .await
+                        .expect("Can't get scores!");
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .expect("Can't read body");
+                serde_json::from_slice::<Vec<Value>>(&bytes)
+                    .expect("Body is not a board")
+                    .iter()
+                    .map(|entry| entry["player_score"].as_i64().unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(board("alpha").await, vec![40, 30, 20]);
+        assert_eq!(
+            board("beta").await,
+            vec![2, 1],
+            "Board is trimmed by another game!"
+        );
+        assert!(board(DEFAULT_GAME).await.is_empty());
+
+        let invalid = submit("Not-A-Game", "Alpha", 50)
+            .await
+            .expect_err("Invalid game id is accepted!");
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+
+        for game in ["alpha", "beta"] {
+            flush_scores_db(&state.pool, game)
+                .await
+                .expect("Can't flush test db!");
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_restore_legacy_backup() {
+        let state = get_test_state(AppConfig::default()).await;
+        let legacy: Backup = serde_json::from_value(json!({
+            "version": LEGACY_BACKUP_VERSION,
+            "scores": [{
+                "player_name": "Dragon",
+                "player_score": 5,
+                "player_id": null,
+                "avatar": null,
+                "tier": null,
+                "client_version": null,
+                "play_count": null,
+                "posted_time": "2026-10-15 12:30:00.123"
+            }],
+            "archive": [{
+                "season_id": 2,
+                "player_name": "Veteran",
+                "player_score": 50,
+                "player_id": null,
+                "avatar": null,
+                "posted_time": "2026-10-14 08:00:00"
+            }],
+            "notice": {"message": null, "level": "info"}
+        }))
+        .expect("Can't read legacy backup!");
+
+        let Json(body) = restore(State(state.clone()), admin(), Json(legacy))
+            .await
+            .expect("Can't restore legacy backup!");
+        assert_eq!(body["scores"], 1);
+
+        let Json(restored) = backup(State(state.clone()), admin())
+            .await
+            .expect("Can't take backup!");
+        let utc = |time: &str| time.parse::<DateTime<Utc>>().ok();
+        assert_eq!(
+            restored.scores[0].posted_time,
+            utc("2026-10-15T12:30:00.123Z")
+        );
+        assert_eq!(restored.archive[0].posted_time, utc("2026-10-14T08:00:00Z"));
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+        sqlx::query!("TRUNCATE TABLE flappy_dragon_score_archive")
+            .execute(&state.pool)
+            .await
+            .expect("Can't clear archive!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_restore_invalid_rows() {
+        let state = get_test_state(AppConfig::default()).await;
+        let row = |name: &str, player_score: i32| BackupScore {
+            game_id: DEFAULT_GAME.to_string(),
+            player_name: name.to_string(),
+            player_score,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            client_version: None,
+            play_count: None,
+            posted_time: None,
+        };
+        let import = Backup {
+            version: BACKUP_VERSION,
+            scores: vec![
+                row("Boundary", 1_000_000),
+                row("Cheater", 1_000_001),
+                row("Dragon", 5),
+            ],
+            archive: Vec::new(),
+            notice: Notice::default(),
+        };
+
+        let refused = restore(State(state.clone()), admin(), Json(import.clone()))
+            .await
+            .expect_err("Backup with invalid row is restored!");
+        assert_eq!(refused.status(), StatusCode::BAD_REQUEST);
+        let kept = state.scores.get_scores().await.expect("Can't get scores!");
+        assert!(kept.is_empty(), "Refused restore has written rows!");
+
+        let skipping = state.clone().with_config(AppConfig {
+            restore_skip_invalid: true,
+            ..AppConfig::default()
+        });
+        let Json(body) = restore(State(skipping.clone()), admin(), Json(import))
+            .await
+            .expect("Can't restore valid rows!");
+        assert_eq!(body["scores"], 2);
+        assert_eq!(
+            body["rejected"],
+            json!([{"table": "scores", "row": 1, "fields": {"player_score": ["range"]}}])
+        );
+        let restored: Vec<i32> = skipping
+            .scores
+            .get_scores()
+            .await
+            .expect("Can't get scores!")
+            .iter()
+            .map(|score| score.player_score)
+            .collect();
+        assert_eq!(restored, vec![1_000_000, 5]);
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_backup_round_trip_legacy_name() {
+        let state = get_test_state(AppConfig {
+            restore_skip_invalid: true,
+            ..AppConfig::default()
+        })
+        .await;
+        sqlx::query!(
+            "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ('<script>x</script>', 40), ('Dragon', 30), ('Wyvern', 20)"
+        )
+        .execute(&state.pool)
+        .await
+        .expect("Can't seed legacy row!");
+
+        let Json(snapshot) = backup(State(state.clone()), admin())
+            .await
+            .expect("Can't take backup!");
+        // Through JSON like the upload, with a game id edited to one no route can serve
+        let mut uploaded = serde_json::to_value(&snapshot).expect("Can't serialize backup!");
+        uploaded["scores"][2]["game_id"] = json!("Bad Game");
+        let uploaded: Backup =
+            serde_json::from_value(uploaded).expect("Backup with legacy name is refused!");
+
+        let Json(body) = restore(State(state.clone()), admin(), Json(uploaded))
+            .await
+            .expect("Can't restore valid rows!");
+        assert_eq!(body["scores"], 1);
+        assert_eq!(
+            body["rejected"],
+            json!([
+                {"table": "scores", "row": 0, "fields": {"player_name": ["player_name"]}},
+                {"table": "scores", "row": 2, "fields": {"game_id": ["game_id"]}},
+            ])
+        );
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_get_scores_by_difficulty() {
+        let state = get_test_state(AppConfig::default()).await;
+        let record = |name: &str, score: i32, difficulty: Option<&str>| PlayerScore {
+            difficulty: difficulty.map(String::from),
+            ..test_score(name, score)
+        };
+        for score in [
+            record("Easy", 30, Some("easy")),
+            record("Hard", 20, Some("hard")),
+            record("Untagged", 10, None),
+            record("Harder", 5, Some("hard")),
+        ] {
+            let _ = commit_record(State(state.clone()), HeaderMap::new(), Sanitized(score))
+                .await
+                .expect("Can't commit record!");
+        }
+        let board = |difficulty: Option<&str>| {
+            let state = state.clone();
+            let difficulty = difficulty.map(String::from);
+            async move {
+                let response = get_scores(
+                    State(state),
+                    Query(ScoresQuery {
+                        difficulty,
+                        ..ScoresQuery::default()
+                    }),
+                )
+                .await
+                .expect("Can't get scores!");
+                response_json::<Vec<PlayerScore>>(response).await
+            }
+        };
+
+        assert_eq!(
+            board(Some("hard")).await,
+            vec![
+                record("Hard", 20, Some("hard")),
+                record("Harder", 5, Some("hard"))
+            ]
+        );
+        assert_eq!(board(Some("normal")).await, vec![]);
+        assert_eq!(board(None).await.len(), 4);
+
+        let unknown = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(record("Nightmare", 50, Some("nightmare"))),
+        )
+        .await
+        .expect_err("Unknown difficulty is accepted!");
+        assert_eq!(unknown.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_tier_ceiling() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            tier_max_scores: HashMap::from([("bronze".to_string(), 100)]),
+            echo_stored_score: true,
+            ..AppConfig::default()
+        });
+        let submit = |name: &str, score: i32, tier: Option<&str>| {
+            commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(PlayerScore {
+                    tier: tier.map(String::from),
+                    ..test_score(name, score)
+                }),
+            )
+        };
+
+        let Json(body) = submit("Rookie", 100, Some("bronze"))
+            .await
+            .expect("In-tier score is rejected!");
+        assert_eq!(body["record"]["tier"], "bronze");
+
+        let over = submit("Cheater", 101, Some("bronze"))
+            .await
+            .expect_err("Score above tier ceiling is accepted!");
+        assert_eq!(over.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let unknown = submit("Stranger", 5, Some("mythic"))
+            .await
+            .expect_err("Unknown tier is accepted!");
+        assert_eq!(unknown.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let _ = submit("Untiered", 5000, None)
+            .await
+            .expect("Score without tier is checked!");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_board_page() {
+        let state = get_test_state(AppConfig::default()).await;
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Dragon", 50)),
+        )
+        .await
+        .expect("Can't commit record!");
+        // Rows stored before the name rules can hold anything
+        sqlx::query!(
+            "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ('<script>x</script>', 40)"
+        )
+        .execute(&state.pool)
+        .await
+        .expect("Can't seed legacy row!");
+
+        let Html(page) = board_page(State(state.clone()))
+            .await
+            .expect("Can't render board page!");
+
+        assert!(page.contains("<td>Dragon</td><td>50</td>"));
+        assert!(page.contains("&lt;script&gt;x&lt;/script&gt;"));
+        assert!(!page.contains("<script>"), "Player name is not escaped!");
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    async fn test_login_errors_are_indistinguishable() {
+        let unknown_user = ServerError::from(LoginError::UnknownUser).into_response();
+        let wrong_password = ServerError::from(LoginError::WrongPassword).into_response();
+
+        assert_eq!(unknown_user.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(unknown_user.status(), wrong_password.status());
+        assert_eq!(
+            response_json::<Value>(unknown_user).await,
+            response_json::<Value>(wrong_password).await,
+            "Login error tells whether the user exists!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_name_pipeline() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            name_normalize: vec![NameStep::StripNonAscii, NameStep::Trim],
+            echo_stored_score: true,
+            ..AppConfig::default()
+        });
+
+        let Json(body) = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Ян Dragon", 10)),
+        )
+        .await
+        .expect("Can't commit record!");
+        assert_eq!(body["record"]["player_name"], "Dragon");
+
+        let emptied = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Дракон", 10)),
+        )
+        .await
+        .expect_err("Name emptied by the pipeline is stored!");
+        assert_eq!(emptied.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_hard_max_rows() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            hard_max_rows: 2,
+            ..AppConfig::default()
+        });
+        for (name, score) in [("Dragon", 5), ("Wyvern", 4)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Submission under the cap is rejected!");
+        }
+
+        let rejected = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Drake", 3)),
+        )
+        .await
+        .expect_err("Submission past the hard cap is accepted!");
+
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(state.scores.count().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_repeat_is_noop() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            duplicate_window_secs: 5,
+            ..AppConfig::default()
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer token".parse().unwrap());
+
+        for _ in 0..2 {
+            let Json(body) = commit_record(
+                State(state.clone()),
+                headers.clone(),
+                Sanitized(test_score("Dragon", 10)),
+            )
+            .await
+            .expect("Repeated submission is rejected!");
+            assert_eq!(body["status"], "Ok");
+        }
+        assert_eq!(
+            state.scores.count().await.unwrap(),
+            1,
+            "Repeated submission is stored twice!"
+        );
+
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Dragon", 10)),
+        )
+        .await
+        .expect("Can't commit record!");
+        assert_eq!(state.scores.count().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_commit_record_failed_submission_is_not_repeat() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            duplicate_window_secs: 5,
+            hard_max_rows: 0,
+            ..AppConfig::default()
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer token".parse().unwrap());
+
+        let failed = commit_record(
+            State(state.clone()),
+            headers.clone(),
+            Sanitized(test_score("Dragon", 10)),
+        )
+        .await
+        .expect_err("Submission past the hard cap is accepted!");
+        assert_eq!(failed.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Same recent submissions and store, with room on the board
+        let state = state.with_config(AppConfig {
+            duplicate_window_secs: 5,
+            ..AppConfig::default()
+        });
+        let _ = commit_record(
+            State(state.clone()),
+            headers,
+            Sanitized(test_score("Dragon", 10)),
+        )
+        .await
+        .expect("Retried submission is rejected!");
+        assert_eq!(
+            state.scores.count().await.unwrap(),
+            1,
+            "Retry of a failed submission is skipped!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_returns_flush() {
+        let state = mock_state(get_test_db_pool().await);
+        let _ = flush(
+            State(state.clone()),
+            Extension(AuthSubject("moderator".into())),
+            Query(FlushQuery::default()),
+        )
+        .await
+        .expect("Can't flush scores!");
+
+        let Json(events) = get_audit(State(state.clone()), Query(AuditQuery { limit: 50 }))
+            .await
+            .expect("Can't get audit events!");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, "flush");
+        assert_eq!(events[0].actor, "moderator");
+
+        let body = serde_json::to_value(&events[0]).unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(body["at"].as_str().unwrap()).is_ok());
+
+        let too_many = get_audit(
+            State(state.clone()),
+            Query(AuditQuery {
+                limit: AUDIT_MAX_LIMIT + 1,
+            }),
+        )
+        .await
+        .expect_err("Limit above the cap is accepted!");
+        assert_eq!(too_many.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_scores_cache_control() {
+        let cache_control = |config: AppConfig| async move {
+            let state = mock_state(get_test_db_pool().await).with_config(config);
+            let response = get_scores(State(state), Query(ScoresQuery::default()))
+                .await
+                .expect("Can't get scores!");
+            response.headers()[header::CACHE_CONTROL].clone()
+        };
+
+        assert_eq!(cache_control(AppConfig::default()).await, "no-store");
+        assert_eq!(
+            cache_control(AppConfig {
+                scores_max_age_secs: 5,
+                scores_stale_secs: 30,
+                ..AppConfig::default()
+            })
+            .await,
+            "public, max-age=5, stale-while-revalidate=30"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_gzip() {
+        use std::io::Read;
+
+        let state = mock_state(get_test_db_pool().await);
+        for (name, score) in [("Dragon", 5), ("Wyvern", 4), ("Drake", 3)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        let unzip = |response: Response| async move {
+            assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("Can't read body");
+            let mut unzipped = String::new();
+            flate2::read::GzDecoder::new(&body[..])
+                .read_to_string(&mut unzipped)
+                .expect("Body is not gzip!");
+            unzipped
+        };
+
+        let by_query = export_csv(
+            State(state.clone()),
+            HeaderMap::new(),
+            Query(ExportQuery {
+                compress: Some(crate::export::ExportCompression::Gzip),
+                ..ExportQuery::default()
+            }),
+        )
+        .await
+        .expect("Can't export!");
+        assert_eq!(
+            by_query.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"scores.csv.gz\""
+        );
+        assert_eq!(
+            unzip(by_query).await,
+            "player_name,player_score\nDragon,5\nWyvern,4\nDrake,3\n"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, "br, gzip;q=0.8".parse().unwrap());
+        let by_header = export_ndjson(State(state.clone()), headers, Query(ExportQuery::default()))
+            .await
+            .expect("Can't export!");
+        let ndjson = unzip(by_header).await;
+        assert_eq!(ndjson.lines().count(), 3);
+        assert!(ndjson.starts_with("{\"player_name\":\"Dragon\",\"player_score\":5}\n"));
+
+        let mut refused = HeaderMap::new();
+        refused.insert(header::ACCEPT_ENCODING, "gzip;q=0".parse().unwrap());
+        let plain = export_csv(State(state.clone()), refused, Query(ExportQuery::default()))
+            .await
+            .expect("Can't export!");
+        assert!(
+            !plain.headers().contains_key(header::CONTENT_ENCODING),
+            "Refused gzip is used!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_checksum() {
+        use sha2::{Digest, Sha256};
+
+        let state = mock_state(get_test_db_pool().await);
+        for (name, score) in [("Dragon", 5), ("Wyvern", 4)] {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score(name, score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        let query = || {
+            Query(ExportQuery {
+                checksum: true,
+                ..ExportQuery::default()
+            })
+        };
+        let body = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("Can't read body");
+            String::from_utf8(body.to_vec()).expect("Body is not UTF-8")
+        };
+        let split = |body: &str| {
+            let content = body
+                .trim_end()
+                .rsplit_once('\n')
+                .expect("No checksum line")
+                .0;
+            format!("{}\n", content)
+        };
+        let digest = |content: &str| hex::encode(Sha256::digest(content.as_bytes()));
+
+        let csv = export_csv(State(state.clone()), HeaderMap::new(), query())
+            .await
+            .expect("Can't export!");
+        let csv = body(csv).await;
+        let content = split(&csv);
+        assert_eq!(content, "player_name,player_score\nDragon,5\nWyvern,4\n");
+        assert_eq!(csv, format!("{}# sha256:{}\n", content, digest(&content)));
+
+        let ndjson = export_ndjson(State(state.clone()), HeaderMap::new(), query())
+            .await
+            .expect("Can't export!");
+        let ndjson = body(ndjson).await;
+        let content = split(&ndjson);
+        let last: Value = serde_json::from_str(ndjson.lines().last().unwrap()).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(last, json!({"sha256": digest(&content)}));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_export_csv_legacy_names() {
+        let state = get_test_state(AppConfig::default()).await;
+        sqlx::query!(
+            "INSERT INTO flappy_dragon_score (player_name, player_score) VALUES ('Smith, \"J\"', 3), ('=HYPERLINK(1)', 2), ('Dragon', 1)"
+        )
+        .execute(&state.pool)
+        .await
+        .expect("Can't seed legacy rows!");
+
+        let csv = export_csv(
+            State(state.clone()),
+            HeaderMap::new(),
+            Query(ExportQuery::default()),
+        )
+        .await
+        .expect("Can't export!");
+        let csv = axum::body::to_bytes(csv.into_body(), usize::MAX)
+            .await
+            .expect("Can't read body");
+        assert_eq!(
+            String::from_utf8(csv.to_vec()).expect("Body is not UTF-8"),
+            "player_name,player_score\n\"Smith, \"\"J\"\"\",3\n\"'=HYPERLINK(1)\",2\nDragon,1\n"
+        );
+
+        flush_scores_db(&state.pool, DEFAULT_GAME)
+            .await
+            .expect("Can't flush test db!");
+    }
+
+    #[tokio::test]
+    async fn test_export_anonymize() {
+        let state = mock_state(get_test_db_pool().await).with_config(AppConfig {
+            export_salt: Some("test_salt".to_string()),
+            ..AppConfig::default()
+        });
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Dragon", 5)),
+        )
+        .await
+        .expect("Can't commit record!");
+
+        let raw = export_csv(
+            State(state.clone()),
+            HeaderMap::new(),
+            Query(ExportQuery::default()),
+        )
+        .await
+        .expect("Can't export!");
+        let raw = axum::body::to_bytes(raw.into_body(), usize::MAX)
+            .await
+            .expect("Can't read body");
+        assert_eq!(raw, "player_name,player_score\nDragon,5\n");
+
+        let anonymized = export_ndjson(
+            State(state.clone()),
+            HeaderMap::new(),
+            Query(ExportQuery {
+                anonymize: true,
+                ..ExportQuery::default()
+            }),
+        )
+        .await
+        .expect("Can't export!");
+        let row: Value = response_json(anonymized).await;
+        assert_eq!(
+            row["player_name"],
+            crate::export::anonymize_name("Dragon", "test_salt")
+        );
+        assert_eq!(row["player_score"], 5);
+
+        let unsalted = export_csv(
+            State(mock_state(get_test_db_pool().await)),
+            HeaderMap::new(),
+            Query(ExportQuery {
+                anonymize: true,
+                ..ExportQuery::default()
+            }),
+        )
+        .await
+        .expect_err("Anonymized export without salt is accepted!");
+        assert_eq!(unsalted.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_get_cutoff() {
+        let state = mock_state(get_test_db_pool().await);
+        let cutoff = |state: AppState| async move {
+            let Json(body) = get_cutoff(State(state)).await.expect("Can't get cutoff!");
+            body["cutoff"].clone()
+        };
+
+        assert_eq!(cutoff(state.clone()).await, 1);
+        for player_score in 20..29 {
+            let _ = commit_record(
+                State(state.clone()),
+                HeaderMap::new(),
+                Sanitized(test_score("Mock", player_score)),
+            )
+            .await
+            .expect("Can't commit record!");
+        }
+        assert_eq!(
+            cutoff(state.clone()).await,
+            1,
+            "Under-full board has cutoff!"
+        );
+
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Mock", 40)),
+        )
+        .await
+        .expect("Can't commit record!");
+        assert_eq!(cutoff(state.clone()).await, 20);
+    }
+
+    #[tokio::test]
+    async fn test_handlers_with_mock_store() {
+        let pool = get_test_db_pool().await;
+        let store = Arc::new(MockScoreStore::default());
+        let state =
+            AppState::new(pool, set_up_jwt(&AppConfig::default())).with_score_store(store.clone());
+
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Mock", 5)),
+        )
+        .await
+        .expect("Can't commit record!");
+        let _ = commit_record(
+            State(state.clone()),
+            HeaderMap::new(),
+            Sanitized(test_score("Mock", 9)),
+        )
+        .await
+        .expect("Can't commit record!");
+
+        let response = get_scores(State(state.clone()), Query(ScoresQuery::default()))
+            .await
+            .expect("Can't get scores!");
+        assert_eq!(
+            response_json::<Vec<PlayerScore>>(response).await,
+            vec![test_score("Mock", 9), test_score("Mock", 5)]
+        );
+
+        let _ = flush(State(state.clone()), admin(), Query(FlushQuery::default()))
+            .await
+            .expect("Can't flush!");
+        assert!(
+            store
+                .get_scores()
+                .await
+                .expect("Can't get scores!")
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notice_set_get_clear() {
+        let state = mock_state(get_test_db_pool().await);
+        let Json(notice) = get_notice(State(state.clone())).await;
+        assert_eq!(notice, Notice::default());
+
+        let warning = Notice {
+            message: Some("Downtime at 22:00".to_string()),
+            level: NoticeLevel::Warn,
+        };
+        let _ = set_notice(State(state.clone()), admin(), Json(warning.clone()))
+            .await
+            .expect("Can't set notice!");
+        let Json(notice) = get_notice(State(state.clone())).await;
+        assert_eq!(notice, warning);
+
+        let too_long = Notice {
+            message: Some("x".repeat(281)),
+            ..Notice::default()
+        };
+        let rejected = set_notice(State(state.clone()), admin(), Json(too_long))
+            .await
+            .expect_err("Too long notice is accepted!");
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+        let _ = set_notice(State(state.clone()), admin(), Json(Notice::default()))
+            .await
+            .expect("Can't clear notice!");
+        let Json(notice) = get_notice(State(state.clone())).await;
+        assert_eq!(notice.message, None, "Notice is not cleared!");
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let pool = get_test_db_pool().await;
+        sqlx::query!("DELETE FROM users WHERE username = 'newplayer'")
+            .execute(&pool)
+            .await
+            .expect("Can't clear test user!");
+        let state = AppState::new(pool.clone(), set_up_jwt(&AppConfig::default()));
+        let request = |password: &str| RegisterRequest {
+            username: "newplayer".to_string(),
+            password: password.to_string(),
+        };
+
+        let (status, _) = register(State(state.clone()), Sanitized(request("longenough")))
+            .await
+            .expect("Can't register!");
+        assert_eq!(status, StatusCode::CREATED);
+        validate_user(&pool, "newplayer", "longenough")
+            .await
+            .expect("Registered user can't log in!");
+
+        let duplicate = register(State(state.clone()), Sanitized(request("otherpassword")))
+            .await
+            .expect_err("Taken username is registered!");
+        assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+
+        for (username, password) in [
+            ("newplayer", "short"),
+            ("ab", "longenough"),
+            ("new player", "longenough"),
+        ] {
+            let rejected = register(
+                State(state.clone()),
+                Sanitized(RegisterRequest {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                }),
+            )
+            .await
+            .expect_err("Invalid registration is accepted!");
+            assert_eq!(rejected.status(), StatusCode::BAD_REQUEST, "{}", username);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_cookie_mode() {
+        let pool = get_test_db_pool().await;
+        seed_test_user(&pool).await;
+        let login_request = || LoginRequest {
+            username: "user".to_string(),
+            password: "password".to_string(),
+        };
+
+        let cookie_state = AppState::new(pool.clone(), set_up_jwt(&AppConfig::default()))
+            .with_config(AppConfig {
+                auth_mode: AuthMode::Cookie,
+                ..AppConfig::default()
+            });
+        let response = login(State(cookie_state), Sanitized(login_request()))
+            .await
+            .expect("Can't login!");
+        let cookie = response
+            .headers()
+            .get(header::SET_COOKIE)
+            .expect("Cookie is not set!")
+            .to_str()
+            .expect("Cookie is not a string");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(cookie.starts_with("token="));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Secure"));
+        assert!(cookie.contains("SameSite=Strict"));
+
+        let header_state = AppState::new(pool, set_up_jwt(&AppConfig::default()));
+        let response = login(State(header_state), Sanitized(login_request()))
+            .await
+            .expect("Can't login!");
+        assert!(!response.headers().contains_key(header::SET_COOKIE));
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let pool = get_test_db_pool().await;
+        seed_test_user(&pool).await;
+        let state = AppState::new(pool, set_up_jwt(&AppConfig::default()));
+        let credentials = LoginRequest {
+            username: "user".to_string(),
+            password: "password".to_string(),
+        };
+
+        let response = login(State(state.clone()), Sanitized(credentials))
+            .await
+            .expect("Can't login!");
+        let tokens = response_json::<Value>(response).await;
+        let refresh_with = |token: &Value| {
+            refresh(
+                State(state.clone()),
+                Sanitized(RefreshRequest {
+                    refresh_token: token.as_str().expect("Token is missing!").to_string(),
+                }),
+            )
+        };
+
+        let refreshed = refresh_with(&tokens["refresh_token"])
+            .await
+            .expect("Can't refresh token!");
+        assert_eq!(refreshed.status(), StatusCode::OK);
+        assert!(response_json::<Value>(refreshed).await["token"].is_string());
+
+        let rejected = refresh_with(&tokens["token"])
+            .await
+            .expect_err("Access token is refreshed!");
+        assert_eq!(rejected.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,331 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    RealTime, db_access::health_db, security::TimeProvider, state::AppState,
+    timestamp::rfc3339_option,
+};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthStatus {
+    Ok,
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ServiceCheck {
+    pub status: HealthStatus,
+    // Critical services being down takes the whole node out of rotation
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl ServiceCheck {
+    pub fn up(critical: bool) -> Self {
+        ServiceCheck {
+            status: HealthStatus::Ok,
+            critical,
+            details: None,
+        }
+    }
+
+    pub fn down(critical: bool, details: impl Into<String>) -> Self {
+        ServiceCheck {
+            status: HealthStatus::Down,
+            critical,
+            details: Some(details.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ServiceHealth {
+    pub services: BTreeMap<String, ServiceCheck>,
+    // Clock of the node, reported with HEALTH_SERVER_TIME to spot skew across the fleet
+    pub server_time: Option<DateTime<Utc>>,
+}
+
+impl ServiceHealth {
+    pub fn add(&mut self, name: &str, check: ServiceCheck) -> &mut Self {
+        self.services.insert(name.to_owned(), check);
+        self
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        let mut status = HealthStatus::Ok;
+        for check in self.services.values() {
+            if check.status == HealthStatus::Ok {
+                continue;
+            }
+            if check.critical {
+                return HealthStatus::Down;
+            }
+            status = HealthStatus::Degraded;
+        }
+        status
+    }
+
+    // Degraded node is still able to serve requests, so it is kept in rotation
+    pub fn status_code(&self) -> StatusCode {
+        match self.status() {
+            HealthStatus::Ok | HealthStatus::Degraded => StatusCode::OK,
+            HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HealthReport<'a> {
+    status: HealthStatus,
+    services: &'a BTreeMap<String, ServiceCheck>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "rfc3339_option")]
+    server_time: Option<DateTime<Utc>>,
+}
+
+impl IntoResponse for ServiceHealth {
+    fn into_response(self) -> Response {
+        let report = HealthReport {
+            status: self.status(),
+            services: &self.services,
+            server_time: self.server_time,
+        };
+        (self.status_code(), Json(report)).into_response()
+    }
+}
+
+// Health is public, so error details are only logged and the report says what is down
+pub async fn collect_health(state: &AppState) -> ServiceHealth {
+    let mut health = ServiceHealth::default();
+    health.add("server", ServiceCheck::up(true));
+
+    let database = match health_db(&state.pool).await {
+        Ok(_) => ServiceCheck::up(true),
+        Err(e) => {
+            tracing::error!("Health check of database failed: {}", e);
+            ServiceCheck::down(true, "Database is unreachable")
+        }
+    };
+    health.add("database", database);
+
+    if let Some(read_pool) = &state.read_pool {
+        let replica = match health_db(read_pool).await {
+            Ok(_) => ServiceCheck::up(false),
+            Err(e) => {
+                tracing::warn!("Health check of read replica failed: {}", e);
+                ServiceCheck::down(false, "Read replica is unreachable, reading from primary")
+            }
+        };
+        health.add("read_replica", replica);
+    }
+
+    health.add("logging", check_log_dir(&state.config.log_dir));
+    health.server_time = state.config.health_server_time.then(|| RealTime.now());
+
+    health
+}
+
+// Node is kept out of rotation during warmup, even with every service up
+pub async fn collect_readiness(state: &AppState) -> ServiceHealth {
+    let mut health = collect_health(state).await;
+    let delay = Duration::from_secs(state.config.readiness_delay_secs);
+    health.add("warmup", check_warmup(state.started_at.elapsed(), delay));
+    health
+}
+
+fn check_warmup(elapsed: Duration, delay: Duration) -> ServiceCheck {
+    match delay.checked_sub(elapsed) {
+        Some(left) if !left.is_zero() => ServiceCheck::down(
+            true,
+            format!("Warming up, ready in {}s", left.as_secs_f32().ceil()),
+        ),
+        _ => ServiceCheck::up(true),
+    }
+}
+
+fn check_log_dir(path: &str) -> ServiceCheck {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_dir() && !meta.permissions().readonly() => ServiceCheck::up(false),
+        Ok(_) => ServiceCheck::down(false, "Log directory is not writable"),
+        Err(e) => {
+            tracing::warn!("Health check of log directory {} failed: {}", path, e);
+            ServiceCheck::down(false, "Log directory is not available")
+        }
+    }
+}
+
+#[cfg(test)]
+mod health_tests {
+    use super::*;
+    use crate::{config::AppConfig, connect_to_db, set_up_jwt};
+
+    #[tokio::test]
+    async fn test_health_all_ok() {
+        let mut health = ServiceHealth::default();
+        health
+            .add("server", ServiceCheck::up(true))
+            .add("database", ServiceCheck::up(true))
+            .add("logging", ServiceCheck::up(false));
+
+        assert_eq!(health.status(), HealthStatus::Ok);
+        assert_eq!(health.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_degraded() {
+        let mut health = ServiceHealth::default();
+        health
+            .add("database", ServiceCheck::up(true))
+            .add("read_replica", ServiceCheck::down(false, "Replica is down"));
+
+        assert_eq!(health.status(), HealthStatus::Degraded);
+        assert_eq!(health.status_code(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_down() {
+        let mut health = ServiceHealth::default();
+        health
+            .add("database", ServiceCheck::down(true, "Database is down"))
+            .add("read_replica", ServiceCheck::down(false, "Replica is down"));
+
+        assert_eq!(health.status(), HealthStatus::Down);
+        assert_eq!(health.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = health.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_health_report_body() {
+        let mut health = ServiceHealth::default();
+        health
+            .add("database", ServiceCheck::up(true))
+            .add("logging", ServiceCheck::down(false, "Not writable"));
+
+        let body = serde_json::to_value(HealthReport {
+            status: health.status(),
+            services: &health.services,
+            server_time: None,
+        })
+        .expect("Can't serialize report");
+
+        assert_eq!(body["status"], "DEGRADED");
+        assert_eq!(body["services"]["database"]["status"], "OK");
+        assert_eq!(body["services"]["logging"]["status"], "DOWN");
+        assert_eq!(body["services"]["logging"]["details"], "Not writable");
+        assert!(body.get("server_time").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_readiness_delay() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let mut state =
+            AppState::new(pool, set_up_jwt(&AppConfig::default())).with_config(AppConfig {
+                readiness_delay_secs: 30,
+                ..AppConfig::default()
+            });
+
+        let warming = collect_readiness(&state).await;
+        assert_eq!(warming.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            warming.services.get("warmup").map(|check| check.status),
+            Some(HealthStatus::Down)
+        );
+        assert_eq!(
+            collect_health(&state)
+                .await
+                .services
+                .get("database")
+                .map(|check| check.status),
+            Some(HealthStatus::Ok),
+            "Database is down, so warmup is not tested!"
+        );
+
+        state.started_at -= Duration::from_secs(31);
+        let ready = collect_readiness(&state).await;
+        assert_eq!(
+            ready.status_code(),
+            StatusCode::OK,
+            "Node is not ready after delay!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_health() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let state = AppState::new(pool, set_up_jwt(&AppConfig::default()));
+        let health = collect_health(&state).await;
+
+        assert_eq!(
+            health.services.get("database").map(|check| check.status),
+            Some(HealthStatus::Ok)
+        );
+        assert!(!health.services.contains_key("read_replica"));
+        assert_ne!(health.status(), HealthStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn test_collect_health_replica_down() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let replica = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(500))
+            .connect_lazy("postgres://nobody@127.0.0.1:1/nothing")
+            .expect("Can't set up replica pool");
+        let state = AppState::new(pool, set_up_jwt(&AppConfig::default()))
+            .with_read_pool(Some(replica))
+            .with_config(AppConfig {
+                log_dir: "/nonexistent/logs".to_string(),
+                ..AppConfig::default()
+            });
+        let health = collect_health(&state).await;
+
+        assert_eq!(health.status(), HealthStatus::Degraded);
+        assert_eq!(
+            health.services["read_replica"].details.as_deref(),
+            Some("Read replica is unreachable, reading from primary"),
+            "Replica error is exposed!"
+        );
+        assert_eq!(
+            health.services["logging"].details.as_deref(),
+            Some("Log directory is not available"),
+            "Log directory error is exposed!"
+        );
+        assert!(
+            state.scores.get_scores().await.is_ok(),
+            "Board is not read from primary!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_server_time() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let state = AppState::new(pool, set_up_jwt(&AppConfig::default())).with_config(AppConfig {
+            health_server_time: true,
+            ..AppConfig::default()
+        });
+
+        let response = collect_health(&state).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Can't read body");
+        let body: serde_json::Value = serde_json::from_slice(&body).expect("Body is not a JSON");
+
+        let server_time = body["server_time"]
+            .as_str()
+            .expect("Server time is missing!");
+        let server_time = DateTime::parse_from_rfc3339(server_time)
+            .expect("Server time is not RFC3339!")
+            .with_timezone(&Utc);
+        assert!((Utc::now() - server_time).num_seconds().abs() < 5);
+    }
+}
@@ -1,56 +1,74 @@
 use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
-use tower_http::limit::RequestBodyLimitLayer;
-use tower_http::timeout::TimeoutLayer;
 
-use axum::{
-    Router, middleware,
-    routing::{delete, get, post},
-};
 use tokio::net::TcpListener;
-use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 
+use config::*;
 use core::*;
 use db_access::*;
-use handlers::*;
+use routes::*;
 use security::*;
 use state::*;
 
+mod audit;
+mod backup;
+mod board_page;
+mod config;
 mod core;
 mod db_access;
 mod error;
+mod export;
+mod game;
+mod game_session;
 mod handlers;
+mod health;
+mod name_limit;
+mod notice;
+mod player_name;
+mod recent_submissions;
+mod revoked_tokens;
+mod routes;
+mod sanitize;
+mod score_schema;
 mod security;
+mod single_flight;
 mod state;
+mod store;
+mod timestamp;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    set_up_tracing();
-    let cors = set_up_cors();
-    let jwt_config = set_up_jwt();
-    let app_state = AppState::new(connect_to_db().await?, jwt_config.clone());
+    set_up_tracing(&AppConfig::log_dir_from_env());
+    let config = AppConfig::from_env();
+    check_jwt_secret(&config).inspect_err(|e| tracing::error!("{}! Server is shutdown!", e))?;
+    let jwt_config = set_up_jwt(&config);
+    let app_state = AppState::new(connect_to_db().await?, jwt_config.clone())
+        .with_read_pool(connect_to_read_db().await?)
+        .with_config(config.clone());
+    let app_state = match config.persist_notice {
+        true => {
+            let notice = get_notice_db(&app_state.pool).await?.unwrap_or_default();
+            app_state.with_notice(notice)
+        }
+        false => app_state,
+    };
 
     //// GOVERNORS ////
-    let public_governor = Arc::new(
-        GovernorConfigBuilder::default()
-            .per_second(60)
-            .burst_size(3)
-            .finish()
-            .expect("Unable to set up Governor! Server is shutdown!"),
-    );
-
-    let private_governor = Arc::new(
-        GovernorConfigBuilder::default()
-            .key_extractor(JwtKeyExtractor)
-            .per_second(60)
-            .burst_size(5)
-            .finish()
-            .expect("Unable to set up Governor! Server is shutdown!"),
-    );
+    let health_governor = set_up_health_governor();
+    let public_governor = set_up_public_governor();
+    let private_governor = set_up_private_governor(&config);
+    let private_ip_governor = set_up_private_ip_governor(&config);
+    let admin_governor = set_up_admin_governor(&config);
 
     //Getting RateLimiters of governors and cloning them to send to closure
+    let health_limiter = health_governor.limiter().clone();
     let public_limiter = public_governor.limiter().clone();
-    let private_limiter = private_governor.limiter().clone();
+    let private_limiter = private_governor
+        .as_ref()
+        .map(|governor| governor.limiter().clone());
+    let private_ip_limiter = private_ip_governor
+        .as_ref()
+        .map(|governor| governor.limiter().clone());
+    let admin_limiter = admin_governor.limiter().clone();
 
     //Creating additional tokio task to clean up RateLimiters storage once a day
     tokio::spawn(async move {
@@ -58,66 +76,88 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         loop {
             interval.tick().await;
             tracing::info!("Starting RateLimiters clean ups...");
+            health_limiter.retain_recent();
             public_limiter.retain_recent();
-            private_limiter.retain_recent();
+            if let Some(limiter) = &private_limiter {
+                limiter.retain_recent();
+            }
+            if let Some(limiter) = &private_ip_limiter {
+                limiter.retain_recent();
+            }
+            admin_limiter.retain_recent();
             tracing::info!("Finished RateLimiters clean ups!");
         }
     });
 
     //Creating additional tokio task to update Secret Every 24-hours
+    //Derived secret is shared by all nodes, it is rotated only by bumping JWT_KEY_VERSION
+    //Tokens signed with the old secret are refused anyway, so their revocations are dropped.
+    //RS256 tokens outlive the rotation, their revocations are kept
+    if config.jwt_passphrase.is_none() {
+        let revoked_tokens = app_state.revoked_tokens.clone();
+        let rsa_signed = config.jwt_private_key_path.is_some();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(86400));
+            loop {
+                interval.tick().await;
+                tracing::info!("Changing Secret");
+                rotate_secret(&jwt_config).await;
+                if !rsa_signed {
+                    revoked_tokens.clear();
+                }
+                tracing::info!("Finished changing Secret");
+            }
+        });
+    }
 
+    //Creating additional tokio task to log submission counters every hour
+    let submissions = app_state.submissions.clone();
+    let rate_limited = app_state.rate_limited.clone();
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(86400));
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
         loop {
             interval.tick().await;
-            tracing::info!("Changing Secret");
-            jwt_config.write().await.secret = generate_secret();
-            tracing::info!("Finished changing Secret");
+            let stats = submissions.snapshot();
+            tracing::info!(
+                total = stats.total,
+                recorded = stats.recorded,
+                rejected = stats.rejected,
+                rate_limited = rate_limited.load(std::sync::atomic::Ordering::Relaxed),
+                "Score submissions since start"
+            );
         }
     });
 
-    let public_governor_layer = GovernorLayer {
-        config: public_governor,
-    };
-
-    let private_governor_layer = GovernorLayer {
-        config: private_governor,
-    };
-
-    //// ROUTERS ////
-    let public_router = Router::new()
-        .route("/health", get(health_check))
-        .route("/login", post(login))
-        .layer(public_governor_layer);
-
-    let private_router = Router::new()
-        .route("/api/get-scores", get(get_scores))
-        .route("/api/set-score", post(commit_record))
-        .route("/api/flush", delete(flush))
-        .layer(middleware::from_fn({
-            let state = app_state.clone();
-
-            move |req, next| {
-                let state = state.clone();
-                jwt_middleware(req, next, state)
+    //Creating additional tokio task to clean up old log files every hour
+    if config.log_retention_days > 0 || config.log_max_total_mb > 0 {
+        let log_dir = config.log_dir.clone();
+        let max_age = (config.log_retention_days > 0)
+            .then(|| Duration::from_secs(config.log_retention_days * 86400));
+        let max_total_bytes =
+            (config.log_max_total_mb > 0).then(|| config.log_max_total_mb * 1024 * 1024);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                let deleted = clean_up_logs(&log_dir, max_age, max_total_bytes);
+                if deleted > 0 {
+                    tracing::info!("Deleted {} old log files", deleted);
+                }
             }
-        }))
-        .layer(private_governor_layer);
+        });
+    }
 
-    let app = Router::new()
-        .merge(public_router)
-        .merge(private_router)
-        .fallback(handler_404)
-        .layer(middleware::from_fn(set_up_security_headers))
-        .layer(TimeoutLayer::new(Duration::from_secs(10)))
-        .layer(RequestBodyLimitLayer::new(1024))
-        .layer(cors)
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO))
-                .on_response(DefaultOnResponse::new().level(tracing::Level::INFO)),
-        )
-        .with_state(app_state.clone());
+    //// ROUTERS ////
+    let app = app_router(
+        app_state,
+        Governors {
+            health: health_governor,
+            public: public_governor,
+            private: private_governor,
+            private_ip: private_ip_governor,
+            admin: admin_governor,
+        },
+    );
 
     let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
 
@@ -0,0 +1,118 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Tokens tracked at once, so a flood of tokens can't grow the map without limit
+const MAX_TRACKED_TOKENS: usize = 10_000;
+
+// Distinct player names submitted by each token within a window. Guards against one
+// token spraying the board with fake identities, which the rate limits don't catch
+#[derive(Debug, Default)]
+pub struct NameLimiter {
+    tokens: Mutex<HashMap<u64, TokenNames>>,
+}
+
+#[derive(Debug)]
+struct TokenNames {
+    window_start: Instant,
+    names: HashSet<String>,
+}
+
+impl NameLimiter {
+    // Returns false when the name would be one too many for the token in current window.
+    // Names already submitted by the token are always allowed
+    pub fn check(&self, token: u64, name: &str, max_names: usize, window: Duration) -> bool {
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().expect("Name limiter is poisoned!");
+
+        if !tokens.contains_key(&token) && tokens.len() >= MAX_TRACKED_TOKENS {
+            evict(&mut tokens, now, window);
+        }
+
+        let entry = tokens.entry(token).or_insert_with(|| TokenNames {
+            window_start: now,
+            names: HashSet::new(),
+        });
+        if now.duration_since(entry.window_start) >= window {
+            entry.window_start = now;
+            entry.names.clear();
+        }
+
+        if entry.names.contains(name) {
+            return true;
+        }
+        if entry.names.len() >= max_names {
+            return false;
+        }
+        entry.names.insert(name.to_owned());
+        true
+    }
+}
+
+// Dropping expired windows first, the oldest window if all of them are still running
+fn evict(tokens: &mut HashMap<u64, TokenNames>, now: Instant, window: Duration) {
+    tokens.retain(|_, entry| now.duration_since(entry.window_start) < window);
+    if tokens.len() < MAX_TRACKED_TOKENS {
+        return;
+    }
+    if let Some(oldest) = tokens
+        .iter()
+        .min_by_key(|(_, entry)| entry.window_start)
+        .map(|(token, _)| *token)
+    {
+        tokens.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod name_limit_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_name_limit_per_token() {
+        let limiter = NameLimiter::default();
+        let window = Duration::from_secs(60);
+
+        assert!(limiter.check(1, "Dragon", 2, window));
+        assert!(limiter.check(1, "Wyvern", 2, window));
+        assert!(
+            !limiter.check(1, "Drake", 2, window),
+            "Third name is allowed!"
+        );
+        assert!(limiter.check(1, "Dragon", 2, window));
+        assert!(
+            limiter.check(2, "Drake", 2, window),
+            "Other token is limited too!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_name_limit_window_expires() {
+        let limiter = NameLimiter::default();
+        let window = Duration::from_millis(20);
+
+        assert!(limiter.check(1, "Dragon", 1, window));
+        assert!(!limiter.check(1, "Wyvern", 1, window));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(
+            limiter.check(1, "Wyvern", 1, window),
+            "Names are kept after the window!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_name_limit_is_bounded() {
+        let limiter = NameLimiter::default();
+        let window = Duration::from_secs(60);
+
+        for token in 0..=MAX_TRACKED_TOKENS as u64 {
+            limiter.check(token, "Dragon", 1, window);
+        }
+
+        let tokens = limiter.tokens.lock().unwrap();
+        assert_eq!(tokens.len(), MAX_TRACKED_TOKENS);
+        assert!(tokens.contains_key(&(MAX_TRACKED_TOKENS as u64)));
+    }
+}
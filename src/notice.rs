@@ -0,0 +1,77 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use validator::Validate;
+
+pub const NOTICE_MAX_LEN: u64 = 280;
+
+// Banner shown by clients, e.g. ahead of planned downtime. No message means no banner
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate)]
+pub struct Notice {
+    // Blank messages are treated as cleared notice
+    #[serde(default, deserialize_with = "non_blank")]
+    #[validate(length(max = NOTICE_MAX_LEN))]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub level: NoticeLevel,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoticeLevel {
+    #[default]
+    Info,
+    Warn,
+}
+
+impl NoticeLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoticeLevel::Info => "info",
+            NoticeLevel::Warn => "warn",
+        }
+    }
+}
+
+impl FromStr for NoticeLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(NoticeLevel::Info),
+            "warn" => Ok(NoticeLevel::Warn),
+            other => Err(format!("Unknown notice level: {}", other)),
+        }
+    }
+}
+
+fn non_blank<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let message = Option::<String>::deserialize(deserializer)?;
+    Ok(message
+        .map(|message| message.trim().to_owned())
+        .filter(|message| !message.is_empty()))
+}
+
+#[cfg(test)]
+mod notice_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notice_serde() {
+        let notice: Notice =
+            serde_json::from_str(r#"{"message": "  Downtime at 22:00 ", "level": "warn"}"#)
+                .expect("Can't parse notice");
+        assert_eq!(notice.message.as_deref(), Some("Downtime at 22:00"));
+        assert_eq!(notice.level, NoticeLevel::Warn);
+
+        let blank: Notice =
+            serde_json::from_str(r#"{"message": "   "}"#).expect("Can't parse notice");
+        assert_eq!(blank, Notice::default(), "Blank message is not cleared!");
+
+        assert_eq!(
+            serde_json::to_value(Notice::default()).expect("Can't serialize notice"),
+            serde_json::json!({"message": null, "level": "info"})
+        );
+        assert!(serde_json::from_str::<Notice>(r#"{"level": "panic"}"#).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::ServerError;
+
+pub const NAME_MIN_LEN: usize = 3;
+pub const NAME_MAX_LEN: usize = 20;
+
+// Player name which is always normalized and valid, all name rules live here
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, sqlx::Type)]
+#[serde(transparent)]
+#[sqlx(transparent)]
+pub struct PlayerName(String);
+
+impl PlayerName {
+    pub fn try_new(raw: &str) -> Result<Self, ServerError> {
+        // Trimming and collapsing any internal whitespace into single spaces
+        let name = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let len = name.chars().count();
+        if !(NAME_MIN_LEN..=NAME_MAX_LEN).contains(&len) {
+            return Err(ServerError::Validation(format!(
+                "Player name must be from {} to {} characters long",
+                NAME_MIN_LEN, NAME_MAX_LEN
+            )));
+        }
+
+        if let Some(c) = name.chars().find(|c| !is_allowed_char(*c)) {
+            return Err(ServerError::Validation(format!(
+                "Player name contains not allowed character: {:?}",
+                c
+            )));
+        }
+
+        Ok(PlayerName(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Steps run in the given order, the result goes through all name rules again,
+    // so e.g. stripping non-ASCII letters can make the name too short
+    pub fn normalized(self, steps: &[NameStep]) -> Result<Self, ServerError> {
+        let name = steps.iter().fold(self.0, |name, step| step.apply(&name));
+        PlayerName::try_new(&name)
+    }
+
+    // Lowercased name, so players differing only by case are treated as the same one
+    pub fn folded(self) -> Self {
+        PlayerName(self.0.to_lowercase())
+    }
+}
+
+// Step of the configurable name pipeline, see NAME_NORMALIZE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameStep {
+    Trim,
+    CaseFold,
+    StripNonAscii,
+}
+
+impl NameStep {
+    fn apply(&self, name: &str) -> String {
+        match self {
+            NameStep::Trim => name.split_whitespace().collect::<Vec<_>>().join(" "),
+            NameStep::CaseFold => name.to_lowercase(),
+            NameStep::StripNonAscii => name.chars().filter(char::is_ascii).collect(),
+        }
+    }
+}
+
+impl FromStr for NameStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "trim" => Ok(NameStep::Trim),
+            "case-fold" => Ok(NameStep::CaseFold),
+            "strip-non-ascii" => Ok(NameStep::StripNonAscii),
+            other => Err(format!("Unknown name step: {}", other)),
+        }
+    }
+}
+
+fn is_allowed_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.')
+}
+
+impl FromStr for PlayerName {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PlayerName::try_new(s)
+    }
+}
+
+impl fmt::Display for PlayerName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for PlayerName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        PlayerName::try_new(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod player_name_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_player_name_trimming() {
+        let name = PlayerName::try_new("   Dragon  ").expect("Name is rejected!");
+        assert_eq!(name.as_str(), "Dragon");
+    }
+
+    #[tokio::test]
+    async fn test_player_name_whitespace_collapsing() {
+        let name = PlayerName::try_new("Big \t  Red\n Dragon").expect("Name is rejected!");
+        assert_eq!(name.as_str(), "Big Red Dragon");
+        assert_eq!(name.folded().as_str(), "big red dragon");
+    }
+
+    #[tokio::test]
+    async fn test_player_name_rejection() {
+        assert!(
+            PlayerName::try_new("Al").is_err(),
+            "Short name is accepted!"
+        );
+        assert!(
+            PlayerName::try_new("  A \t b  ").is_ok_and(|name| name.as_str() == "A b"),
+            "Length is not checked after normalization!"
+        );
+        assert!(
+            PlayerName::try_new("A_very_long_dragon_name").is_err(),
+            "Long name is accepted!"
+        );
+        assert!(PlayerName::try_new("Drop;Table").is_err());
+        assert!(PlayerName::try_new("Bad\u{0000}Name").is_err());
+        assert!(
+            PlayerName::try_new("Дракон").is_ok(),
+            "Unicode letters are rejected!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_player_name_pipeline() {
+        let name = || PlayerName::try_new("Élise Dragon").expect("Name is rejected!");
+
+        assert_eq!(
+            name().normalized(&[NameStep::Trim]).unwrap().as_str(),
+            "Élise Dragon"
+        );
+        assert_eq!(
+            name()
+                .normalized(&[NameStep::StripNonAscii, NameStep::CaseFold])
+                .unwrap()
+                .as_str(),
+            "lise dragon"
+        );
+        assert_eq!(
+            PlayerName::try_new("Ян Lin")
+                .unwrap()
+                .normalized(&[NameStep::StripNonAscii, NameStep::Trim])
+                .unwrap()
+                .as_str(),
+            "Lin",
+            "Stripped name is not trimmed!"
+        );
+        assert!(
+            PlayerName::try_new("Дракон")
+                .unwrap()
+                .normalized(&[NameStep::StripNonAscii])
+                .is_err(),
+            "Name emptied by the pipeline is accepted!"
+        );
+        assert_eq!("Case-Fold".parse(), Ok(NameStep::CaseFold));
+        assert!("uppercase".parse::<NameStep>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_player_name_serde() {
+        let name: PlayerName =
+            serde_json::from_str(r#""  Red   Dragon ""#).expect("Can't deserialize name");
+        assert_eq!(name.as_str(), "Red Dragon");
+        assert_eq!(
+            serde_json::to_string(&name).expect("Can't serialize name"),
+            r#""Red Dragon""#
+        );
+        assert!(serde_json::from_str::<PlayerName>(r#""<>""#).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Submissions tracked at once, so a flood of tokens can't grow the map without limit
+const MAX_TRACKED_SUBMISSIONS: usize = 10_000;
+
+// Token, player name and score of a submission
+type SubmissionKey = (u64, String, i32);
+
+// Submissions seen within a short window, so a double-tapped submit is written only once.
+// Accidental repeats only - spam is left to the rate limits
+#[derive(Debug, Default)]
+pub struct RecentSubmissions {
+    seen: Mutex<HashMap<SubmissionKey, Instant>>,
+}
+
+impl RecentSubmissions {
+    // Returns true when the same submission came from the token within the window.
+    // Window runs from the first one, so repeats don't extend it
+    pub fn is_repeat(&self, token: u64, name: &str, score: i32, window: Duration) -> bool {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().expect("Recent submissions are poisoned!");
+
+        let key = (token, name.to_owned(), score);
+        if let Some(first_seen) = seen.get(&key) {
+            if now.duration_since(*first_seen) < window {
+                return true;
+            }
+        } else if seen.len() >= MAX_TRACKED_SUBMISSIONS {
+            evict(&mut seen, now, window);
+        }
+
+        seen.insert(key, now);
+        false
+    }
+
+    // Takes back a submission that failed to be stored, so a retry within the window is written
+    pub fn forget(&self, token: u64, name: &str, score: i32) {
+        self.seen
+            .lock()
+            .expect("Recent submissions are poisoned!")
+            .remove(&(token, name.to_owned(), score));
+    }
+}
+
+// Dropping expired submissions first, the oldest one if all of them are still fresh
+fn evict(seen: &mut HashMap<SubmissionKey, Instant>, now: Instant, window: Duration) {
+    seen.retain(|_, first_seen| now.duration_since(*first_seen) < window);
+    if seen.len() < MAX_TRACKED_SUBMISSIONS {
+        return;
+    }
+    if let Some(oldest) = seen
+        .iter()
+        .min_by_key(|(_, first_seen)| **first_seen)
+        .map(|(key, _)| key.clone())
+    {
+        seen.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod recent_submissions_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_repeat_within_window() {
+        let recent = RecentSubmissions::default();
+        let window = Duration::from_millis(30);
+
+        assert!(!recent.is_repeat(1, "Dragon", 10, window));
+        assert!(recent.is_repeat(1, "Dragon", 10, window));
+        assert!(
+            !recent.is_repeat(1, "Dragon", 11, window),
+            "Other score is a repeat!"
+        );
+        assert!(
+            !recent.is_repeat(2, "Dragon", 10, window),
+            "Other token is a repeat!"
+        );
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert!(
+            !recent.is_repeat(1, "Dragon", 10, window),
+            "Submission is a repeat after the window!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forgotten_submission_is_not_repeat() {
+        let recent = RecentSubmissions::default();
+        let window = Duration::from_secs(60);
+
+        assert!(!recent.is_repeat(1, "Dragon", 10, window));
+        recent.forget(1, "Dragon", 10);
+        assert!(
+            !recent.is_repeat(1, "Dragon", 10, window),
+            "Forgotten submission is a repeat!"
+        );
+        assert!(recent.is_repeat(1, "Dragon", 10, window));
+    }
+
+    #[tokio::test]
+    async fn test_recent_submissions_are_bounded() {
+        let recent = RecentSubmissions::default();
+        let window = Duration::from_secs(60);
+
+        for token in 0..=MAX_TRACKED_SUBMISSIONS as u64 {
+            recent.is_repeat(token, "Dragon", 10, window);
+        }
+
+        let seen = recent.seen.lock().unwrap();
+        assert_eq!(seen.len(), MAX_TRACKED_SUBMISSIONS);
+        assert!(seen.contains_key(&(MAX_TRACKED_SUBMISSIONS as u64, "Dragon".to_string(), 10)));
+    }
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::security::TimeProvider;
+
+// Ids of tokens revoked by logout with their expiry. Expired tokens are refused anyway,
+// so their ids are dropped and the set holds only tokens that are still alive
+#[derive(Debug, Default)]
+pub struct RevokedTokens {
+    tokens: Mutex<HashMap<String, usize>>,
+}
+
+impl RevokedTokens {
+    pub fn revoke(&self, jti: String, exp: usize, time: &impl TimeProvider) {
+        let now = time.now().timestamp();
+        let mut tokens = self.tokens.lock().expect("Revoked tokens are poisoned!");
+
+        tokens.retain(|_, exp| *exp as i64 > now);
+        tokens.insert(jti, exp);
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        self.tokens
+            .lock()
+            .expect("Revoked tokens are poisoned!")
+            .contains_key(jti)
+    }
+
+    pub fn clear(&self) {
+        self.tokens
+            .lock()
+            .expect("Revoked tokens are poisoned!")
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod revoked_tokens_tests {
+    use super::*;
+    use crate::security::MockTime;
+
+    #[test]
+    fn test_expired_tokens_are_dropped() {
+        let revoked = RevokedTokens::default();
+        let now = MockTime.now().timestamp() as usize;
+
+        revoked.revoke("expired".into(), now - 1, &MockTime);
+        revoked.revoke("alive".into(), now + 60, &MockTime);
+
+        assert!(revoked.is_revoked("alive"));
+        assert!(
+            !revoked.is_revoked("expired"),
+            "Expired token is kept in the set!"
+        );
+        assert_eq!(revoked.tokens.lock().unwrap().len(), 1);
+    }
+}
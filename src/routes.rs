@@ -0,0 +1,1069 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Router,
+    error_handling::HandleErrorLayer,
+    middleware,
+    routing::{delete, get, patch, post, put},
+};
+use governor::middleware::{NoOpMiddleware, StateInformationMiddleware};
+use tower::{ServiceBuilder, limit::GlobalConcurrencyLimitLayer, util::option_layer};
+use tower_governor::{GovernorLayer, governor::GovernorConfig, key_extractor::PeerIpKeyExtractor};
+use tower_http::{
+    limit::RequestBodyLimitLayer,
+    timeout::TimeoutLayer,
+    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+};
+
+use crate::{
+    config::AppConfig,
+    core::{
+        check_content_length, handle_overload, log_rate_limited, log_slow_requests,
+        pool_diagnostics, rate_limit_headers, set_up_cors, set_up_trusted_proxies,
+    },
+    handlers::{
+        backup, board_page, commit_game_record, commit_record, delete_players, export_csv,
+        export_ndjson, flush, flush_game, get_audit, get_cutoff, get_game_ranked_scores,
+        get_game_scores, get_notice, get_player_best, get_ranked_scores, get_score_rank,
+        get_score_schema, get_scores, get_season_scores, handler_404, health_check, introspect,
+        liveness_check, login, logout, readiness_check, refresh, register, restore, set_notice,
+        start_game, update_jwt_validation, validate_batch,
+    },
+    security::{
+        ClientIpKeyExtractor, JwtKeyExtractor, RealTime, check_origin, jwt_middleware,
+        require_admin, require_api_key, require_https, require_user_agent, set_up_security_headers,
+    },
+    state::AppState,
+};
+
+// Largest request body in bytes accepted by any route except restore
+pub const BODY_LIMIT: usize = 1024;
+// Restore takes the whole board in one body
+pub const BACKUP_BODY_LIMIT: usize = 10 * 1024 * 1024;
+// Full batch of tokens for validation with room to spare
+pub const GATEWAY_BODY_LIMIT: usize = 64 * 1024;
+
+pub type IpGovernorConfig = GovernorConfig<PeerIpKeyExtractor, NoOpMiddleware>;
+pub type JwtGovernorConfig = GovernorConfig<JwtKeyExtractor, NoOpMiddleware>;
+pub type PrivateGovernorConfig = GovernorConfig<JwtKeyExtractor, StateInformationMiddleware>;
+pub type ClientIpGovernorConfig = GovernorConfig<ClientIpKeyExtractor, StateInformationMiddleware>;
+
+pub struct Governors {
+    pub health: Arc<IpGovernorConfig>,
+    pub public: Arc<IpGovernorConfig>,
+    // Private routes are limited by token, by client IP or by both, see RATE_LIMIT_KEY
+    pub private: Option<Arc<PrivateGovernorConfig>>,
+    pub private_ip: Option<Arc<ClientIpGovernorConfig>>,
+    pub admin: Arc<JwtGovernorConfig>,
+}
+
+// CORS layer is outside of everything that can fail, so error responses carry CORS headers too
+pub fn app_router(state: AppState, governors: Governors) -> Router {
+    let config = state.config.clone();
+
+    let router = Router::new()
+        .merge(public_router(
+            governors.health,
+            governors.public,
+            config.board_page_enabled,
+            config.score_schema_enabled,
+        ))
+        .merge(private_router(
+            state.clone(),
+            governors.private,
+            governors.private_ip,
+        ))
+        .fallback(handler_404);
+
+    limit_body(router, BODY_LIMIT, config.early_length_check)
+        .merge(admin_router(state.clone(), governors.admin))
+        .merge(gateway_router(&config))
+        .layer(option_layer(config.rate_limit_events.then(|| {
+            let state = state.clone();
+            middleware::from_fn(move |req, next| log_rate_limited(req, next, state.clone()))
+        })))
+        .layer(option_layer(config.debug_diagnostics.then(|| {
+            let pool = state.pool.clone();
+            middleware::from_fn(move |req, next| pool_diagnostics(req, next, pool.clone()))
+        })))
+        .layer(middleware::from_fn({
+            let threshold = Duration::from_millis(config.slow_request_ms);
+            move |req, next| log_slow_requests(req, next, threshold)
+        }))
+        .layer(option_layer(
+            config
+                .security_headers_enabled
+                .then(|| middleware::from_fn(set_up_security_headers)),
+        ))
+        .layer(option_layer(config.enforce_origin.then(|| {
+            let allowed_origins = Arc::new(config.cors_allowed_origins.clone());
+            middleware::from_fn(move |req, next| check_origin(req, next, allowed_origins.clone()))
+        })))
+        .layer(option_layer(config.require_https.then(|| {
+            let trusted_proxies = Arc::new(set_up_trusted_proxies(&config));
+            middleware::from_fn(move |req, next| require_https(req, next, trusted_proxies.clone()))
+        })))
+        .layer(TimeoutLayer::new(Duration::from_secs(10)))
+        // Shedding load before the timeout starts, so waiting requests don't pile up.
+        // Router layers every route separately, so the limit has to share one semaphore
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_overload))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(config.max_concurrency)),
+        )
+        // Preflights are answered here and never reach the governors or use up the budget
+        .layer(set_up_cors(&config))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO))
+                .on_response(DefaultOnResponse::new().level(tracing::Level::INFO)),
+        )
+        .with_state(state)
+}
+
+// Health probes and logins are limited separately, so monitoring never throttles logins.
+// Notice, cutoff, board page and score schema are cheap reads polled by clients,
+// so they share the lenient governor
+pub fn public_router(
+    health_governor: Arc<IpGovernorConfig>,
+    login_governor: Arc<IpGovernorConfig>,
+    board_page_enabled: bool,
+    score_schema_enabled: bool,
+) -> Router<AppState> {
+    let mut health_router = Router::new()
+        .route("/health", get(health_check))
+        .route("/livez", get(liveness_check))
+        .route("/readyz", get(readiness_check))
+        .route("/api/notice", get(get_notice))
+        .route("/api/cutoff", get(get_cutoff));
+    if board_page_enabled {
+        health_router = health_router.route("/board", get(board_page));
+    }
+    if score_schema_enabled {
+        health_router = health_router.route("/api/schema/score", get(get_score_schema));
+    }
+    let health_router = health_router.layer(GovernorLayer {
+        config: health_governor,
+    });
+
+    let login_router = Router::new()
+        .route("/login", post(login))
+        .route("/register", post(register))
+        .route("/refresh", post(refresh))
+        .layer(GovernorLayer {
+            config: login_governor,
+        });
+
+    health_router.merge(login_router)
+}
+
+// User-Agent check covers only writes, so probes and read-only scripts are not affected
+pub fn private_router(
+    state: AppState,
+    governor: Option<Arc<PrivateGovernorConfig>>,
+    ip_governor: Option<Arc<ClientIpGovernorConfig>>,
+) -> Router<AppState> {
+    let expose_budget = state.config.rate_limit_headers;
+    let mut write_router = Router::new()
+        .route("/api/set-score", post(commit_record))
+        .route(
+            "/api/flush",
+            delete(flush).layer(middleware::from_fn(require_admin)),
+        )
+        .route(
+            "/api/scores/delete",
+            post(delete_players).layer(middleware::from_fn(require_admin)),
+        )
+        .route(
+            "/api/notice",
+            put(set_notice).layer(middleware::from_fn(require_admin)),
+        )
+        .route("/api/{game_id}/set-score", post(commit_game_record))
+        .route(
+            "/api/{game_id}/flush",
+            delete(flush_game).layer(middleware::from_fn(require_admin)),
+        );
+    if state.config.game_sessions {
+        write_router = write_router.route("/api/game/start", post(start_game));
+    }
+    let write_router = write_router.layer(option_layer(
+        state
+            .config
+            .require_user_agent
+            .then(|| middleware::from_fn(require_user_agent)),
+    ));
+
+    let mut read_router = Router::new()
+        .route("/api/get-scores", get(get_scores))
+        .route("/api/get-ranked-scores", get(get_ranked_scores))
+        .route("/api/player/{name}/best", get(get_player_best))
+        .route("/api/{game_id}/get-scores", get(get_game_scores))
+        .route(
+            "/api/{game_id}/get-ranked-scores",
+            get(get_game_ranked_scores),
+        );
+    if state.config.expose_score_ids {
+        read_router = read_router.route("/api/scores/{id}/rank", get(get_score_rank));
+    }
+
+    read_router
+        .route("/api/seasons/{id}/scores", get(get_season_scores))
+        .route(
+            "/api/export.csv",
+            get(export_csv).layer(middleware::from_fn(require_admin)),
+        )
+        .route(
+            "/api/export.ndjson",
+            get(export_ndjson).layer(middleware::from_fn(require_admin)),
+        )
+        .route(
+            "/api/introspect",
+            post(introspect).layer(middleware::from_fn(require_admin)),
+        )
+        .route("/api/logout", post(logout))
+        .route(
+            "/api/audit",
+            get(get_audit).layer(middleware::from_fn(require_admin)),
+        )
+        .merge(write_router)
+        .layer(middleware::from_fn(move |req, next| {
+            let state = state.clone();
+            jwt_middleware(req, next, state, RealTime)
+        }))
+        .layer(option_layer(
+            governor.map(|config| GovernorLayer { config }),
+        ))
+        .layer(option_layer(
+            ip_governor.map(|config| GovernorLayer { config }),
+        ))
+        .layer(middleware::from_fn(move |req, next| {
+            rate_limit_headers(req, next, expose_budget)
+        }))
+}
+
+// Admin routes carry their own body limit, so only they can take a full backup.
+// JWT validation changes are applied to the live config, they are lost on restart
+pub fn admin_router(state: AppState, governor: Arc<JwtGovernorConfig>) -> Router<AppState> {
+    let router = Router::new()
+        .route("/api/restore", post(restore))
+        .route("/api/jwt-validation", patch(update_jwt_validation))
+        .layer(option_layer(
+            state
+                .config
+                .require_user_agent
+                .then(|| middleware::from_fn(require_user_agent)),
+        ))
+        .route("/api/backup", get(backup));
+
+    limit_body(router, BACKUP_BODY_LIMIT, state.config.early_length_check)
+        .layer(middleware::from_fn(require_admin))
+        .layer(middleware::from_fn(move |req, next| {
+            let state = state.clone();
+            jwt_middleware(req, next, state, RealTime)
+        }))
+        .layer(GovernorLayer { config: governor })
+}
+
+// Internal routes of the edge gateway, present only when GATEWAY_API_KEY is set.
+// Gateway sends traffic of many players, so it is not rate limited like a single client
+pub fn gateway_router(config: &AppConfig) -> Router<AppState> {
+    let Some(api_key) = config.gateway_api_key.clone().map(Arc::new) else {
+        return Router::new();
+    };
+
+    let router = Router::new().route("/api/validate-batch", post(validate_batch));
+    limit_body(router, GATEWAY_BODY_LIMIT, config.early_length_check).layer(middleware::from_fn(
+        move |req, next| require_api_key(req, next, api_key.clone()),
+    ))
+}
+
+fn limit_body(
+    router: Router<AppState>,
+    limit: usize,
+    early_length_check: bool,
+) -> Router<AppState> {
+    router
+        .layer(RequestBodyLimitLayer::new(limit))
+        .layer(option_layer(early_length_check.then(|| {
+            middleware::from_fn(move |req, next| check_content_length(req, next, limit))
+        })))
+}
+
+#[cfg(test)]
+mod routes_tests {
+    use super::*;
+    use crate::{
+        RealTime,
+        config::{AppConfig, RateLimitKey},
+        db_access::{get_test_db_pool, seed_test_user},
+        security::{ADMIN_ROLE, JwtConfig, generate_jwt, generate_jwt_pair},
+        set_up_admin_governor, set_up_health_governor, set_up_jwt, set_up_private_governor,
+        set_up_private_ip_governor, set_up_public_governor,
+    };
+    use axum::{
+        body::Body,
+        extract::ConnectInfo,
+        http::{Request, StatusCode},
+    };
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    fn request_from(method: &str, uri: &str, body: Body) -> Request<Body> {
+        let mut request = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .expect("Can't create request");
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+        request
+    }
+
+    fn login_request() -> Request<Body> {
+        request_from(
+            "POST",
+            "/login",
+            Body::from(r#"{"username": "user", "password": "password"}"#),
+        )
+    }
+
+    fn authorized(
+        mut request: Request<Body>,
+        signing_config: &JwtConfig,
+        role: &str,
+    ) -> Request<Body> {
+        let token = generate_jwt("player", signing_config, role, &RealTime).unwrap();
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    fn test_governors() -> Governors {
+        Governors {
+            health: set_up_health_governor(),
+            public: set_up_public_governor(),
+            private: set_up_private_governor(&AppConfig::default()),
+            private_ip: None,
+            admin: set_up_admin_governor(&AppConfig::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cors_headers_on_errors() {
+        let pool = get_test_db_pool().await;
+        let app = app_router(
+            AppState::new(pool, set_up_jwt(&AppConfig::default())),
+            test_governors(),
+        );
+
+        let mut request = request_from("GET", "/api/get-scores", Body::empty());
+        request
+            .headers_mut()
+            .insert("Origin", "http://0.0.0.0:3000".parse().unwrap());
+        request
+            .headers_mut()
+            .insert("Authorization", "Bearer garbage".parse().unwrap());
+
+        let res = app.oneshot(request).await.expect("Can't get response");
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            res.headers()
+                .get("Access-Control-Allow-Origin")
+                .expect("CORS header is missing on error response!"),
+            "http://0.0.0.0:3000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_vary_origin() {
+        let pool = get_test_db_pool().await;
+        let app = app_router(
+            AppState::new(pool, set_up_jwt(&AppConfig::default())),
+            test_governors(),
+        );
+
+        for origin in ["http://0.0.0.0:3000", "https://evil.example"] {
+            let mut request = request_from("GET", "/api/get-scores", Body::empty());
+            request
+                .headers_mut()
+                .insert("Origin", origin.parse().unwrap());
+
+            let res = app
+                .clone()
+                .oneshot(request)
+                .await
+                .expect("Can't get response");
+
+            let vary: Vec<String> = res
+                .headers()
+                .get_all("Vary")
+                .iter()
+                .flat_map(|value| value.to_str().expect("Vary is not a string").split(','))
+                .map(|name| name.trim().to_lowercase())
+                .collect();
+            assert!(
+                vary.contains(&"origin".to_string()),
+                "Vary: Origin is missing for {}: {:?}",
+                origin,
+                vary
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_skips_governor() {
+        let pool = get_test_db_pool().await;
+        let app = app_router(
+            AppState::new(pool, set_up_jwt(&AppConfig::default())),
+            test_governors(),
+        );
+
+        for _ in 0..10 {
+            let mut preflight = request_from("OPTIONS", "/api/get-scores", Body::empty());
+            let headers = preflight.headers_mut();
+            headers.insert("Origin", "http://0.0.0.0:3000".parse().unwrap());
+            headers.insert("Access-Control-Request-Method", "GET".parse().unwrap());
+            headers.insert(
+                "Access-Control-Request-Headers",
+                "authorization".parse().unwrap(),
+            );
+            let res = app
+                .clone()
+                .oneshot(preflight)
+                .await
+                .expect("Can't get response");
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let mut request = request_from("GET", "/api/get-scores", Body::empty());
+        request
+            .headers_mut()
+            .insert("Authorization", "Bearer garbage".parse().unwrap());
+        let res = app.oneshot(request).await.expect("Can't get response");
+        assert_eq!(
+            res.status(),
+            StatusCode::UNAUTHORIZED,
+            "Preflights used up the rate limit!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notice_is_public_only_for_reading() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let signing_config = jwt_config.read().await.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+        let put_request = || {
+            request_from(
+                "PUT",
+                "/api/notice",
+                Body::from(r#"{"message": "Hacked", "level": "warn"}"#),
+            )
+        };
+
+        let get_res = app
+            .clone()
+            .oneshot(request_from("GET", "/api/notice", Body::empty()))
+            .await
+            .expect("Can't get response");
+        let mut garbage_request = put_request();
+        garbage_request
+            .headers_mut()
+            .insert("Authorization", "Bearer garbage".parse().unwrap());
+        let garbage_res = app
+            .clone()
+            .oneshot(garbage_request)
+            .await
+            .expect("Can't get response");
+        let player_res = app
+            .oneshot(authorized(put_request(), &signing_config, "default"))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(get_res.status(), StatusCode::OK);
+        assert_eq!(garbage_res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            player_res.status(),
+            StatusCode::FORBIDDEN,
+            "Notice is set by a player!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_toggle() {
+        let pool = get_test_db_pool().await;
+        let app_with = |security_headers_enabled: bool| {
+            let config = AppConfig {
+                security_headers_enabled,
+                ..AppConfig::default()
+            };
+            app_router(
+                AppState::new(pool.clone(), set_up_jwt(&config)).with_config(config),
+                test_governors(),
+            )
+        };
+
+        let enabled_res = app_with(true)
+            .oneshot(request_from("GET", "/missing", Body::empty()))
+            .await
+            .expect("Can't get response");
+        let disabled_res = app_with(false)
+            .oneshot(request_from("GET", "/missing", Body::empty()))
+            .await
+            .expect("Can't get response");
+
+        assert!(
+            enabled_res
+                .headers()
+                .contains_key("Content-Security-Policy")
+        );
+        assert!(
+            !disabled_res
+                .headers()
+                .contains_key("Content-Security-Policy"),
+            "Security headers are set while disabled!"
+        );
+        assert!(!disabled_res.headers().contains_key("X-Frame-Options"));
+    }
+
+    #[tokio::test]
+    async fn test_health_does_not_throttle_login() {
+        let pool = get_test_db_pool().await;
+        seed_test_user(&pool).await;
+        let app = public_router(
+            set_up_health_governor(),
+            set_up_public_governor(),
+            false,
+            false,
+        )
+        .with_state(AppState::new(pool, set_up_jwt(&AppConfig::default())));
+
+        for _ in 0..10 {
+            let res = app
+                .clone()
+                .oneshot(request_from("GET", "/health", Body::empty()))
+                .await
+                .expect("Can't get response");
+            assert_ne!(
+                res.status(),
+                StatusCode::TOO_MANY_REQUESTS,
+                "Health is throttled!"
+            );
+        }
+
+        let login_res = app
+            .clone()
+            .oneshot(login_request())
+            .await
+            .expect("Can't get response");
+        assert_eq!(
+            login_res.status(),
+            StatusCode::OK,
+            "Login is throttled by health!"
+        );
+
+        // Exhausting login budget shouldn't affect health probes
+        for _ in 0..3 {
+            let _ = app.clone().oneshot(login_request()).await;
+        }
+        let throttled_login = app
+            .clone()
+            .oneshot(login_request())
+            .await
+            .expect("Can't get response");
+        let health_res = app
+            .oneshot(request_from("GET", "/health", Body::empty()))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(throttled_login.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_ne!(health_res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_backup_requires_admin_role() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let secret = jwt_config.read().await.secret.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+        let token = generate_jwt(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .unwrap();
+
+        let mut request = request_from("GET", "/api/backup", Body::empty());
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        let res = app.oneshot(request).await.expect("Can't get response");
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_retry_after() {
+        let pool = get_test_db_pool().await;
+        let app = public_router(
+            set_up_health_governor(),
+            set_up_public_governor(),
+            false,
+            false,
+        )
+        .with_state(AppState::new(pool, set_up_jwt(&AppConfig::default())));
+
+        // Burst of the public governor is 3
+        for _ in 0..3 {
+            let res = app
+                .clone()
+                .oneshot(login_request())
+                .await
+                .expect("Can't get response");
+            assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+        let res = app
+            .oneshot(login_request())
+            .await
+            .expect("Can't get response");
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let retry_after: u64 = res
+            .headers()
+            .get(axum::http::header::RETRY_AFTER)
+            .expect("Retry-After is missing!")
+            .to_str()
+            .expect("Retry-After is not a string")
+            .parse()
+            .expect("Retry-After is not a number of seconds!");
+        // One request comes back every 60 seconds
+        assert!(
+            (1..=60).contains(&retry_after),
+            "Retry-After is {}",
+            retry_after
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_returns_token() {
+        let pool = get_test_db_pool().await;
+        seed_test_user(&pool).await;
+        let app = app_router(
+            AppState::new(pool, set_up_jwt(&AppConfig::default())),
+            test_governors(),
+        );
+
+        let res = app
+            .oneshot(login_request())
+            .await
+            .expect("Can't get response");
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .expect("Can't read body");
+        let body: serde_json::Value = serde_json::from_slice(&body).expect("Body is not JSON");
+        assert!(
+            body["token"]
+                .as_str()
+                .is_some_and(|token| !token.is_empty()),
+            "Token is missing!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flush_requires_admin_role() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let signing_config = jwt_config.read().await.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+        let status_for = |role: &str| {
+            let token = generate_jwt("player", &signing_config, role, &RealTime).unwrap();
+            // Dry run, so the admin request leaves the shared test board alone
+            let mut request = request_from("DELETE", "/api/flush?dry_run=true", Body::empty());
+            request.headers_mut().insert(
+                "Authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            let app = app.clone();
+            async move {
+                app.oneshot(request)
+                    .await
+                    .expect("Can't get response")
+                    .status()
+            }
+        };
+
+        assert_eq!(status_for("default").await, StatusCode::FORBIDDEN);
+        assert_eq!(status_for(ADMIN_ROLE).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_delete_players_requires_admin_role() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let signing_config = jwt_config.read().await.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+        let token = generate_jwt("player", &signing_config, "default", &RealTime).unwrap();
+
+        let mut request = request_from(
+            "POST",
+            "/api/scores/delete",
+            Body::from(r#"{"names": ["Alice"]}"#),
+        );
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        let res = app.oneshot(request).await.expect("Can't get response");
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_export_requires_admin_role() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let signing_config = jwt_config.read().await.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+
+        for uri in ["/api/export.csv", "/api/export.ndjson"] {
+            let request = request_from("GET", uri, Body::empty());
+            let res = app
+                .clone()
+                .oneshot(authorized(request, &signing_config, "default"))
+                .await
+                .expect("Can't get response");
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{} is not gated!", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_introspect_requires_admin_role() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let signing_config = jwt_config.read().await.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+        let introspect_request = || {
+            request_from(
+                "POST",
+                "/api/introspect",
+                Body::from(r#"{"token": "garbage"}"#),
+            )
+        };
+
+        let player_res = app
+            .clone()
+            .oneshot(authorized(introspect_request(), &signing_config, "default"))
+            .await
+            .expect("Can't get response");
+        let admin_res = app
+            .oneshot(authorized(
+                introspect_request(),
+                &signing_config,
+                ADMIN_ROLE,
+            ))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(player_res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(admin_res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_token() {
+        let pool = get_test_db_pool().await;
+        let jwt_config = set_up_jwt(&AppConfig::default());
+        let secret = jwt_config.read().await.secret.clone();
+        let app = app_router(AppState::new(pool, jwt_config), test_governors());
+        let (token, refresh_token) = generate_jwt_pair(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .unwrap();
+        let other_token = generate_jwt(
+            "player",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .unwrap();
+        let send = |method: &str, uri: &str, token: &str, body: Body| {
+            let mut request = request_from(method, uri, body);
+            request.headers_mut().insert(
+                "Authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            app.clone().oneshot(request)
+        };
+        let refresh_body = || Body::from(format!(r#"{{"refresh_token": "{}"}}"#, refresh_token));
+
+        let res = send("POST", "/api/logout", &token, refresh_body())
+            .await
+            .expect("Can't get response");
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = send("GET", "/api/get-scores", &token, Body::empty())
+            .await
+            .expect("Can't get response");
+        assert_eq!(
+            res.status(),
+            StatusCode::UNAUTHORIZED,
+            "Token is valid after logout!"
+        );
+        let res = send("GET", "/api/get-scores", &other_token, Body::empty())
+            .await
+            .expect("Can't get response");
+        assert_eq!(res.status(), StatusCode::OK, "Other token is revoked!");
+        let res = app
+            .clone()
+            .oneshot(request_from("POST", "/refresh", refresh_body()))
+            .await
+            .expect("Can't get response");
+        assert_eq!(
+            res.status(),
+            StatusCode::UNAUTHORIZED,
+            "Refresh token is valid after logout!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_diagnostics_toggle() {
+        let pool = get_test_db_pool().await;
+        let app_with = |debug_diagnostics: bool| {
+            let config = AppConfig {
+                debug_diagnostics,
+                ..AppConfig::default()
+            };
+            app_router(
+                AppState::new(pool.clone(), set_up_jwt(&config)).with_config(config),
+                test_governors(),
+            )
+        };
+
+        let plain = app_with(false)
+            .oneshot(request_from("GET", "/livez", Body::empty()))
+            .await
+            .expect("Can't get response");
+        for header in ["X-DB-Pool-Size", "X-DB-Pool-Idle", "X-DB-Pool-Max"] {
+            assert!(
+                !plain.headers().contains_key(header),
+                "{} is sent without DEBUG_DIAGNOSTICS!",
+                header
+            );
+        }
+
+        let debug = app_with(true)
+            .oneshot(request_from("GET", "/livez", Body::empty()))
+            .await
+            .expect("Can't get response");
+        let count = |header: &str| -> u32 {
+            debug.headers()[header]
+                .to_str()
+                .unwrap()
+                .parse()
+                .expect("Pool count is not a number")
+        };
+        assert!(count("X-DB-Pool-Idle") <= count("X-DB-Pool-Size"));
+        assert!(count("X-DB-Pool-Size") <= count("X-DB-Pool-Max"));
+    }
+
+    #[tokio::test]
+    async fn test_board_page_toggle() {
+        let pool = get_test_db_pool().await;
+        let app_with = |board_page_enabled: bool| {
+            let config = AppConfig {
+                board_page_enabled,
+                ..AppConfig::default()
+            };
+            app_router(
+                AppState::new(pool.clone(), set_up_jwt(&config)).with_config(config),
+                test_governors(),
+            )
+        };
+
+        let disabled_res = app_with(false)
+            .oneshot(request_from("GET", "/board", Body::empty()))
+            .await
+            .expect("Can't get response");
+        let enabled_res = app_with(true)
+            .oneshot(request_from("GET", "/board", Body::empty()))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(disabled_res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(enabled_res.status(), StatusCode::OK);
+        assert!(
+            enabled_res.headers()["Content-Type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_batch_requires_api_key() {
+        let pool = get_test_db_pool().await;
+        let app_with = |gateway_api_key: Option<&str>| {
+            let config = AppConfig {
+                gateway_api_key: gateway_api_key.map(str::to_string),
+                ..AppConfig::default()
+            };
+            app_router(
+                AppState::new(pool.clone(), set_up_jwt(&config)).with_config(config),
+                test_governors(),
+            )
+        };
+        let batch_request = |api_key: Option<&str>| {
+            let mut request = request_from(
+                "POST",
+                "/api/validate-batch",
+                Body::from(r#"{"tokens": ["garbage"]}"#),
+            );
+            if let Some(api_key) = api_key {
+                request
+                    .headers_mut()
+                    .insert("X-Api-Key", api_key.parse().unwrap());
+            }
+            request
+        };
+        let status = |app: Router, request: Request<Body>| async move {
+            app.oneshot(request)
+                .await
+                .expect("Can't get response")
+                .status()
+        };
+
+        let app = app_with(Some("gateway-key"));
+        assert_eq!(
+            status(app.clone(), batch_request(Some("gateway-key"))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(app.clone(), batch_request(Some("wrong-key"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(app, batch_request(None)).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(app_with(None), batch_request(Some("gateway-key"))).await,
+            StatusCode::NOT_FOUND,
+            "Batch validation is served without GATEWAY_API_KEY!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_by_ip_and_token() {
+        let pool = get_test_db_pool().await;
+        let throttled_after_token_rotation = |rate_limit_key: RateLimitKey| {
+            let config = AppConfig {
+                rate_limit_key,
+                ..AppConfig::default()
+            };
+            let state =
+                AppState::new(pool.clone(), set_up_jwt(&config)).with_config(config.clone());
+            let app = private_router(
+                state.clone(),
+                set_up_private_governor(&config),
+                set_up_private_ip_governor(&config),
+            )
+            .with_state(state);
+            async move {
+                for i in 0..10 {
+                    let mut request = request_from("GET", "/api/get-scores", Body::empty());
+                    request.headers_mut().insert(
+                        "Authorization",
+                        format!("Bearer garbage-{}", i).parse().unwrap(),
+                    );
+                    let res = app
+                        .clone()
+                        .oneshot(request)
+                        .await
+                        .expect("Can't get response");
+                    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                        return true;
+                    }
+                }
+                false
+            }
+        };
+
+        assert!(
+            throttled_after_token_rotation(RateLimitKey::IpAndToken).await,
+            "Rotating tokens bypasses the limit!"
+        );
+        assert!(throttled_after_token_rotation(RateLimitKey::Ip).await);
+        assert!(!throttled_after_token_rotation(RateLimitKey::Token).await);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_headers() {
+        let pool = get_test_db_pool().await;
+        let budgets = |rate_limit_headers: bool| {
+            let config = AppConfig {
+                rate_limit_headers,
+                ..AppConfig::default()
+            };
+            let state =
+                AppState::new(pool.clone(), set_up_jwt(&config)).with_config(config.clone());
+            let app = private_router(state.clone(), set_up_private_governor(&config), None)
+                .with_state(state);
+            async move {
+                let mut budgets = Vec::new();
+                for _ in 0..3 {
+                    let mut request = request_from("GET", "/api/get-scores", Body::empty());
+                    request
+                        .headers_mut()
+                        .insert("Authorization", "Bearer garbage".parse().unwrap());
+                    let res = app
+                        .clone()
+                        .oneshot(request)
+                        .await
+                        .expect("Can't get response");
+                    let header = |name: &str| {
+                        res.headers()
+                            .get(name)
+                            .map(|value| value.to_str().unwrap().to_owned())
+                    };
+                    budgets.push((
+                        header("X-RateLimit-Limit"),
+                        header("X-RateLimit-Remaining"),
+                        header("X-RateLimit-Reset"),
+                    ));
+                }
+                budgets
+            }
+        };
+
+        let budget = |remaining: &str, reset: &str| {
+            (
+                Some("5".to_owned()),
+                Some(remaining.to_owned()),
+                Some(reset.to_owned()),
+            )
+        };
+        assert_eq!(
+            budgets(true).await,
+            vec![budget("4", "60"), budget("3", "120"), budget("2", "180")]
+        );
+        assert!(
+            budgets(false)
+                .await
+                .iter()
+                .all(|budget| *budget == (None, None, None)),
+            "Rate limit budget is sent without RATE_LIMIT_HEADERS!"
+        );
+    }
+}
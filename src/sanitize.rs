@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use axum::{
+    Json,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{error::ServerError, state::AppState};
+
+// What is done with control characters (NUL, CR, LF, ESC...) found in text fields of a body
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ControlChars {
+    // Whole request is refused with 400
+    #[default]
+    Reject,
+    // Characters are dropped and the rest of the text is kept
+    Strip,
+}
+
+impl FromStr for ControlChars {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(ControlChars::Reject),
+            "strip" => Ok(ControlChars::Strip),
+            other => Err(format!("Unknown control characters policy: {}", other)),
+        }
+    }
+}
+
+// Every string value of the body goes through the policy, so new fields of any request type
+// are covered without touching them one by one. Keys with control characters can't name
+// a real field, so they are refused under both policies
+pub fn sanitize_value(value: &mut Value, policy: ControlChars) -> Result<(), ServerError> {
+    match value {
+        Value::String(text) => sanitize_text(text, policy),
+        Value::Array(items) => items
+            .iter_mut()
+            .try_for_each(|item| sanitize_value(item, policy)),
+        Value::Object(fields) => {
+            if fields.keys().any(|key| key.chars().any(char::is_control)) {
+                return Err(control_chars_error());
+            }
+            fields
+                .values_mut()
+                .try_for_each(|field| sanitize_value(field, policy))
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => Ok(()),
+    }
+}
+
+fn sanitize_text(text: &mut String, policy: ControlChars) -> Result<(), ServerError> {
+    if !text.chars().any(char::is_control) {
+        return Ok(());
+    }
+    match policy {
+        ControlChars::Reject => Err(control_chars_error()),
+        ControlChars::Strip => {
+            text.retain(|c| !c.is_control());
+            Ok(())
+        }
+    }
+}
+
+fn control_chars_error() -> ServerError {
+    ServerError::Validation("Text fields can't contain control characters".into())
+}
+
+// JSON body with control characters handled by CONTROL_CHARS before it reaches the type.
+// Body that doesn't fit the type is refused with 422, same as plain Json extractor does
+pub struct Sanitized<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest<AppState> for Sanitized<T> {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Json(mut value) = Json::<Value>::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        sanitize_value(&mut value, state.config.control_chars).map_err(|e| {
+            tracing::warn!("Request with control characters is refused!");
+            e.into_response()
+        })?;
+
+        serde_json::from_value(value).map(Sanitized).map_err(|e| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "Failed to deserialize the JSON body into the target type: {}",
+                    e
+                ),
+            )
+                .into_response()
+        })
+    }
+}
+
+#[cfg(test)]
+mod sanitize_tests {
+    use super::*;
+    use crate::{
+        config::AppConfig, db_access::PlayerScore, db_access::get_test_db_pool,
+        handlers::LoginRequest, set_up_jwt,
+    };
+    use axum::body::Body;
+    use serde_json::json;
+
+    async fn state_with(control_chars: ControlChars) -> AppState {
+        AppState::new(get_test_db_pool().await, set_up_jwt(&AppConfig::default())).with_config(
+            AppConfig {
+                control_chars,
+                ..AppConfig::default()
+            },
+        )
+    }
+
+    fn json_request(body: Value) -> Request {
+        Request::builder()
+            .method("POST")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("Can't create request")
+    }
+
+    #[tokio::test]
+    async fn test_sanitize_value() {
+        let mut value = json!({"name": "Dra\u{0000}gon", "tags": ["a\r\nb", 5], "ok": "fine"});
+
+        assert!(sanitize_value(&mut value.clone(), ControlChars::Reject).is_err());
+        sanitize_value(&mut value, ControlChars::Strip).expect("Can't strip");
+        assert_eq!(
+            value,
+            json!({"name": "Dragon", "tags": ["ab", 5], "ok": "fine"})
+        );
+
+        let mut bad_key = json!({"na\u{0007}me": "Dragon"});
+        assert!(sanitize_value(&mut bad_key, ControlChars::Strip).is_err());
+        assert_eq!("STRIP".parse(), Ok(ControlChars::Strip));
+        assert!("ignore".parse::<ControlChars>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sanitized_login_request() {
+        let body = || json!({"username": "us\u{0000}er", "password": "pass\r\nword"});
+
+        let rejected = Sanitized::<LoginRequest>::from_request(
+            json_request(body()),
+            &state_with(ControlChars::Reject).await,
+        )
+        .await
+        .err()
+        .expect("Control characters are accepted!");
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+        let Sanitized(stripped) = Sanitized::<LoginRequest>::from_request(
+            json_request(body()),
+            &state_with(ControlChars::Strip).await,
+        )
+        .await
+        .unwrap_or_else(|_| panic!("Can't strip control characters!"));
+        assert_eq!(stripped.username, "user");
+        assert_eq!(stripped.password, "password");
+    }
+
+    #[tokio::test]
+    async fn test_sanitized_player_score() {
+        let body =
+            || json!({"player_name": "Drag\r\non", "player_score": 5, "avatar": "\u{0000}red"});
+
+        let rejected = Sanitized::<PlayerScore>::from_request(
+            json_request(body()),
+            &state_with(ControlChars::Reject).await,
+        )
+        .await
+        .err()
+        .expect("Control characters are accepted!");
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+
+        let Sanitized(stripped) = Sanitized::<PlayerScore>::from_request(
+            json_request(body()),
+            &state_with(ControlChars::Strip).await,
+        )
+        .await
+        .unwrap_or_else(|_| panic!("Can't strip control characters!"));
+        assert_eq!(stripped.player_name.as_str(), "Dragon");
+        assert_eq!(stripped.avatar.as_deref(), Some("red"));
+
+        let invalid = Sanitized::<PlayerScore>::from_request(
+            json_request(json!({"player_name": "Dragon"})),
+            &state_with(ControlChars::Strip).await,
+        )
+        .await
+        .err()
+        .expect("Incomplete score is accepted!");
+        assert_eq!(invalid.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,60 @@
+use serde_json::{Value, json};
+
+use crate::{
+    config::AppConfig,
+    db_access::{SCORE_MAX, SCORE_MIN},
+    player_name::{NAME_MAX_LEN, NAME_MIN_LEN},
+};
+
+// JSON Schema of the set-score body, built from the same limits the server checks against.
+// Name length is counted after whitespace is collapsed and difficulties follow DIFFICULTIES,
+// tier ceilings and the score floor depend on the board and are left to the server
+pub fn score_schema(config: &AppConfig) -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "PlayerScore",
+        "type": "object",
+        "required": ["player_name", "player_score"],
+        "properties": {
+            "player_name": {
+                "type": "string",
+                "minLength": NAME_MIN_LEN,
+                "maxLength": NAME_MAX_LEN
+            },
+            "player_score": {
+                "type": "integer",
+                "minimum": SCORE_MIN,
+                "maximum": SCORE_MAX
+            },
+            "player_id": { "type": "string", "minLength": 1, "maxLength": 64 },
+            "avatar": { "type": "string", "minLength": 1, "maxLength": 128 },
+            "tier": { "type": "string", "minLength": 1, "maxLength": 32 },
+            "difficulty": { "type": "string", "enum": config.difficulties }
+        }
+    })
+}
+
+#[cfg(test)]
+mod score_schema_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_score_schema_bounds() {
+        let config = AppConfig {
+            difficulties: vec!["casual".to_string(), "insane".to_string()],
+            ..AppConfig::default()
+        };
+        let schema = score_schema(&config);
+        let properties = &schema["properties"];
+
+        assert_eq!(properties["player_name"]["minLength"], NAME_MIN_LEN);
+        assert_eq!(properties["player_name"]["maxLength"], NAME_MAX_LEN);
+        assert_eq!(properties["player_score"]["minimum"], SCORE_MIN);
+        assert_eq!(properties["player_score"]["maximum"], 1_000_000);
+        assert_eq!(
+            properties["difficulty"]["enum"],
+            json!(["casual", "insane"]),
+            "Configured difficulties are not in the schema!"
+        );
+    }
+}
@@ -1,17 +1,61 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
 use axum::{
     body::Body,
-    extract::Request,
-    http::{HeaderValue, header},
+    extract::{ConnectInfo, Request},
+    http::{HeaderMap, HeaderName, HeaderValue, header},
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use chrono::{DateTime, Duration, TimeZone, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+#[cfg(test)]
+use chrono::TimeZone;
+use chrono::{DateTime, Duration, Utc};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode, errors::ErrorKind,
+};
 use rand::{Rng, distr::Alphanumeric};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::RwLock;
 use tower_governor::key_extractor::KeyExtractor;
 
-use crate::{error::JwtError, state::AppState};
+use crate::{
+    config::AuthMode,
+    db_access::{PlayerScore, create_user_db, get_user_db},
+    error::{JwtError, ServerError},
+    revoked_tokens::RevokedTokens,
+    state::AppState,
+};
+
+// Name of the cookie holding JWT in cookie auth mode
+pub const AUTH_COOKIE: &str = "token";
+
+// Default lifetime of access tokens, see JWT_TTL_SECONDS
+pub const ACCESS_TOKEN_TTL: Duration = Duration::hours(1);
+// Refresh tokens only get new access tokens from /refresh, so they may live longer. Without
+// JWT_PASSPHRASE the HS256 secret is generated on start and rotated every 24h, which refuses
+// older tokens anyway, so they live no longer than the rotation period
+pub const REFRESH_TOKEN_TTL: Duration = Duration::hours(24);
+
+// Tokens missing any of these claims are rejected before their values are checked
+pub const REQUIRED_CLAIMS: [&str; 3] = ["exp", "sub", "role"];
+// Fixed salt of secret derivation, the passphrase is the only secret input
+const SECRET_KDF_SALT: &[u8] = b"flappy_server jwt secret";
+// Role of tokens allowed to reach admin-only routes
+pub const ADMIN_ROLE: &str = "admin";
+
+// HS256 key can't be shorter than the hash output (RFC 7518, section 3.2)
+pub const MIN_SECRET_LEN: usize = 32;
+
+// HKDF doesn't stretch the passphrase, so it has to be hard to guess on its own
+pub const MIN_PASSPHRASE_LEN: usize = 16;
 
 pub trait TimeProvider {
     fn now(&self) -> DateTime<chrono::Utc>;
@@ -25,8 +69,10 @@ impl TimeProvider for RealTime {
     }
 }
 
+#[cfg(test)]
 pub struct MockTime;
 
+#[cfg(test)]
 impl TimeProvider for MockTime {
     fn now(&self) -> DateTime<chrono::Utc> {
         chrono::Utc
@@ -37,40 +83,192 @@ impl TimeProvider for MockTime {
 }
 
 #[derive(Clone)]
-pub struct JwtKeyExtractor;
+pub struct JwtKeyExtractor {
+    pub auth_mode: AuthMode,
+    pub auth_scheme: String,
+}
 
 impl KeyExtractor for JwtKeyExtractor {
-    type Key = String;
+    // Hash of the token, so giant tokens can't bloat the limiter storage
+    type Key = u64;
 
     fn extract<T>(
         &self,
         req: &axum::http::Request<T>,
     ) -> Result<Self::Key, tower_governor::GovernorError> {
-        req.headers()
-            .get("Authorization")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.strip_prefix("Bearer "))
-            .map(|token| token.to_string())
-            .ok_or(tower_governor::errors::GovernorError::UnableToExtractKey)
+        extract_token(req.headers(), self.auth_mode, &self.auth_scheme)
+            .map(token_key)
+            .map_err(|_| tower_governor::errors::GovernorError::UnableToExtractKey)
+    }
+}
+
+#[derive(Clone)]
+pub struct ClientIpKeyExtractor {
+    pub trusted_proxies: Arc<Vec<IpAddr>>,
+}
+
+impl KeyExtractor for ClientIpKeyExtractor {
+    type Key = IpAddr;
+
+    fn extract<T>(
+        &self,
+        req: &axum::http::Request<T>,
+    ) -> Result<Self::Key, tower_governor::GovernorError> {
+        let ConnectInfo(peer) = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .ok_or(tower_governor::errors::GovernorError::UnableToExtractKey)?;
+        Ok(client_ip(req.headers(), peer.ip(), &self.trusted_proxies))
+    }
+}
+
+// X-Forwarded-For is read from the right, skipping our own proxies - entries on the left
+// are written by the client and can be anything. Peer itself is used when it isn't trusted
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map_while(|ip| ip.trim().parse().ok())
+        .collect();
+
+    forwarded
+        .into_iter()
+        .rev()
+        .find(|ip| !trusted_proxies.contains(ip))
+        .unwrap_or(peer)
+}
+
+// DefaultHasher::new is seeded with fixed keys, so the same token maps to the same key
+pub fn token_key(token: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    token.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Authorization header always wins, cookie is only a fallback in cookie mode.
+// Scheme name is case-insensitive (RFC 7235), so "bearer" is as good as "Bearer"
+pub fn extract_token<'a>(
+    headers: &'a HeaderMap,
+    auth_mode: AuthMode,
+    auth_scheme: &str,
+) -> Result<&'a str, JwtError> {
+    match headers.get(header::AUTHORIZATION) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| JwtError::InvalidTokenFormat)?
+            .split_once(' ')
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(auth_scheme))
+            .map(|(_, token)| token.trim())
+            .ok_or(JwtError::InvalidTokenFormat),
+        None if auth_mode == AuthMode::Cookie => {
+            token_from_cookie(headers).ok_or(JwtError::MissingAuthHeader)
+        }
+        None => Err(JwtError::MissingAuthHeader),
     }
 }
 
+fn token_from_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == AUTH_COOKIE)
+        .map(|(_, token)| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+pub fn auth_cookie(token: &str, max_age: Duration) -> String {
+    format!(
+        "{}={}; HttpOnly; Secure; SameSite=Strict; Path=/; Max-Age={}",
+        AUTH_COOKIE,
+        token,
+        max_age.num_seconds()
+    )
+}
+
+// Tokens issued before refresh tokens have no token_type claim and are access tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    #[default]
+    Access,
+    Refresh,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
     pub sub: String,
     pub exp: usize,
     pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>,
+    #[serde(default)]
+    pub token_type: TokenType,
+    // Id of the token for revoking it, tokens issued before ids have none and can't be revoked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
+    pub role: String,
+}
+
+// RS256 key pair. Other services check tokens with the public key, without holding the one
+// that signs them
+#[derive(Clone)]
+pub struct RsaKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl RsaKeys {
+    // Keys are tried on a probe token, so a private key of another pair stops startup
+    pub fn from_pem(private_pem: &[u8], public_pem: &[u8]) -> Result<Self, ServerError> {
+        let invalid = |e: jsonwebtoken::errors::Error| {
+            ServerError::Configuration(format!("RSA key can't be read: {}", e))
+        };
+        let keys = RsaKeys {
+            encoding: EncodingKey::from_rsa_pem(private_pem).map_err(invalid)?,
+            decoding: DecodingKey::from_rsa_pem(public_pem).map_err(invalid)?,
+        };
+
+        let probe = encode(
+            &Header::new(Algorithm::RS256),
+            &serde_json::json!({"sub": "probe"}),
+            &keys.encoding,
+        )
+        .map_err(invalid)?;
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        decode::<serde_json::Value>(&probe, &keys.decoding, &validation).map_err(|_| {
+            ServerError::Configuration("RSA private and public keys are not a pair".into())
+        })?;
+
+        Ok(keys)
+    }
 }
 
 #[derive(Clone)]
 pub struct JwtConfig {
+    // Signs tokens with HS256 unless there are RSA keys. Game sessions are signed with it always
     pub secret: String,
     pub validation: Validation,
+    // Skew allowed for nbf of tokens issued by a machine ahead of us, apart from exp leeway
+    pub nbf_leeway: u64,
+    pub access_token_ttl: Duration,
+    pub algorithm: Algorithm,
+    pub rsa_keys: Option<RsaKeys>,
 }
 
 impl JwtConfig {
@@ -78,72 +276,419 @@ impl JwtConfig {
         let mut validation = Validation::default();
         validation.leeway = 60;
         validation.validate_exp = true;
-        validation.validate_nbf = true;
+        // jsonwebtoken has one leeway for exp and nbf, so nbf is checked in decode_claims
+        validation.validate_nbf = false;
+        validation.set_required_spec_claims(&REQUIRED_CLAIMS);
+
+        Self {
+            secret,
+            validation,
+            nbf_leeway: 60,
+            access_token_ttl: ACCESS_TOKEN_TTL,
+            algorithm: Algorithm::HS256,
+            rsa_keys: None,
+        }
+    }
+
+    // Only RS256 tokens are accepted from then on, HS256 ones signed with the secret are refused
+    pub fn with_rsa_keys(mut self, rsa_keys: RsaKeys) -> Self {
+        self.algorithm = Algorithm::RS256;
+        self.validation.algorithms = vec![Algorithm::RS256];
+        self.rsa_keys = Some(rsa_keys);
+        self
+    }
+
+    fn encoding_key(&self) -> EncodingKey {
+        match &self.rsa_keys {
+            Some(keys) => keys.encoding.clone(),
+            None => EncodingKey::from_secret(self.secret.as_ref()),
+        }
+    }
+
+    fn decoding_key(&self) -> DecodingKey {
+        match &self.rsa_keys {
+            Some(keys) => keys.decoding.clone(),
+            None => DecodingKey::from_secret(self.secret.as_ref()),
+        }
+    }
 
-        Self { secret, validation }
+    pub fn with_access_token_ttl(mut self, access_token_ttl: Duration) -> Self {
+        self.access_token_ttl = access_token_ttl;
+        self
+    }
+
+    pub fn with_nbf_leeway(mut self, nbf_leeway: u64) -> Self {
+        self.nbf_leeway = nbf_leeway;
+        self
+    }
+
+    fn decode_claims(&self, token: &str) -> Result<Claims, JwtError> {
+        self.decode_claims_at(token, &RealTime)
+    }
+
+    // jsonwebtoken reads only the system clock, so exp is checked here against the given one.
+    // Its presence is still enforced by required claims
+    fn decode_claims_at(&self, token: &str, time: &impl TimeProvider) -> Result<Claims, JwtError> {
+        let mut validation = self.validation.clone();
+        validation.validate_exp = false;
+        let claims = decode::<Claims>(token, &self.decoding_key(), &validation)
+            .map_err(JwtError::DecodeError)?
+            .claims;
+
+        let now = u64::try_from(time.now().timestamp()).unwrap_or(0);
+        if self.validation.validate_exp
+            && (claims.exp as u64) < now.saturating_sub(self.validation.leeway)
+        {
+            return Err(JwtError::DecodeError(ErrorKind::ExpiredSignature.into()));
+        }
+        match claims.nbf {
+            Some(nbf) if nbf as u64 > now.saturating_add(self.nbf_leeway) => {
+                Err(JwtError::DecodeError(ErrorKind::ImmatureSignature.into()))
+            }
+            _ => Ok(claims),
+        }
+    }
+
+    pub fn settings(&self) -> ValidationSettings {
+        let mut required_claims: Vec<String> = self
+            .validation
+            .required_spec_claims
+            .iter()
+            .cloned()
+            .collect();
+        required_claims.sort();
+
+        ValidationSettings {
+            leeway: self.validation.leeway,
+            nbf_leeway: self.nbf_leeway,
+            validate_exp: self.validation.validate_exp,
+            required_claims,
+        }
+    }
+
+    // Update is checked as a whole before anything is applied, so a rejected one changes nothing
+    pub fn apply_update(
+        &mut self,
+        update: ValidationUpdate,
+    ) -> Result<ValidationSettings, ServerError> {
+        if update.validate_signature == Some(false) {
+            return Err(ServerError::Validation(
+                "Signature verification can't be disabled".into(),
+            ));
+        }
+        if update.leeway.is_none()
+            && update.nbf_leeway.is_none()
+            && update.validate_exp.is_none()
+            && update.required_claims.is_none()
+        {
+            return Err(ServerError::Validation("Nothing to update".into()));
+        }
+
+        if let Some(leeway) = update.leeway {
+            self.validation.leeway = leeway;
+        }
+        if let Some(nbf_leeway) = update.nbf_leeway {
+            self.nbf_leeway = nbf_leeway;
+        }
+        if let Some(validate_exp) = update.validate_exp {
+            self.validation.validate_exp = validate_exp;
+        }
+        if let Some(claims) = update.required_claims {
+            self.validation.set_required_spec_claims(&claims);
+        }
+
+        Ok(self.settings())
+    }
+
+    // jsonwebtoken checks presence only of spec claims (exp, nbf, aud, iss, sub),
+    // custom ones like role are enforced by Claims deserialization
+    pub fn with_required_claims<T: ToString>(mut self, claims: &[T]) -> Self {
+        self.validation.set_required_spec_claims(claims);
+        self
     }
 }
 
+// Part of validation operators may change at runtime, algorithms and secret are not in it
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationUpdate {
+    pub leeway: Option<u64>,
+    pub nbf_leeway: Option<u64>,
+    pub validate_exp: Option<bool>,
+    pub required_claims: Option<Vec<String>>,
+    // Accepted only to refuse turning it off with a clear error
+    pub validate_signature: Option<bool>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ValidationSettings {
+    pub leeway: u64,
+    pub nbf_leeway: u64,
+    pub validate_exp: bool,
+    pub required_claims: Vec<String>,
+}
+
+// Subject of the verified token, put into request extensions for handlers acting on its behalf
+#[derive(Debug, Clone)]
+pub struct AuthSubject(pub String);
+
+// Role claim of the verified token, checked by admin-only routes
+#[derive(Debug, Clone)]
+pub struct AuthRole(pub String);
+
+// Id and expiry claims of the verified token, id is None for tokens issued before ids
+#[derive(Debug, Clone)]
+pub struct AuthTokenId {
+    pub jti: Option<String>,
+    pub exp: usize,
+}
+
+// Clock is passed in, so tests can check expiry at any moment. Server runs it with RealTime
 pub async fn jwt_middleware(
-    req: Request<Body>,
+    mut req: Request<Body>,
     next: Next,
     state: AppState,
+    time: impl TimeProvider,
 ) -> Result<Response, JwtError> {
-    let token = req
-        .headers()
-        .get("Authorization")
-        .ok_or(JwtError::MissingAuthHeader)?
-        .to_str()
-        .map_err(|_| JwtError::InvalidTokenFormat)?
-        .strip_prefix("Bearer ")
-        .ok_or(JwtError::InvalidTokenFormat)?
-        .trim();
+    let token = extract_token(
+        req.headers(),
+        state.config.auth_mode,
+        &state.config.auth_scheme,
+    )?;
 
-    let secret = &state.jwt_config.read().await.secret;
-    let validation = &state.jwt_config.read().await.validation;
+    // Single read guard, so secret and validation always come from the same config
+    let jwt_config = state.jwt_config.read().await;
 
     //Decoding token and checking if it is valid
-    let _claims = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        validation,
-    )
-    .map_err(JwtError::DecodeError)?
-    .claims;
+    let claims = jwt_config.decode_claims_at(token, &time)?;
+    drop(jwt_config);
+    if claims.token_type != TokenType::Access {
+        return Err(JwtError::WrongTokenType);
+    }
+    if is_revoked(&state.revoked_tokens, &claims) {
+        return Err(JwtError::Revoked);
+    }
+
+    req.extensions_mut().insert(AuthSubject(claims.sub));
+    req.extensions_mut().insert(AuthRole(claims.role));
+    req.extensions_mut().insert(AuthTokenId {
+        jti: claims.jti,
+        exp: claims.exp,
+    });
 
     Ok(next.run(req).await)
 }
 
+// RFC 7662 style answer. Claims are never shown for inactive tokens
+#[derive(Debug, Serialize)]
+pub struct Introspection {
+    pub active: bool,
+    #[serde(flatten)]
+    claims: Option<Claims>,
+}
+
+// Only the current secret is kept, so tokens signed before rotation are inactive.
+// Revoked tokens are inactive too
+pub fn introspect_token(
+    jwt_config: &JwtConfig,
+    revoked_tokens: &RevokedTokens,
+    token: &str,
+) -> Introspection {
+    let claims = jwt_config
+        .decode_claims(token)
+        .ok()
+        .filter(|claims| !is_revoked(revoked_tokens, claims));
+
+    Introspection {
+        active: claims.is_some(),
+        claims,
+    }
+}
+
 pub fn generate_jwt(
     user_id: &str,
-    secret: &str,
+    jwt_config: &JwtConfig,
+    role: &str,
+    time: &impl TimeProvider,
+) -> Result<String, JwtError> {
+    let ttl = jwt_config.access_token_ttl;
+    generate_token(user_id, jwt_config, role, TokenType::Access, ttl, time)
+}
+
+// Access token together with a refresh token for getting the next one without a login
+pub fn generate_jwt_pair(
+    user_id: &str,
+    jwt_config: &JwtConfig,
+    role: &str,
+    time: &impl TimeProvider,
+) -> Result<(String, String), JwtError> {
+    let refresh = TokenType::Refresh;
+    Ok((
+        generate_jwt(user_id, jwt_config, role, time)?,
+        generate_token(user_id, jwt_config, role, refresh, REFRESH_TOKEN_TTL, time)?,
+    ))
+}
+
+fn generate_token(
+    user_id: &str,
+    jwt_config: &JwtConfig,
     role: &str,
+    token_type: TokenType,
+    ttl: Duration,
     time: &impl TimeProvider,
 ) -> Result<String, JwtError> {
     let expiration = time
         .now()
-        .checked_add_signed(Duration::hours(1))
-        .expect("Invalid timestamp! Server is shutdown!")
-        .timestamp() as usize;
+        .checked_add_signed(ttl)
+        .ok_or(JwtError::TimestampOverflow)?
+        .timestamp();
+    let expiration = usize::try_from(expiration).map_err(|_| JwtError::TimestampOverflow)?;
 
     let claims = Claims {
         sub: user_id.to_owned(),
         exp: expiration,
         role: role.to_owned(),
+        nbf: None,
+        token_type,
+        jti: Some(hex::encode(rand::random::<[u8; 16]>())),
     };
 
     encode(
-        &Header::new(jsonwebtoken::Algorithm::HS256),
+        &Header::new(jwt_config.algorithm),
         &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
+        &jwt_config.encoding_key(),
     )
     .map_err(JwtError::_EncodingError)
 }
 
-pub async fn validate_user(_username: &str, _password: &str) -> Result<User, String> {
-    Ok(User {
-        id: "default".into(),
-    })
+// New access token for a valid refresh token, with subject and role carried over
+pub fn refresh_access_token(
+    jwt_config: &JwtConfig,
+    revoked_tokens: &RevokedTokens,
+    refresh_token: &str,
+    time: &impl TimeProvider,
+) -> Result<String, JwtError> {
+    let claims = jwt_config.decode_claims(refresh_token)?;
+    if claims.token_type != TokenType::Refresh {
+        return Err(JwtError::WrongTokenType);
+    }
+    if is_revoked(revoked_tokens, &claims) {
+        return Err(JwtError::Revoked);
+    }
+    generate_jwt(&claims.sub, jwt_config, &claims.role, time)
+}
+
+// Refresh token is revoked on logout along with the access token, so the session can't be
+// continued with it. Only refresh tokens of the same subject are accepted
+pub fn revoke_refresh_token(
+    jwt_config: &JwtConfig,
+    revoked_tokens: &RevokedTokens,
+    refresh_token: &str,
+    subject: &str,
+    time: &impl TimeProvider,
+) -> Result<(), JwtError> {
+    let claims = jwt_config.decode_claims(refresh_token)?;
+    if claims.token_type != TokenType::Refresh || claims.sub != subject {
+        return Err(JwtError::WrongTokenType);
+    }
+    if let Some(jti) = claims.jti {
+        revoked_tokens.revoke(jti, claims.exp, time);
+    }
+    Ok(())
+}
+
+fn is_revoked(revoked_tokens: &RevokedTokens, claims: &Claims) -> bool {
+    claims
+        .jti
+        .as_ref()
+        .is_some_and(|jti| revoked_tokens.is_revoked(jti))
+}
+
+// Reason is kept for logs only, clients get the same answer for a wrong username or password
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginError {
+    UnknownUser,
+    WrongPassword,
+    Database(String),
+}
+
+impl From<LoginError> for ServerError {
+    fn from(e: LoginError) -> Self {
+        match e {
+            LoginError::UnknownUser | LoginError::WrongPassword => {
+                ServerError::Authentication("Invalid username or password".into())
+            }
+            LoginError::Database(msg) => ServerError::Database(msg),
+        }
+    }
+}
+
+// Hash of a random password with default params, verified for a missing user so the response
+// takes as long as for an existing one and doesn't tell which usernames exist
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$O8uhvPO8KqyuUBfBCPPRpQ$cEPpdZ8k4oYNa4AFe43x80nPaZGHksO4Rjjor1UaLmM";
+
+pub async fn validate_user(
+    pool: &PgPool,
+    username: &str,
+    password: &str,
+) -> Result<User, LoginError> {
+    let user = get_user_db(pool, username)
+        .await
+        .map_err(|e| LoginError::Database(format!("{:?}", e)))?;
+
+    let hash = user
+        .as_ref()
+        .map_or(DUMMY_HASH, |user| user.argon2_hash.as_str())
+        .to_owned();
+    let password = password.to_owned();
+    // Argon2 is slow on purpose, so it is kept off the async workers
+    let verified = tokio::task::spawn_blocking(move || verify_password(&password, &hash))
+        .await
+        .map_err(|e| LoginError::Database(format!("Password check failed: {}", e)))?;
+
+    match user {
+        None => Err(LoginError::UnknownUser),
+        Some(_) if !verified => Err(LoginError::WrongPassword),
+        Some(user) => Ok(User {
+            id: user.id.to_string(),
+            role: user.role,
+        }),
+    }
+}
+
+// New accounts get the default role, hashing runs off the async workers like verification
+pub async fn register_user(
+    pool: &PgPool,
+    username: &str,
+    password: &str,
+) -> Result<i32, ServerError> {
+    let password = password.to_owned();
+    let hash = tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(|e| ServerError::Database(format!("Password hashing failed: {}", e)))??;
+    create_user_db(pool, username, &hash).await
+}
+
+fn hash_password(password: &str) -> Result<String, ServerError> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+        .map_err(|e| ServerError::Database(format!("Can't encode salt: {}", e)))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ServerError::Database(format!("Can't hash password: {}", e)))
+}
+
+// Params are read from the stored hash, a hash that can't be parsed never matches
+fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(e) => {
+            tracing::error!("Stored password hash is malformed: {}", e);
+            false
+        }
+    }
 }
 
 pub async fn set_up_security_headers(
@@ -189,14 +734,175 @@ pub async fn set_up_security_headers(
     Ok(response)
 }
 
+// Browsers enforce CORS on their own, this check makes misconfigured origins visible
+pub async fn check_origin(
+    req: Request<Body>,
+    next: Next,
+    allowed_origins: Arc<Vec<String>>,
+) -> Result<Response, ServerError> {
+    if let Some(origin) = req.headers().get(header::ORIGIN) {
+        let origin = origin.to_str().unwrap_or_default();
+        if !allowed_origins.iter().any(|allowed| allowed == origin) {
+            tracing::warn!("Request from not allowed origin: {}", origin);
+            return Err(ServerError::OriginNotAllowed(origin.to_owned()));
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Scripted abuse tends to come without User-Agent, legitimate clients set one
+pub async fn require_user_agent(req: Request<Body>, next: Next) -> Result<Response, ServerError> {
+    let has_user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| !value.trim().is_empty());
+
+    if !has_user_agent {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| peer.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        tracing::warn!("Request without User-Agent is rejected, source: {}", peer);
+        return Err(ServerError::Validation(
+            "User-Agent header is required".into(),
+        ));
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Has to run after jwt_middleware, requests without verified role are refused too
+pub async fn require_role(
+    req: Request<Body>,
+    next: Next,
+    required: &'static str,
+) -> Result<Response, ServerError> {
+    match req.extensions().get::<AuthRole>() {
+        Some(AuthRole(role)) if role == required => Ok(next.run(req).await),
+        _ => {
+            tracing::warn!("Route for {} role is requested without it!", required);
+            Err(ServerError::Forbidden(format!(
+                "Role {} is required",
+                required
+            )))
+        }
+    }
+}
+
+pub async fn require_admin(req: Request<Body>, next: Next) -> Result<Response, ServerError> {
+    require_role(req, next, ADMIN_ROLE).await
+}
+
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+// Gateway routes are called by our own edge, not by players, so they take a shared key
+// instead of JWT. Digests are compared, so response timing tells nothing about the key
+pub async fn require_api_key(
+    req: Request<Body>,
+    next: Next,
+    api_key: Arc<String>,
+) -> Result<Response, ServerError> {
+    let provided = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .map(HeaderValue::as_bytes)
+        .unwrap_or_default();
+    if Sha256::digest(provided) != Sha256::digest(api_key.as_bytes()) {
+        tracing::warn!("Gateway route is requested without valid API key!");
+        return Err(ServerError::Authentication("Invalid API key".into()));
+    }
+    Ok(next.run(req).await)
+}
+
+// Server itself speaks plain HTTP, so HTTPS is known only from URI scheme or from
+// X-Forwarded-Proto set by a trusted proxy - the header from anyone else is ignored
+pub async fn require_https(
+    req: Request<Body>,
+    next: Next,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+) -> Result<Response, ServerError> {
+    let from_trusted_proxy = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(peer)| trusted_proxies.contains(&peer.ip()));
+
+    // Only the first proxy in the chain has seen the original scheme
+    let forwarded_https = from_trusted_proxy
+        && req
+            .headers()
+            .get("X-Forwarded-Proto")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+    if req.uri().scheme_str() != Some("https") && !forwarded_https {
+        tracing::warn!("Plaintext request is rejected: {}", req.uri());
+        return Err(ServerError::HttpsRequired);
+    }
+
+    Ok(next.run(req).await)
+}
+
+// New secret is generated before taking the lock, so the critical section is a plain swap
+// without awaits - the write can't be cancelled or fail halfway
+pub async fn rotate_secret(jwt_config: &RwLock<JwtConfig>) {
+    let new_secret = generate_secret();
+    let mut config = jwt_config.write().await;
+    config.secret = new_secret;
+}
+
 pub fn generate_secret() -> String {
     let mut rng = rand::rng();
     (0..32).map(|_| rng.sample(Alphanumeric) as char).collect()
 }
 
+// Checks the HS256 secret, any secret at least as long as the hash output is usable. It is checked
+// with RSA keys too, as game sessions are always signed with it
+pub fn check_secret(secret: &str) -> Result<(), ServerError> {
+    if secret.len() < MIN_SECRET_LEN {
+        return Err(ServerError::Configuration(format!(
+            "JWT secret must be at least {} bytes for HS256, got {}",
+            MIN_SECRET_LEN,
+            secret.len()
+        )));
+    }
+    Ok(())
+}
+
+// Secret shared by all nodes without passing the key itself around. Bumping the version
+// gives an unrelated key, which is how derived secrets are rotated
+pub fn derive_secret(passphrase: &str, key_version: u32) -> String {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(SECRET_KDF_SALT), passphrase.as_bytes())
+        .expand(format!("jwt-key-v{}", key_version).as_bytes(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    hex::encode(key)
+}
+
+pub const SCORE_SIGNATURE_HEADER: HeaderName = HeaderName::from_static("x-score-signature");
+
+// Hex HMAC-SHA256 of "player_name:player_score" keyed with SCORE_SIGNING_SECRET. Name is taken
+// as the server reads it, with whitespace collapsed. Compared in constant time by verify_slice
+pub fn verify_score_signature(record: &PlayerScore, sig: &str, secret: &str) -> bool {
+    let Ok(signature) = hex::decode(sig) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}:{}", record.player_name, record.player_score).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
 #[cfg(test)]
 mod security_tests {
-    use crate::connect_to_db;
+    use crate::{
+        config::{AppConfig, DEFAULT_AUTH_SCHEME},
+        connect_to_db,
+        db_access::{TEST_USER_HASH, get_test_db_pool, seed_test_user},
+    };
     use axum::routing::method_routing::get;
     use axum::{
         Router,
@@ -236,6 +942,46 @@ mod security_tests {
         assert_ne!(secret, secret2, "Secrets are same!");
     }
 
+    #[tokio::test]
+    async fn test_rotate_secret() {
+        let jwt_config = Arc::new(RwLock::new(JwtConfig::new("old_secret".to_string())));
+        let token = generate_jwt(
+            "test user",
+            &JwtConfig::new("old_secret".to_string()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't get token");
+
+        // Rotation has to wait for readers, so nobody sees config in the middle of swap
+        let reader = jwt_config.read().await;
+        let rotation = tokio::spawn({
+            let jwt_config = jwt_config.clone();
+            async move { rotate_secret(&jwt_config).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(reader.secret, "old_secret");
+        assert!(
+            !rotation.is_finished(),
+            "Rotation is not waiting for readers!"
+        );
+        drop(reader);
+        rotation.await.expect("Rotation failed!");
+
+        let config = jwt_config.read().await;
+        assert_ne!(config.secret, "old_secret", "Secret is not rotated!");
+        assert_eq!(config.secret.len(), 32);
+        assert!(
+            decode::<Claims>(
+                &token,
+                &DecodingKey::from_secret(config.secret.as_ref()),
+                &config.validation,
+            )
+            .is_err(),
+            "Old token is valid after rotation!"
+        );
+    }
+
     #[tokio::test]
     async fn test_jwt_middleware() {
         let exp = RealTime
@@ -250,12 +996,18 @@ mod security_tests {
             sub: "test user".into(),
             exp,
             role: "default".into(),
+            nbf: None,
+            token_type: TokenType::Access,
+            jti: None,
         };
 
         let bad_exp_claims = Claims {
             sub: "test user".into(),
             exp: bad_exp,
             role: "default".into(),
+            nbf: None,
+            token_type: TokenType::Access,
+            jti: None,
         };
 
         let secret = "test_secret";
@@ -283,23 +1035,26 @@ mod security_tests {
 
         let pool = connect_to_db().await.expect("Can't get pool");
 
-        let fake_state = AppState {
+        let fake_state = AppState::new(
             pool,
-            jwt_config: Arc::new(RwLock::new(JwtConfig::new(secret.to_string()))),
-        };
+            Arc::new(RwLock::new(JwtConfig::new(secret.to_string()))),
+        );
         
         let req = generate_test_request(vec![("Authorization", &format!("Bearer {}", token))]);
         let bad_exp_req = generate_test_request(vec![("Authorization", &format!("Bearer {}", bad_exp_token))]);
         let bad_secret_req = generate_test_request(vec![("Authorization", &format!("Bearer {}", bad_secret_token))]);
         let bad_no_auth_header_req = generate_test_request(vec![]);
-        let bad_bearer_req = generate_test_request(vec![("Authorization", &format!("bearer {}", token))]);
+        let lowercase_bearer_req =
+            generate_test_request(vec![("Authorization", &format!("bearer {}", token))]);
+        let bad_scheme_req =
+            generate_test_request(vec![("Authorization", &format!("Token {}", token))]);
 
         let app = Router::new()
             .route("/test", get(|| async { "Hello" }))
             .layer(middleware::from_fn({
                 move |req, next| {
                     let state = fake_state.clone();
-                    jwt_middleware(req, next, state)
+                    jwt_middleware(req, next, state, RealTime)
                 }
             }));
 
@@ -319,9 +1074,14 @@ mod security_tests {
             .oneshot(bad_no_auth_header_req)
             .await
             .expect("Can't get response");
-        let bad_bearer_res = app
+        let lowercase_bearer_res = app
             .clone()
-            .oneshot(bad_bearer_req)
+            .oneshot(lowercase_bearer_req)
+            .await
+            .expect("Can't get response");
+        let bad_scheme_res = app
+            .clone()
+            .oneshot(bad_scheme_req)
             .await
             .expect("Can't get response");
 
@@ -329,7 +1089,398 @@ mod security_tests {
         assert_eq!(bad_exp_res.status(), StatusCode::UNAUTHORIZED);
         assert_eq!(bad_secret_res.status(), StatusCode::UNAUTHORIZED);
         assert_eq!(bad_no_auth_res.status(), StatusCode::UNAUTHORIZED);
-        assert_eq!(bad_bearer_res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            lowercase_bearer_res.status(),
+            StatusCode::OK,
+            "Scheme is compared case-sensitively!"
+        );
+        assert_eq!(bad_scheme_res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_extract_token_custom_scheme() {
+        let headers_with = |auth: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, auth.parse().unwrap());
+            headers
+        };
+
+        let token_scheme = headers_with("Token my_test_key");
+        let upper_scheme = headers_with("TOKEN my_test_key");
+        let bearer_scheme = headers_with("Bearer my_test_key");
+
+        assert_eq!(
+            extract_token(&token_scheme, AuthMode::Header, "Token").ok(),
+            Some("my_test_key")
+        );
+        assert_eq!(
+            extract_token(&upper_scheme, AuthMode::Header, "token").ok(),
+            Some("my_test_key")
+        );
+        assert!(
+            extract_token(&bearer_scheme, AuthMode::Header, "Token").is_err(),
+            "Default scheme is accepted with custom one configured!"
+        );
+
+        let extractor = JwtKeyExtractor {
+            auth_mode: AuthMode::Header,
+            auth_scheme: "Token".to_string(),
+        };
+        assert_eq!(
+            extractor
+                .extract(&generate_test_request(vec![(
+                    "Authorization",
+                    "token my_test_key"
+                )]))
+                .expect("Can't extract key"),
+            token_key("my_test_key")
+        );
+        assert!(
+            extractor
+                .extract(&generate_test_request(vec![(
+                    "Authorization",
+                    "Bearer my_test_key"
+                )]))
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jwt_missing_exp_rejected() {
+        let secret = "test_secret";
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &serde_json::json!({"sub": "test user", "role": "default"}),
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .expect("Can't encode data!");
+
+        for leeway in [0, 60, u64::MAX] {
+            let mut config = JwtConfig::new(secret.to_string());
+            config.validation.leeway = leeway;
+
+            // Decoding into plain JSON, so the rejection comes from validation, not Claims shape
+            let result = decode::<serde_json::Value>(
+                &token,
+                &DecodingKey::from_secret(secret.as_bytes()),
+                &config.validation,
+            );
+            assert!(
+                matches!(
+                    result.as_ref().map_err(|e| e.kind()),
+                    Err(ErrorKind::MissingRequiredClaim(claim)) if claim == "exp"
+                ),
+                "Token without exp is not rejected with leeway {}: {:?}",
+                leeway,
+                result
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_require_user_agent() {
+        let app = Router::new()
+            .route("/test", get(|| async { "Hello" }))
+            .layer(middleware::from_fn(require_user_agent));
+        let status_for = |headers: Vec<(&str, &str)>| {
+            let app = app.clone();
+            let request = generate_test_request(headers);
+            async move {
+                app.oneshot(request)
+                    .await
+                    .expect("Can't get response")
+                    .status()
+            }
+        };
+
+        assert_eq!(status_for(vec![]).await, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status_for(vec![("User-Agent", " ")]).await,
+            StatusCode::BAD_REQUEST,
+            "Blank User-Agent is accepted!"
+        );
+        assert_eq!(
+            status_for(vec![("User-Agent", "FlappyDragon/2.0")]).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validation_update_at_runtime() {
+        let secret = "test_secret";
+        let mut jwt_config = JwtConfig::new(secret.into());
+        let expired = generate_jwt(
+            "test user",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &MockTime,
+        )
+        .expect("Can't get token");
+        assert!(jwt_config.decode_claims(&expired).is_err());
+
+        let settings = jwt_config
+            .apply_update(ValidationUpdate {
+                validate_exp: Some(false),
+                ..ValidationUpdate::default()
+            })
+            .expect("Can't update validation!");
+        assert!(!settings.validate_exp);
+        assert!(
+            jwt_config.decode_claims(&expired).is_ok(),
+            "Expired token is rejected with expiry check off!"
+        );
+
+        jwt_config
+            .apply_update(ValidationUpdate {
+                validate_exp: Some(true),
+                ..ValidationUpdate::default()
+            })
+            .expect("Can't update validation!");
+        assert!(jwt_config.decode_claims(&expired).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validation_update_keeps_signature_check() {
+        let mut jwt_config = JwtConfig::new("test_secret".into());
+        let before = jwt_config.settings();
+
+        let result = jwt_config.apply_update(ValidationUpdate {
+            validate_signature: Some(false),
+            leeway: Some(0),
+            ..ValidationUpdate::default()
+        });
+
+        assert!(matches!(result, Err(ServerError::Validation(_))));
+        assert_eq!(jwt_config.settings(), before, "Rejected update is applied!");
+        assert!(
+            jwt_config
+                .apply_update(ValidationUpdate::default())
+                .is_err()
+        );
+        assert!(
+            serde_json::from_str::<ValidationUpdate>(r#"{"algorithms": []}"#).is_err(),
+            "Field outside of safe subset is accepted!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_ip() {
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Forwarded-For",
+            "1.2.3.4, 203.0.113.7, 10.0.0.2".parse().unwrap(),
+        );
+
+        assert_eq!(client_ip(&headers, proxy, &[proxy]), client);
+        assert_eq!(
+            client_ip(&headers, client, &[proxy]),
+            client,
+            "Forwarded header from untrusted peer is used!"
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), proxy, &[proxy]), proxy);
+    }
+
+    #[tokio::test]
+    async fn test_derive_secret() {
+        let secret = derive_secret("correct horse battery staple", 1);
+
+        assert_eq!(secret, derive_secret("correct horse battery staple", 1));
+        assert_eq!(secret.len(), 64);
+        assert_ne!(
+            secret,
+            derive_secret("correct horse battery staple", 2),
+            "Key version doesn't change the secret!"
+        );
+        assert_ne!(secret, derive_secret("correct horse battery", 1));
+    }
+
+    #[tokio::test]
+    async fn test_check_secret() {
+        let short = check_secret("too-short-for-hs256").expect_err("Short secret is accepted!");
+        assert!(matches!(short, ServerError::Configuration(_)));
+
+        assert!(check_secret(&generate_secret()).is_ok());
+        assert!(check_secret(&derive_secret("correct horse battery staple", 1)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_require_admin() {
+        let app = Router::new()
+            .route("/test", get(|| async { "Hello" }))
+            .layer(middleware::from_fn(require_admin));
+        let status_for = |role: Option<&str>| {
+            let app = app.clone();
+            let mut request = generate_test_request(vec![]);
+            if let Some(role) = role {
+                request.extensions_mut().insert(AuthRole(role.into()));
+            }
+            async move {
+                app.oneshot(request)
+                    .await
+                    .expect("Can't get response")
+                    .status()
+            }
+        };
+
+        assert_eq!(status_for(None).await, StatusCode::FORBIDDEN);
+        assert_eq!(status_for(Some("default")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status_for(Some(ADMIN_ROLE)).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_jwt_nbf_leeway() {
+        let secret = "test_secret";
+        let now = jsonwebtoken::get_current_timestamp() as usize;
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &Claims {
+                sub: "test user".into(),
+                exp: now + 3600,
+                role: "default".into(),
+                nbf: Some(now + 30),
+                token_type: TokenType::Access,
+                jti: None,
+            },
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .expect("Can't encode data!");
+
+        let tight = JwtConfig::new(secret.to_string()).with_nbf_leeway(0);
+        let loose = JwtConfig::new(secret.to_string()).with_nbf_leeway(60);
+
+        assert!(
+            matches!(
+                tight.decode_claims(&token),
+                Err(JwtError::DecodeError(e)) if *e.kind() == ErrorKind::ImmatureSignature
+            ),
+            "Future nbf is accepted without leeway!"
+        );
+        assert_eq!(
+            loose
+                .decode_claims(&token)
+                .expect("Future nbf is rejected within leeway!")
+                .nbf,
+            Some(now + 30)
+        );
+
+        // Expiry leeway doesn't cover nbf anymore
+        let mut loose_exp = JwtConfig::new(secret.to_string()).with_nbf_leeway(0);
+        loose_exp.validation.leeway = 3600;
+        assert!(loose_exp.decode_claims(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_jwt_required_claims_config() {
+        let secret = "test_secret";
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &serde_json::json!({"exp": usize::MAX, "role": "default"}),
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .expect("Can't encode data!");
+        let decode_with = |config: JwtConfig| {
+            decode::<serde_json::Value>(
+                &token,
+                &DecodingKey::from_secret(secret.as_bytes()),
+                &config.validation,
+            )
+        };
+
+        assert!(
+            decode_with(JwtConfig::new(secret.to_string())).is_err(),
+            "Token without sub is accepted!"
+        );
+        assert!(
+            decode_with(JwtConfig::new(secret.to_string()).with_required_claims(&["exp"])).is_ok(),
+            "Required claims are not tunable!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jwt_middleware_cookie_mode() {
+        let secret = "test_secret";
+        let token = generate_jwt(
+            "test user",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't get token");
+        let pool = connect_to_db().await.expect("Can't get pool");
+
+        let app_for = |auth_mode: AuthMode| {
+            let state = AppState::new(
+                pool.clone(),
+                Arc::new(RwLock::new(JwtConfig::new(secret.to_string()))),
+            )
+            .with_config(AppConfig {
+                auth_mode,
+                ..AppConfig::default()
+            });
+
+            Router::new()
+                .route("/test", get(|| async { "Hello" }))
+                .layer(middleware::from_fn(move |req, next| {
+                    jwt_middleware(req, next, state.clone(), RealTime)
+                }))
+        };
+
+        let cookie = format!("theme=dark; {}={}", AUTH_COOKIE, token);
+        let cookie_res = app_for(AuthMode::Cookie)
+            .oneshot(generate_test_request(vec![("Cookie", &cookie)]))
+            .await
+            .expect("Can't get response");
+        let bad_cookie_res = app_for(AuthMode::Cookie)
+            .oneshot(generate_test_request(vec![(
+                "Cookie",
+                &format!("{}=garbage", AUTH_COOKIE),
+            )]))
+            .await
+            .expect("Can't get response");
+        let header_in_cookie_mode_res = app_for(AuthMode::Cookie)
+            .oneshot(generate_test_request(vec![(
+                "Authorization",
+                &format!("Bearer {}", token),
+            )]))
+            .await
+            .expect("Can't get response");
+        let header_mode_res = app_for(AuthMode::Header)
+            .oneshot(generate_test_request(vec![("Cookie", &cookie)]))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(cookie_res.status(), StatusCode::OK);
+        assert_eq!(bad_cookie_res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(header_in_cookie_mode_res.status(), StatusCode::OK);
+        assert_eq!(
+            header_mode_res.status(),
+            StatusCode::UNAUTHORIZED,
+            "Cookie is accepted in header mode!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jwt_extractor_cookie_mode() {
+        let request = generate_test_request(vec![("Cookie", "token=my_test_key; other=1")]);
+
+        let cookie_extractor = JwtKeyExtractor {
+            auth_mode: AuthMode::Cookie,
+            auth_scheme: DEFAULT_AUTH_SCHEME.to_string(),
+        };
+        let header_extractor = JwtKeyExtractor {
+            auth_mode: AuthMode::Header,
+            auth_scheme: DEFAULT_AUTH_SCHEME.to_string(),
+        };
+
+        assert_eq!(
+            cookie_extractor
+                .extract(&request)
+                .expect("Can't extract key"),
+            token_key("my_test_key")
+        );
+        assert!(header_extractor.extract(&request).is_err());
     }
 
     #[tokio::test]
@@ -354,16 +1505,42 @@ mod security_tests {
             .body(Body::empty())
             .expect("Can't create request");
 
-        let x = JwtKeyExtractor;
+        let x = JwtKeyExtractor {
+            auth_mode: AuthMode::Header,
+            auth_scheme: DEFAULT_AUTH_SCHEME.to_string(),
+        };
         let right_key = x.extract(&request_good).expect("Can't extract key");
         let bad_key = x.extract(&request_bad).expect("Can't extract key");
         let bad_key_wo_head = x.extract(&request_bad_wo_header);
 
-        assert_eq!(right_key, "my_test_key".to_string());
-        assert_ne!(bad_key, "my_test_key".to_string());
+        assert_eq!(right_key, token_key("my_test_key"));
+        assert_ne!(bad_key, token_key("my_test_key"));
         assert!(bad_key_wo_head.is_err());
     }
 
+    #[tokio::test]
+    async fn test_jwt_extractor_hashes_token() {
+        let giant_token = "x".repeat(8 * 1024);
+        let auth = format!("Bearer {}", giant_token);
+        let extractor = JwtKeyExtractor {
+            auth_mode: AuthMode::Header,
+            auth_scheme: DEFAULT_AUTH_SCHEME.to_string(),
+        };
+        let key_for = |auth: &str| {
+            extractor
+                .extract(&generate_test_request(vec![("Authorization", auth)]))
+                .expect("Can't extract key")
+        };
+
+        assert_eq!(
+            key_for(&auth),
+            key_for(&auth),
+            "Same token gives different keys!"
+        );
+        assert_ne!(key_for(&auth), key_for(&format!("{}y", auth)));
+        assert_eq!(key_for(&auth), token_key(&giant_token));
+    }
+
     #[tokio::test]
     async fn test_set_up_security_headers() {
         let request = Request::builder()
@@ -408,6 +1585,96 @@ mod security_tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_check_origin() {
+        let allowed = Arc::new(vec!["http://allowed.test".to_string()]);
+        let app = Router::new()
+            .route("/test", get(|| async { "Hello" }))
+            .layer(middleware::from_fn(move |req, next| {
+                check_origin(req, next, allowed.clone())
+            }));
+
+        let allowed_res = app
+            .clone()
+            .oneshot(generate_test_request(vec![(
+                "Origin",
+                "http://allowed.test",
+            )]))
+            .await
+            .expect("Can't get response");
+        let disallowed_res = app
+            .clone()
+            .oneshot(generate_test_request(vec![("Origin", "http://evil.test")]))
+            .await
+            .expect("Can't get response");
+        let no_origin_res = app
+            .oneshot(generate_test_request(vec![]))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(allowed_res.status(), StatusCode::OK);
+        assert_eq!(disallowed_res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(no_origin_res.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(disallowed_res.into_body(), 1024)
+            .await
+            .expect("Can't read body");
+        assert!(
+            String::from_utf8_lossy(&body).contains("http://evil.test"),
+            "Offending origin is not reported!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_require_https() {
+        let proxy = SocketAddr::from(([10, 0, 0, 1], 4000));
+        let stranger = SocketAddr::from(([192, 168, 0, 7], 4000));
+        let trusted = Arc::new(vec![proxy.ip()]);
+        let app = Router::new()
+            .route("/test", get(|| async { "Hello" }))
+            .layer(middleware::from_fn(move |req, next| {
+                require_https(req, next, trusted.clone())
+            }));
+
+        let request_from = |peer: SocketAddr, headers: Vec<(&str, &str)>| {
+            let mut request = generate_test_request(headers);
+            request.extensions_mut().insert(ConnectInfo(peer));
+            request
+        };
+
+        let plaintext_res = app
+            .clone()
+            .oneshot(request_from(proxy, vec![]))
+            .await
+            .expect("Can't get response");
+        let forwarded_res = app
+            .clone()
+            .oneshot(request_from(
+                proxy,
+                vec![("X-Forwarded-Proto", "https, http")],
+            ))
+            .await
+            .expect("Can't get response");
+        let forwarded_http_res = app
+            .clone()
+            .oneshot(request_from(proxy, vec![("X-Forwarded-Proto", "http")]))
+            .await
+            .expect("Can't get response");
+        let spoofed_res = app
+            .oneshot(request_from(stranger, vec![("X-Forwarded-Proto", "https")]))
+            .await
+            .expect("Can't get response");
+
+        assert_eq!(plaintext_res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(forwarded_res.status(), StatusCode::OK);
+        assert_eq!(forwarded_http_res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            spoofed_res.status(),
+            StatusCode::BAD_REQUEST,
+            "Forwarded scheme from untrusted peer is accepted!"
+        );
+    }
+
     #[tokio::test]
     async fn test_jwt_generate() {
         let test_user_id = "test_user";
@@ -416,8 +1683,13 @@ mod security_tests {
         let mut no_time_validation = Validation::new(Algorithm::HS256);
         no_time_validation.validate_exp = false;
 
-        let token = generate_jwt(test_user_id, test_secret, test_role, &MockTime)
-            .expect("Can't genereate jwt it test!");
+        let token = generate_jwt(
+            test_user_id,
+            &JwtConfig::new(test_secret.to_string()),
+            test_role,
+            &MockTime,
+        )
+        .expect("Can't genereate jwt it test!");
 
         //Creating decode that will fail because of old data
         let cursed_decode = decode::<Claims>(
@@ -444,4 +1716,290 @@ mod security_tests {
             &((MockTime.now() + Duration::hours(1)).timestamp() as usize)
         );
     }
+
+    #[tokio::test]
+    async fn test_jwt_configured_ttl() {
+        let jwt_config = crate::set_up_jwt(&AppConfig {
+            jwt_ttl_secs: 10,
+            ..AppConfig::default()
+        });
+        let jwt_config = jwt_config.read().await;
+        assert_eq!(jwt_config.access_token_ttl, Duration::seconds(10));
+
+        let token = generate_jwt("test_user", &jwt_config, "default", &MockTime)
+            .expect("Can't generate jwt!");
+        let mut no_time_validation = Validation::new(Algorithm::HS256);
+        no_time_validation.validate_exp = false;
+        let claims = decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(jwt_config.secret.as_bytes()),
+            &no_time_validation,
+        )
+        .expect("Can't decode test jwt!")
+        .claims;
+
+        assert_eq!(claims.exp, (MockTime.now().timestamp() + 10) as usize);
+    }
+
+    struct EndOfTime;
+
+    impl TimeProvider for EndOfTime {
+        fn now(&self) -> DateTime<chrono::Utc> {
+            DateTime::<Utc>::MAX_UTC - Duration::minutes(1)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwt_generate_timestamp_overflow() {
+        let result = generate_jwt(
+            "test_user",
+            &JwtConfig::new("serious_secret".to_string()),
+            "default",
+            &EndOfTime,
+        );
+
+        assert!(
+            matches!(result, Err(JwtError::TimestampOverflow)),
+            "Overflowing expiration is not reported!"
+        );
+        assert_eq!(
+            result.unwrap_err().into_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_password_with_fixed_hash() {
+        assert!(
+            verify_password("password", TEST_USER_HASH),
+            "Right password is refused!"
+        );
+        assert!(
+            !verify_password("Password", TEST_USER_HASH),
+            "Wrong password is accepted!"
+        );
+        assert!(
+            !verify_password("", TEST_USER_HASH),
+            "Empty password is accepted!"
+        );
+        assert!(
+            !verify_password("password", "not a hash"),
+            "Malformed hash matches!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user() {
+        let pool = get_test_db_pool().await;
+        seed_test_user(&pool).await;
+
+        let user = validate_user(&pool, "user", "password")
+            .await
+            .expect("Right password is refused!");
+        assert_eq!(user.role, "default");
+
+        assert_eq!(
+            validate_user(&pool, "user", "wrong").await.unwrap_err(),
+            LoginError::WrongPassword
+        );
+        assert_eq!(
+            validate_user(&pool, "nobody", "password")
+                .await
+                .unwrap_err(),
+            LoginError::UnknownUser
+        );
+        let status = ServerError::from(LoginError::WrongPassword)
+            .into_response()
+            .status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_revoked_tokens_are_inactive() {
+        let jwt_config = JwtConfig::new("test_secret".to_string());
+        let revoked_tokens = RevokedTokens::default();
+        let (access, refresh) = generate_jwt_pair("test user", &jwt_config, "default", &RealTime)
+            .expect("Can't get tokens");
+        let access_claims = jwt_config.decode_claims(&access).unwrap();
+
+        revoked_tokens.revoke(access_claims.jti.unwrap(), access_claims.exp, &RealTime);
+        assert!(!introspect_token(&jwt_config, &revoked_tokens, &access).active);
+        assert!(introspect_token(&jwt_config, &revoked_tokens, &refresh).active);
+
+        assert!(matches!(
+            revoke_refresh_token(
+                &jwt_config,
+                &revoked_tokens,
+                &refresh,
+                "other user",
+                &RealTime
+            ),
+            Err(JwtError::WrongTokenType)
+        ));
+        revoke_refresh_token(
+            &jwt_config,
+            &revoked_tokens,
+            &refresh,
+            "test user",
+            &RealTime,
+        )
+        .expect("Can't revoke refresh token");
+        assert!(!introspect_token(&jwt_config, &revoked_tokens, &refresh).active);
+        assert!(matches!(
+            refresh_access_token(&jwt_config, &revoked_tokens, &refresh, &RealTime),
+            Err(JwtError::Revoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_token_types_are_not_interchangeable() {
+        let secret = "test_secret";
+        let jwt_config = JwtConfig::new(secret.to_string());
+        let (access, refresh) = generate_jwt_pair(
+            "test user",
+            &JwtConfig::new(secret.to_string()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't get tokens");
+
+        // Access token is refused by /refresh, refresh token gets a new access token
+        let revoked_tokens = RevokedTokens::default();
+        assert!(matches!(
+            refresh_access_token(&jwt_config, &revoked_tokens, &access, &RealTime),
+            Err(JwtError::WrongTokenType)
+        ));
+        let refreshed = refresh_access_token(&jwt_config, &revoked_tokens, &refresh, &RealTime)
+            .expect("Can't refresh token");
+        let claims = jwt_config
+            .decode_claims(&refreshed)
+            .expect("Can't decode token");
+        assert_eq!(claims.token_type, TokenType::Access);
+        assert_eq!(
+            (claims.sub.as_str(), claims.role.as_str()),
+            ("test user", "default")
+        );
+
+        // Refresh token is refused by protected routes
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let state = AppState::new(pool, Arc::new(RwLock::new(jwt_config)));
+        let app = Router::new()
+            .route("/test", get(|| async { "Hello" }))
+            .layer(middleware::from_fn(move |req, next| {
+                jwt_middleware(req, next, state.clone(), RealTime)
+            }));
+        let status_with = |token: &str| {
+            let req = generate_test_request(vec![("Authorization", &format!("Bearer {}", token))]);
+            let app = app.clone();
+            async move { app.oneshot(req).await.expect("Can't get response").status() }
+        };
+
+        assert_eq!(status_with(&refreshed).await, StatusCode::OK);
+        assert_eq!(
+            status_with(&refresh).await,
+            StatusCode::UNAUTHORIZED,
+            "Refresh token reaches protected route!"
+        );
+    }
+
+    const RS256_PRIVATE_PEM: &[u8] = include_bytes!("../test_data/jwt/rs256_private.pem");
+    const RS256_PUBLIC_PEM: &[u8] = include_bytes!("../test_data/jwt/rs256_public.pem");
+
+    fn rs256_config() -> JwtConfig {
+        let keys = RsaKeys::from_pem(RS256_PRIVATE_PEM, RS256_PUBLIC_PEM).expect("Can't load keys");
+        JwtConfig::new(generate_secret()).with_rsa_keys(keys)
+    }
+
+    #[tokio::test]
+    async fn test_rs256_round_trip() {
+        let jwt_config = rs256_config();
+        let token =
+            generate_jwt("test user", &jwt_config, "default", &RealTime).expect("Can't get token");
+
+        let header = jsonwebtoken::decode_header(&token).expect("Can't decode header");
+        assert_eq!(header.alg, Algorithm::RS256);
+        let claims = jwt_config
+            .decode_claims(&token)
+            .expect("RS256 token is refused!");
+        assert_eq!(claims.sub, "test user");
+
+        // Public key alone is enough to check the token
+        let public_key = DecodingKey::from_rsa_pem(RS256_PUBLIC_PEM).expect("Can't load key");
+        decode::<Claims>(&token, &public_key, &Validation::new(Algorithm::RS256))
+            .expect("Token can't be checked with public key!");
+    }
+
+    #[tokio::test]
+    async fn test_hs256_token_fails_rs256_validation() {
+        let jwt_config = rs256_config();
+        let hs256_token = generate_jwt(
+            "test user",
+            &JwtConfig::new(jwt_config.secret.clone()),
+            "default",
+            &RealTime,
+        )
+        .expect("Can't get token");
+
+        assert!(
+            jwt_config.decode_claims(&hs256_token).is_err(),
+            "HS256 token is accepted with RS256 keys!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rsa_keys_of_different_pairs() {
+        let other_public = include_bytes!("../test_data/jwt/rs256_other_public.pem");
+
+        let mismatched = RsaKeys::from_pem(RS256_PRIVATE_PEM, other_public);
+        assert!(matches!(mismatched, Err(ServerError::Configuration(_))));
+        let garbage = RsaKeys::from_pem(b"not a key", RS256_PUBLIC_PEM);
+        assert!(matches!(garbage, Err(ServerError::Configuration(_))));
+    }
+
+    // MockTime moved by the given offset, for checking tokens at a later moment
+    #[derive(Clone, Copy)]
+    struct LaterThanMock(Duration);
+
+    impl TimeProvider for LaterThanMock {
+        fn now(&self) -> DateTime<chrono::Utc> {
+            MockTime.now() + self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwt_middleware_expiry_with_mock_time() {
+        let secret = "test_secret";
+        let jwt_config = JwtConfig::new(secret.to_string());
+        let token =
+            generate_jwt("test user", &jwt_config, "default", &MockTime).expect("Can't get token");
+
+        let expired = jwt_config.decode_claims_at(&token, &LaterThanMock(Duration::hours(2)));
+        assert!(matches!(
+            expired,
+            Err(JwtError::DecodeError(e)) if *e.kind() == ErrorKind::ExpiredSignature
+        ));
+
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let state = AppState::new(pool, Arc::new(RwLock::new(jwt_config)));
+        let status_at = |time: LaterThanMock| {
+            let state = state.clone();
+            let app = Router::new()
+                .route("/test", get(|| async { "Hello" }))
+                .layer(middleware::from_fn(move |req, next| {
+                    jwt_middleware(req, next, state.clone(), time)
+                }));
+            let req = generate_test_request(vec![("Authorization", &format!("Bearer {}", token))]);
+            async move { app.oneshot(req).await.expect("Can't get response").status() }
+        };
+
+        assert_eq!(
+            status_at(LaterThanMock(Duration::zero())).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_at(LaterThanMock(Duration::hours(2))).await,
+            StatusCode::UNAUTHORIZED,
+            "Expired token is accepted!"
+        );
+    }
 }
@@ -0,0 +1,93 @@
+use std::sync::Mutex;
+
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Shared},
+};
+
+// Concurrent calls with the same key share one run of the future and all get its result.
+// Only the latest key is kept - a call with a new key starts its own run
+pub struct SingleFlight<K, T: Clone> {
+    in_flight: Mutex<Option<(K, Shared<BoxFuture<'static, T>>)>>,
+}
+
+impl<K, T: Clone> Default for SingleFlight<K, T> {
+    fn default() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(None),
+        }
+    }
+}
+
+impl<K: PartialEq, T: Clone + Send + Sync + 'static> SingleFlight<K, T> {
+    pub async fn run<F>(&self, key: K, future: F) -> T
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let shared = {
+            let mut in_flight = self.in_flight.lock().expect("Single flight is poisoned!");
+            match in_flight.as_ref() {
+                Some((current, shared)) if *current == key => shared.clone(),
+                _ => {
+                    let shared = future.boxed().shared();
+                    *in_flight = Some((key, shared.clone()));
+                    shared
+                }
+            }
+        };
+
+        let result = shared.clone().await;
+
+        // Finished run is dropped, so later calls never get an old result
+        let mut in_flight = self.in_flight.lock().expect("Single flight is poisoned!");
+        if in_flight
+            .as_ref()
+            .is_some_and(|(_, current)| current.ptr_eq(&shared))
+        {
+            *in_flight = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod single_flight_tests {
+    use super::*;
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    async fn counted_query(runs: Arc<AtomicUsize>, value: u32) -> u32 {
+        runs.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        value
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_shares_run() {
+        let flight = Arc::new(SingleFlight::<u64, u32>::default());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let calls = (0..50).map(|_| {
+            let flight = flight.clone();
+            let runs = runs.clone();
+            tokio::spawn(async move { flight.run(1, counted_query(runs, 7)).await })
+        });
+        for result in futures::future::join_all(calls).await {
+            assert_eq!(result.expect("Call panicked"), 7);
+        }
+        assert_eq!(
+            runs.load(Ordering::SeqCst),
+            1,
+            "Concurrent calls are not shared!"
+        );
+
+        assert_eq!(flight.run(1, counted_query(runs.clone(), 8)).await, 8);
+        assert_eq!(flight.run(2, counted_query(runs.clone(), 9)).await, 9);
+        assert_eq!(runs.load(Ordering::SeqCst), 3, "Finished run is reused!");
+    }
+}
@@ -1,17 +1,160 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
 
-use crate::security::JwtConfig;
+use crate::{
+    audit::AuditLog,
+    config::AppConfig,
+    db_access::PlayerScore,
+    error::ServerError,
+    game::GameId,
+    game_session::UsedSessions,
+    name_limit::NameLimiter,
+    notice::Notice,
+    recent_submissions::RecentSubmissions,
+    revoked_tokens::RevokedTokens,
+    security::JwtConfig,
+    single_flight::SingleFlight,
+    store::{PgScoreStore, ScoreStore},
+};
 use sqlx::PgPool;
 use tokio::sync::RwLock;
 
+pub type BoardReads = SingleFlight<(GameId, u64), Result<Vec<PlayerScore>, ServerError>>;
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
+    pub read_pool: Option<PgPool>,
+    pub scores: Arc<dyn ScoreStore>,
+    // Game whose board the score store works on
+    pub game: GameId,
     pub jwt_config: Arc<RwLock<JwtConfig>>,
+    // Ids of tokens revoked by logout, emptied when the HS256 secret is rotated
+    pub revoked_tokens: Arc<RevokedTokens>,
+    pub config: Arc<AppConfig>,
+    // Version of the board, changed by every write. Monotonic per instance, starts from 0 on restart.
+    // It is bumped once the primary has committed, while the board is read from the replica
+    // when READ_DATABASE_URL is set. A lagging replica can send the board from before the write
+    // with the new version, and clients holding that version get 304 until the next write
+    pub board_version: Arc<AtomicU64>,
+    pub submissions: Arc<SubmissionStats>,
+    pub notice: Arc<RwLock<Notice>>,
+    pub audit: Arc<AuditLog>,
+    pub names: Arc<NameLimiter>,
+    pub recent: Arc<RecentSubmissions>,
+    // Game sessions a score was already accepted with, used only with GAME_SESSIONS
+    pub used_sessions: Arc<UsedSessions>,
+    // Requests rejected by governors since start, counted only with RATE_LIMIT_EVENTS
+    pub rate_limited: Arc<AtomicU64>,
+    // Board reads in flight keyed by game and board version, shared only with COALESCE_READS
+    pub board_reads: Arc<BoardReads>,
+    pub started_at: Instant,
+}
+
+// Counters of score submissions, cumulative since start of the instance and never reset.
+// Invalid and failed submissions are counted only in total
+#[derive(Debug, Default)]
+pub struct SubmissionStats {
+    pub total: AtomicU64,
+    pub recorded: AtomicU64,
+    pub rejected: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubmissionSnapshot {
+    pub total: u64,
+    pub recorded: u64,
+    pub rejected: u64,
+}
+
+impl SubmissionStats {
+    pub fn record_submission(&self) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Outcome of a valid submission - stored on the board or rejected as unworthy
+    pub fn record_outcome(&self, stored: bool) {
+        let counter = match stored {
+            true => &self.recorded,
+            false => &self.rejected,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> SubmissionSnapshot {
+        SubmissionSnapshot {
+            total: self.total.load(Ordering::Relaxed),
+            recorded: self.recorded.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
 }
 
 impl AppState {
     pub fn new(pool: PgPool, jwt_config: Arc<RwLock<JwtConfig>>) -> Self {
-        AppState { pool, jwt_config }
+        AppState {
+            scores: Arc::new(PgScoreStore::new(pool.clone(), None)),
+            game: GameId::default(),
+            pool,
+            read_pool: None,
+            jwt_config,
+            revoked_tokens: Arc::new(RevokedTokens::default()),
+            config: Arc::new(AppConfig::default()),
+            board_version: Arc::new(AtomicU64::new(0)),
+            submissions: Arc::new(SubmissionStats::default()),
+            notice: Arc::new(RwLock::new(Notice::default())),
+            audit: Arc::new(AuditLog::new(None)),
+            names: Arc::new(NameLimiter::default()),
+            recent: Arc::new(RecentSubmissions::default()),
+            used_sessions: Arc::new(UsedSessions::default()),
+            rate_limited: Arc::new(AtomicU64::new(0)),
+            board_reads: Arc::new(SingleFlight::default()),
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn board_version(&self) -> u64 {
+        self.board_version.load(Ordering::SeqCst)
+    }
+
+    // Flush bumps version too - going back to old number could match stale client caches
+    pub fn bump_board_version(&self) -> u64 {
+        self.board_version.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    // Audit log depends on config, so it is created anew here
+    pub fn with_config(mut self, config: AppConfig) -> Self {
+        self.audit = Arc::new(AuditLog::new(config.audit_chain_key.clone()));
+        self.config = Arc::new(config);
+        self
+    }
+
+    pub fn with_notice(mut self, notice: Notice) -> Self {
+        self.notice = Arc::new(RwLock::new(notice));
+        self
+    }
+
+    pub fn with_read_pool(mut self, read_pool: Option<PgPool>) -> Self {
+        self.scores = Arc::new(PgScoreStore::new(self.pool.clone(), read_pool.clone()));
+        self.read_pool = read_pool;
+        self
+    }
+
+    // Same state with the score store on the board of the game, everything else is shared
+    pub fn for_game(mut self, game: GameId) -> Self {
+        self.scores = self.scores.for_game(&game);
+        self.game = game;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_score_store(mut self, scores: Arc<dyn ScoreStore>) -> Self {
+        self.scores = scores;
+        self
     }
 }
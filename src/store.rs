@@ -0,0 +1,772 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, stream::BoxStream};
+use sqlx::PgPool;
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+
+use crate::{
+    db_access::{
+        PlayerScore, RankedPlayerScore, add_new_score_db, board_cutoff_db, count_scores_db,
+        delete_players_db, flush_scores_db, get_archived_scores_db, get_player_best_db,
+        get_ranked_scores_db, get_scores_db, get_scores_paginated_db, get_scores_since_db,
+        player_best_db, score_rank_db, season_archived_db, stream_scores_db,
+    },
+    error::ServerError,
+    game::GameId,
+    player_name::PlayerName,
+};
+
+// Storage backend of the leaderboard, handlers work only through this trait.
+// Store works on the board of one game, the default one unless it came from for_game
+#[async_trait]
+pub trait ScoreStore: Send + Sync {
+    // Same storage scoped to the board of another game
+    fn for_game(&self, game: &GameId) -> Arc<dyn ScoreStore>;
+
+    async fn get_scores(&self) -> Result<Vec<PlayerScore>, ServerError>;
+
+    // Slice of the board in board order, ties keep their insertion order across pages
+    async fn get_scores_page(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PlayerScore>, ServerError>;
+
+    // Board limited to scores posted from the given time
+    async fn get_scores_since(&self, since: DateTime<Utc>)
+    -> Result<Vec<PlayerScore>, ServerError>;
+
+    // Same board as get_scores, but row by row for boards too big to hold in memory
+    fn stream_scores(&self) -> BoxStream<'static, Result<PlayerScore, ServerError>>;
+
+    // Returns stored representation of the score or None if it is not worthy of the board.
+    // With dedup a player keeps only the best score, lower ones are not stored, but
+    // with counted plays they still bump play count of the player's entry.
+    // Client version is kept for debugging and never returned with the board.
+    // Floor is the lowest score accepted while the board has free places,
+    // board size is the number of places kept after insert
+    async fn add_new_score(
+        &self,
+        score: PlayerScore,
+        dedup: bool,
+        count_plays: bool,
+        client_version: Option<&str>,
+        floor: i32,
+        board_size: usize,
+    ) -> Result<Option<PlayerScore>, ServerError>;
+
+    async fn flush(&self) -> Result<(), ServerError>;
+
+    // Removes all scores of the players at once, on boards of every game.
+    // Returns removed count per name present on any board
+    async fn delete_players(&self, names: &[String]) -> Result<HashMap<String, i64>, ServerError>;
+
+    // Number of scores flush would delete
+    async fn count(&self) -> Result<i64, ServerError>;
+
+    // Whether the season has ended and its board was moved to the archive
+    async fn season_archived(&self, season: i32) -> Result<bool, ServerError>;
+
+    // Page of archived board of the season, None if the season has no archive
+    async fn get_archived_scores(
+        &self,
+        season: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Option<Vec<PlayerScore>>, ServerError>;
+
+    // Whole board with positions, equal scores share the rank
+    async fn ranked_scores(&self) -> Result<Vec<RankedPlayerScore>, ServerError>;
+
+    // Rank of the score by its row id, None if it is no longer on the board
+    async fn rank(&self, id: i32) -> Result<Option<i64>, ServerError>;
+
+    // Best score of the player on the board, None if the player has no entry
+    async fn player_best(&self, score: &PlayerScore) -> Result<Option<i32>, ServerError>;
+
+    // Best entry stored under the name, None if the name is not on the board
+    async fn best_by_name(&self, name: &PlayerName) -> Result<Option<PlayerScore>, ServerError>;
+
+    // Lowest score which is still worthy of the board
+    async fn cutoff(&self, floor: i32, board_size: usize) -> Result<i32, ServerError>;
+}
+
+pub struct PgScoreStore {
+    pool: PgPool,
+    read_pool: Option<PgPool>,
+    game: GameId,
+}
+
+impl PgScoreStore {
+    pub fn new(pool: PgPool, read_pool: Option<PgPool>) -> Self {
+        PgScoreStore {
+            pool,
+            read_pool,
+            game: GameId::default(),
+        }
+    }
+
+    fn game(&self) -> &str {
+        self.game.as_str()
+    }
+
+    // Pool for read-only queries - replica if configured, primary otherwise
+    pub fn read_pool(&self) -> &PgPool {
+        self.read_pool.as_ref().unwrap_or(&self.pool)
+    }
+
+    // Query on the read pool, repeated on the primary when the replica fails. Down replica
+    // is not critical for health, so reads must go on without it
+    async fn read<'a, T, Fut>(&'a self, query: impl Fn(&'a PgPool) -> Fut) -> Result<T, ServerError>
+    where
+        Fut: Future<Output = Result<T, ServerError>>,
+    {
+        match query(self.read_pool()).await {
+            Err(e) if self.read_pool.is_some() => {
+                tracing::warn!("Read replica query failed: {}! Reading from primary", e);
+                query(&self.pool).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[async_trait]
+impl ScoreStore for PgScoreStore {
+    fn for_game(&self, game: &GameId) -> Arc<dyn ScoreStore> {
+        Arc::new(PgScoreStore {
+            pool: self.pool.clone(),
+            read_pool: self.read_pool.clone(),
+            game: game.clone(),
+        })
+    }
+
+    async fn get_scores(&self) -> Result<Vec<PlayerScore>, ServerError> {
+        self.read(|pool| get_scores_db(pool, self.game())).await
+    }
+
+    async fn get_scores_page(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PlayerScore>, ServerError> {
+        self.read(|pool| get_scores_paginated_db(pool, self.game(), limit, offset))
+            .await
+    }
+
+    async fn get_scores_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<PlayerScore>, ServerError> {
+        self.read(|pool| get_scores_since_db(pool, self.game(), since))
+            .await
+    }
+
+    // Replica failing on the first row is left for the primary, later failures end the stream
+    // as rows already sent can't be taken back
+    fn stream_scores(&self) -> BoxStream<'static, Result<PlayerScore, ServerError>> {
+        let game = self.game.to_string();
+        let Some(replica) = self.read_pool.clone() else {
+            return stream_scores_db(self.pool.clone(), game);
+        };
+        let primary = self.pool.clone();
+
+        futures::stream::once(async move {
+            let mut rows = stream_scores_db(replica, game.clone()).peekable();
+            match Pin::new(&mut rows).peek().await {
+                Some(Err(e)) => {
+                    tracing::warn!("Read replica query failed: {}! Reading from primary", e);
+                    stream_scores_db(primary, game)
+                }
+                _ => rows.boxed(),
+            }
+        })
+        .flatten()
+        .boxed()
+    }
+
+    async fn add_new_score(
+        &self,
+        score: PlayerScore,
+        dedup: bool,
+        count_plays: bool,
+        client_version: Option<&str>,
+        floor: i32,
+        board_size: usize,
+    ) -> Result<Option<PlayerScore>, ServerError> {
+        add_new_score_db(
+            &self.pool,
+            self.game(),
+            score,
+            dedup,
+            count_plays,
+            client_version,
+            floor,
+            board_size,
+        )
+        .await
+    }
+
+    async fn flush(&self) -> Result<(), ServerError> {
+        flush_scores_db(&self.pool, self.game()).await
+    }
+
+    async fn delete_players(&self, names: &[String]) -> Result<HashMap<String, i64>, ServerError> {
+        delete_players_db(&self.pool, names).await
+    }
+
+    // Primary pool, so the count matches what flush would see
+    async fn count(&self) -> Result<i64, ServerError> {
+        count_scores_db(&self.pool, self.game()).await
+    }
+
+    async fn season_archived(&self, season: i32) -> Result<bool, ServerError> {
+        self.read(|pool| season_archived_db(pool, season)).await
+    }
+
+    async fn get_archived_scores(
+        &self,
+        season: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Option<Vec<PlayerScore>>, ServerError> {
+        if !self.season_archived(season).await? {
+            return Ok(None);
+        }
+        self.read(|pool| get_archived_scores_db(pool, season, limit, offset))
+            .await
+            .map(Some)
+    }
+
+    async fn ranked_scores(&self) -> Result<Vec<RankedPlayerScore>, ServerError> {
+        self.read(|pool| get_ranked_scores_db(pool, self.game()))
+            .await
+    }
+
+    async fn rank(&self, id: i32) -> Result<Option<i64>, ServerError> {
+        self.read(|pool| score_rank_db(pool, self.game(), id)).await
+    }
+
+    // Primary pool, so a score stored a moment ago is already seen
+    async fn player_best(&self, score: &PlayerScore) -> Result<Option<i32>, ServerError> {
+        player_best_db(&self.pool, self.game(), score).await
+    }
+
+    async fn best_by_name(&self, name: &PlayerName) -> Result<Option<PlayerScore>, ServerError> {
+        self.read(|pool| get_player_best_db(pool, self.game(), name))
+            .await
+    }
+
+    async fn cutoff(&self, floor: i32, board_size: usize) -> Result<i32, ServerError> {
+        board_cutoff_db(&self.pool, self.game(), floor, board_size).await
+    }
+}
+
+#[cfg(test)]
+type MockBoard = Arc<std::sync::Mutex<Vec<PlayerScore>>>;
+
+// In-memory store with the same board rules as Postgres one, used in handler tests.
+// Stores of all games made by for_game share the boards, archive and ids
+#[cfg(test)]
+pub struct MockScoreStore {
+    scores: MockBoard,
+    boards: Arc<std::sync::Mutex<HashMap<GameId, MockBoard>>>,
+    archive: Arc<std::sync::Mutex<std::collections::HashMap<i32, Vec<PlayerScore>>>>,
+    last_id: Arc<std::sync::atomic::AtomicI32>,
+}
+
+#[cfg(test)]
+impl Default for MockScoreStore {
+    fn default() -> Self {
+        let scores = MockBoard::default();
+        MockScoreStore {
+            boards: Arc::new(std::sync::Mutex::new(HashMap::from([(
+                GameId::default(),
+                scores.clone(),
+            )]))),
+            scores,
+            archive: Arc::default(),
+            last_id: Arc::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl MockScoreStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<PlayerScore>> {
+        self.scores.lock().expect("Mock store is poisoned!")
+    }
+
+    // Scores are expected in board order
+    pub fn archive_season(&self, season: i32, scores: Vec<PlayerScore>) {
+        self.archive
+            .lock()
+            .expect("Mock store is poisoned!")
+            .insert(season, scores);
+    }
+
+    async fn is_worthy(
+        &self,
+        score: &PlayerScore,
+        floor: i32,
+        board_size: usize,
+    ) -> Result<bool, ServerError> {
+        Ok(score.player_score >= self.cutoff(floor, board_size).await?)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl ScoreStore for MockScoreStore {
+    fn for_game(&self, game: &GameId) -> Arc<dyn ScoreStore> {
+        let scores = self
+            .boards
+            .lock()
+            .expect("Mock store is poisoned!")
+            .entry(game.clone())
+            .or_default()
+            .clone();
+        Arc::new(MockScoreStore {
+            scores,
+            boards: self.boards.clone(),
+            archive: self.archive.clone(),
+            last_id: self.last_id.clone(),
+        })
+    }
+
+    async fn get_scores(&self) -> Result<Vec<PlayerScore>, ServerError> {
+        Ok(self.lock().clone())
+    }
+
+    async fn get_scores_page(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PlayerScore>, ServerError> {
+        Ok(self
+            .lock()
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    // Mock scores carry no posted time, they all count as posted just now
+    async fn get_scores_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<PlayerScore>, ServerError> {
+        match since <= Utc::now() {
+            true => Ok(self.lock().clone()),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    fn stream_scores(&self) -> BoxStream<'static, Result<PlayerScore, ServerError>> {
+        use futures::StreamExt;
+        futures::stream::iter(self.lock().clone().into_iter().map(Ok)).boxed()
+    }
+
+    async fn add_new_score(
+        &self,
+        score: PlayerScore,
+        dedup: bool,
+        count_plays: bool,
+        _client_version: Option<&str>,
+        floor: i32,
+        board_size: usize,
+    ) -> Result<Option<PlayerScore>, ServerError> {
+        let is_lower =
+            |s: &PlayerScore| s.same_player(&score) && s.player_score >= score.player_score;
+        if dedup && count_plays {
+            let mut scores = self.lock();
+            if let Some(best) = scores.iter_mut().find(|s| is_lower(s)) {
+                best.play_count = Some(best.play_count.unwrap_or(1) + 1);
+                return Ok(None);
+            }
+        }
+
+        if !self.is_worthy(&score, floor, board_size).await? {
+            return Ok(None);
+        }
+
+        let mut scores = self.lock();
+        let mut previous_plays = 0;
+        if dedup {
+            if scores.iter().any(is_lower) {
+                return Ok(None);
+            }
+            previous_plays = scores
+                .iter()
+                .filter(|s| s.same_player(&score))
+                .map(|s| s.play_count.unwrap_or(1))
+                .max()
+                .unwrap_or(0);
+            scores.retain(|s| !s.same_player(&score));
+        }
+        let score = PlayerScore {
+            id: Some(
+                self.last_id
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                    + 1,
+            ),
+            play_count: count_plays.then_some(previous_plays + 1),
+            ..score
+        };
+        // Stable sort keeps older equal scores above newer ones
+        scores.push(score.clone());
+        scores.sort_by_key(|s| std::cmp::Reverse(s.player_score));
+        scores.truncate(board_size);
+        Ok(Some(score))
+    }
+
+    async fn flush(&self) -> Result<(), ServerError> {
+        self.lock().clear();
+        Ok(())
+    }
+
+    async fn delete_players(&self, names: &[String]) -> Result<HashMap<String, i64>, ServerError> {
+        let mut removed = HashMap::new();
+        for board in self
+            .boards
+            .lock()
+            .expect("Mock store is poisoned!")
+            .values()
+        {
+            board.lock().expect("Mock store is poisoned!").retain(|s| {
+                let matched = names.iter().any(|name| name == s.player_name.as_str());
+                if matched {
+                    *removed.entry(s.player_name.to_string()).or_insert(0) += 1;
+                }
+                !matched
+            });
+        }
+        Ok(removed)
+    }
+
+    async fn count(&self) -> Result<i64, ServerError> {
+        Ok(self.lock().len() as i64)
+    }
+
+    async fn season_archived(&self, season: i32) -> Result<bool, ServerError> {
+        Ok(self
+            .archive
+            .lock()
+            .expect("Mock store is poisoned!")
+            .contains_key(&season))
+    }
+
+    async fn get_archived_scores(
+        &self,
+        season: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Option<Vec<PlayerScore>>, ServerError> {
+        let archive = self.archive.lock().expect("Mock store is poisoned!");
+        Ok(archive.get(&season).map(|scores| {
+            scores
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .cloned()
+                .collect()
+        }))
+    }
+
+    async fn ranked_scores(&self) -> Result<Vec<RankedPlayerScore>, ServerError> {
+        let scores = self.lock();
+        Ok(scores
+            .iter()
+            .map(|score| RankedPlayerScore {
+                rank: 1 + scores
+                    .iter()
+                    .filter(|s| s.player_score > score.player_score)
+                    .count() as i64,
+                player_name: score.player_name.clone(),
+                player_score: score.player_score,
+            })
+            .collect())
+    }
+
+    async fn rank(&self, id: i32) -> Result<Option<i64>, ServerError> {
+        let scores = self.lock();
+        Ok(scores.iter().find(|s| s.id == Some(id)).map(|score| {
+            1 + scores
+                .iter()
+                .filter(|s| s.player_score > score.player_score)
+                .count() as i64
+        }))
+    }
+
+    async fn player_best(&self, score: &PlayerScore) -> Result<Option<i32>, ServerError> {
+        Ok(self
+            .lock()
+            .iter()
+            .filter(|s| s.same_player(score))
+            .map(|s| s.player_score)
+            .max())
+    }
+
+    async fn best_by_name(&self, name: &PlayerName) -> Result<Option<PlayerScore>, ServerError> {
+        Ok(self
+            .lock()
+            .iter()
+            .filter(|s| s.player_name == *name)
+            .reduce(|best, s| match s.player_score > best.player_score {
+                true => s,
+                false => best,
+            })
+            .cloned())
+    }
+
+    async fn cutoff(&self, floor: i32, board_size: usize) -> Result<i32, ServerError> {
+        let scores = self.lock();
+        if scores.len() < board_size {
+            return Ok(floor);
+        }
+        Ok(scores
+            .iter()
+            .take(board_size)
+            .map(|s| s.player_score)
+            .min()
+            .map_or(floor, |min| min.max(floor)))
+    }
+}
+
+#[cfg(test)]
+mod store_tests {
+    use super::*;
+    use crate::{
+        config::{DEFAULT_LEADERBOARD_SIZE, DEFAULT_SCORE_FLOOR},
+        connect_to_db,
+    };
+
+    fn test_score(player_score: i32) -> PlayerScore {
+        PlayerScore {
+            id: None,
+            player_name: "Mock".parse().unwrap(),
+            player_score,
+            player_id: None,
+            avatar: None,
+            tier: None,
+            difficulty: None,
+            play_count: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pg_store_read_pool_fallback() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let store = PgScoreStore::new(pool, None);
+
+        assert!(
+            std::ptr::eq(store.read_pool(), &store.pool),
+            "Reads are not using primary pool without replica!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pg_store_read_pool_configured() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let read_pool = connect_to_db().await.expect("Can't get read pool");
+        let store = PgScoreStore::new(pool, Some(read_pool));
+
+        assert!(
+            std::ptr::eq(
+                store.read_pool(),
+                store.read_pool.as_ref().expect("Read pool is not set")
+            ),
+            "Reads are not using read pool!"
+        );
+        assert!(!std::ptr::eq(store.read_pool(), &store.pool));
+        assert!(store.get_scores().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pg_store_replica_down() {
+        let pool = connect_to_db().await.expect("Can't get pool");
+        let replica = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(500))
+            .connect_lazy("postgres://nobody@127.0.0.1:1/nothing")
+            .expect("Can't set up replica pool");
+        let store = PgScoreStore::new(pool, Some(replica));
+
+        assert!(
+            store.get_scores().await.is_ok(),
+            "Board read fails with replica down!"
+        );
+        assert!(store.ranked_scores().await.is_ok());
+        let streamed: Vec<_> = store.stream_scores().collect().await;
+        assert!(
+            streamed.iter().all(Result::is_ok),
+            "Stream fails with replica down!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_store_board_rules() {
+        let store = MockScoreStore::default();
+
+        assert!(
+            !store
+                .is_worthy(
+                    &test_score(0),
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE
+                )
+                .await
+                .unwrap()
+        );
+        for i in 1..=12 {
+            store
+                .add_new_score(
+                    test_score(i),
+                    false,
+                    false,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE,
+                )
+                .await
+                .unwrap();
+        }
+
+        let scores = store.get_scores().await.unwrap();
+        assert_eq!(scores.len(), 10, "Board is not trimmed!");
+        assert_eq!(
+            scores.first().cloned().map(PlayerScore::without_id),
+            Some(test_score(12))
+        );
+        assert_eq!(
+            scores.last().cloned().map(PlayerScore::without_id),
+            Some(test_score(3))
+        );
+        assert_eq!(
+            store
+                .add_new_score(
+                    test_score(2),
+                    false,
+                    false,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE
+                )
+                .await
+                .unwrap(),
+            None
+        );
+
+        store.flush().await.unwrap();
+        assert!(store.get_scores().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mock_store_counts_plays() {
+        let store = MockScoreStore::default();
+
+        for player_score in [50, 30, 60, 0] {
+            store
+                .add_new_score(
+                    test_score(player_score),
+                    true,
+                    true,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE,
+                )
+                .await
+                .unwrap();
+        }
+
+        let scores = store.get_scores().await.unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].player_score, 60);
+        assert_eq!(scores[0].play_count, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_mock_store_dedup_by_player_id() {
+        let store = MockScoreStore::default();
+        let with_id = |player_score: i32, id: &str| PlayerScore {
+            player_id: Some(id.to_string()),
+            ..test_score(player_score)
+        };
+
+        store
+            .add_new_score(
+                with_id(3, "id-2"),
+                true,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .unwrap();
+        store
+            .add_new_score(
+                test_score(4),
+                true,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .unwrap();
+        store
+            .add_new_score(
+                with_id(5, "id-1"),
+                true,
+                false,
+                None,
+                DEFAULT_SCORE_FLOOR,
+                DEFAULT_LEADERBOARD_SIZE,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store
+                .add_new_score(
+                    with_id(4, "id-1"),
+                    true,
+                    false,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE
+                )
+                .await
+                .unwrap(),
+            None,
+            "Lower score of the same player is stored!"
+        );
+        assert!(
+            store
+                .add_new_score(
+                    with_id(8, "id-1"),
+                    true,
+                    false,
+                    None,
+                    DEFAULT_SCORE_FLOOR,
+                    DEFAULT_LEADERBOARD_SIZE
+                )
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let scores: Vec<PlayerScore> = store
+            .get_scores()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(PlayerScore::without_id)
+            .collect();
+        assert_eq!(
+            scores,
+            vec![with_id(8, "id-1"), test_score(4), with_id(3, "id-2")]
+        );
+    }
+}
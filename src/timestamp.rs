@@ -0,0 +1,95 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serializer};
+
+// Every datetime leaves the API as RFC 3339 string in UTC, e.g. "2026-10-15T12:00:00Z".
+// Fraction is written only when there is one. Used as #[serde(with = "rfc3339")] on fields
+pub mod rfc3339 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        time: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_rfc3339(time))
+    }
+}
+
+pub mod rfc3339_option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_some(&format_rfc3339(time)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    // Any offset is accepted on input, it is converted to UTC. Postgres text without offset,
+    // e.g. "2026-10-15 12:30:00.123" of legacy backups, is read as UTC
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|raw| {
+                DateTime::parse_from_rfc3339(&raw)
+                    .map(|time| time.with_timezone(&Utc))
+                    .or_else(|_| {
+                        NaiveDateTime::parse_from_str(&raw, "%Y-%m-%d %H:%M:%S%.f")
+                            .map(|time| time.and_utc())
+                    })
+                    .map_err(serde::de::Error::custom)
+            })
+            .transpose()
+    }
+}
+
+fn format_rfc3339(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[cfg(test)]
+mod timestamp_tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Sample {
+        #[serde(with = "rfc3339")]
+        created_at: DateTime<Utc>,
+        #[serde(with = "rfc3339_option")]
+        posted_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Deserialize)]
+    struct Posted {
+        #[serde(default, with = "rfc3339_option")]
+        posted_at: Option<DateTime<Utc>>,
+    }
+
+    #[tokio::test]
+    async fn test_rfc3339_round_trip() {
+        let time = Utc.with_ymd_and_hms(2026, 10, 15, 12, 30, 0).unwrap();
+        let json = serde_json::to_value(Sample {
+            created_at: time,
+            posted_at: None,
+        })
+        .expect("Can't serialize sample");
+
+        assert_eq!(json["created_at"], "2026-10-15T12:30:00Z");
+        assert!(json["posted_at"].is_null());
+        assert!(DateTime::parse_from_rfc3339(json["created_at"].as_str().unwrap()).is_ok());
+
+        let parsed: Posted = serde_json::from_str(r#"{"posted_at": "2026-10-15T14:30:00+02:00"}"#)
+            .expect("Can't deserialize sample");
+        assert_eq!(
+            parsed.posted_at,
+            Some(time),
+            "Offset is not converted to UTC!"
+        );
+        assert!(serde_json::from_str::<Posted>(r#"{"posted_at": 1792067400}"#).is_err());
+    }
+}